@@ -9,6 +9,7 @@ CREATE TABLE users (
     id CHAR(36) PRIMARY KEY,
     username VARCHAR(50) NOT NULL UNIQUE,
     password_hash TEXT NOT NULL,
+    subsonic_password VARCHAR(64), -- plain, Subsonic token auth needs it; generated, never the login password
     avatar_url TEXT,
     is_admin BOOLEAN NOT NULL DEFAULT FALSE,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
@@ -50,6 +51,9 @@ CREATE TABLE albums (
     release_year INT,
     cover_url TEXT,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    -- ReplayGain: dB to apply to reach the loudness target, and linear sample peak
+    album_gain FLOAT NULL,
+    album_peak FLOAT NULL,
     FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE
 );
 
@@ -70,8 +74,13 @@ CREATE TABLE songs (
     artist_id CHAR(36) NOT NULL,
     album_id CHAR(36) NULL,
     genre_id INT,
+    track_number INT NULL,
     duration_seconds INT NOT NULL,
     sftp_path VARCHAR(255) NOT NULL DEFAULT '',
+    -- Integrated loudness (EBU R128) and ReplayGain values measured at ingestion
+    loudness_lufs FLOAT NULL,
+    track_gain FLOAT NULL,
+    track_peak FLOAT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
     FOREIGN KEY (artist_id) REFERENCES artists(id) ON DELETE CASCADE,
@@ -96,6 +105,8 @@ CREATE TABLE playlists (
     is_public BOOLEAN DEFAULT FALSE,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    kind VARCHAR(10) NOT NULL DEFAULT 'regular', -- 'regular', or 'smart': songs listed from rules on read
+    rules TEXT NULL, -- JSON rules of a smart playlist
     FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
 );
 
@@ -109,9 +120,35 @@ CREATE TABLE playlist_songs (
     song_id CHAR(36) NOT NULL,
     position INT NOT NULL,
     added_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    added_by CHAR(36) NULL, -- owner or editor who added the song
     PRIMARY KEY (playlist_id, song_id),
+    UNIQUE KEY uq_playlist_songs_position (playlist_id, position), -- 0, 1, 2... kept by the server
     FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE,
-    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    FOREIGN KEY (added_by) REFERENCES users(id) ON DELETE SET NULL
+);
+
+-- Users a playlist is shared with: editors change its songs, viewers only see it
+CREATE TABLE playlist_members (
+    playlist_id CHAR(36) NOT NULL,
+    user_id CHAR(36) NOT NULL,
+    role VARCHAR(10) NOT NULL, -- 'editor' or 'viewer'
+    added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (playlist_id, user_id),
+    FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    INDEX idx_playlist_members_user (user_id)
+);
+
+-- Public playlists of other users kept in a library
+CREATE TABLE playlist_follows (
+    playlist_id CHAR(36) NOT NULL,
+    user_id CHAR(36) NOT NULL,
+    followed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (playlist_id, user_id),
+    FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    INDEX idx_playlist_follows_user (user_id)
 );
 
 -- -----------------------
@@ -125,3 +162,116 @@ CREATE TABLE favorites (
     FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
     FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
 );
+
+-- -----------------------
+-- INGEST_JOBS
+-- -----------------------
+CREATE TABLE ingest_jobs (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'queued',
+    total_items INT NOT NULL,
+    processed_items INT NOT NULL DEFAULT 0,
+    items_json TEXT NOT NULL,
+    report_json TEXT,
+    song_ids_json TEXT,
+    error_message TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP NULL,
+    finished_at TIMESTAMP NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_ingest_jobs_status ON ingest_jobs(status, created_at);
+
+-- -----------------------
+-- SONG_RENDITIONS
+-- -----------------------
+CREATE TABLE song_renditions (
+    id CHAR(36) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    codec VARCHAR(20) NOT NULL,
+    bitrate_kbps INT NULL,
+    size_bytes BIGINT NOT NULL,
+    object_key VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    UNIQUE (song_id, format, bitrate_kbps)
+);
+
+-- On-the-fly transcodes kept in storage, so they can be removed with their song
+CREATE TABLE transcode_cache (
+    object_key VARCHAR(255) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
+
+-- -----------------------
+-- PLAY HISTORY
+-- -----------------------
+CREATE TABLE plays (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    played_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    seconds_listened INT NULL,
+    client VARCHAR(100) NULL,
+    source VARCHAR(10) NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    INDEX idx_plays_user_played (user_id, played_at)
+);
+
+CREATE TABLE now_playing (
+    user_id CHAR(36) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    client VARCHAR(100) NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
+
+-- -----------------------
+-- RADIO
+-- -----------------------
+CREATE TABLE radio_stations (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    seed_type VARCHAR(10) NOT NULL,
+    seed_id VARCHAR(36) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Tracks handed out by a station, so continuation tokens can be replayed
+CREATE TABLE radio_tracks (
+    station_id CHAR(36) NOT NULL,
+    position INT NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    PRIMARY KEY (station_id, position),
+    FOREIGN KEY (station_id) REFERENCES radio_stations(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
+
+CREATE TABLE skips (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    station_id CHAR(36) NULL,
+    skipped_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    FOREIGN KEY (station_id) REFERENCES radio_stations(id) ON DELETE SET NULL,
+    INDEX idx_skips_user_skipped (user_id, skipped_at)
+);
+
+-- -----------------------
+-- FULL-TEXT SEARCH
+-- -----------------------
+-- Matching follows the column collation, which ignores case and accents with MySQL 8's default (utf8mb4_0900_ai_ci)
+CREATE FULLTEXT INDEX ft_songs_title ON songs(title);
+CREATE FULLTEXT INDEX ft_albums_name ON albums(name);
+CREATE FULLTEXT INDEX ft_artists_name ON artists(name);
+CREATE FULLTEXT INDEX ft_playlists_name_description ON playlists(name, description);
@@ -0,0 +1,17 @@
+CREATE TABLE ingest_jobs (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'queued',
+    total_items INT NOT NULL,
+    processed_items INT NOT NULL DEFAULT 0,
+    items_json TEXT NOT NULL,
+    report_json TEXT,
+    song_ids_json TEXT,
+    error_message TEXT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP NULL,
+    finished_at TIMESTAMP NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_ingest_jobs_status ON ingest_jobs(status, created_at);
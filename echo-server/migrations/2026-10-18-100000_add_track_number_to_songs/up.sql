@@ -0,0 +1 @@
+ALTER TABLE songs ADD COLUMN track_number INT NULL AFTER genre_id;
@@ -0,0 +1,8 @@
+ALTER TABLE albums
+    DROP COLUMN album_peak,
+    DROP COLUMN album_gain;
+
+ALTER TABLE songs
+    DROP COLUMN track_peak,
+    DROP COLUMN track_gain,
+    DROP COLUMN loudness_lufs;
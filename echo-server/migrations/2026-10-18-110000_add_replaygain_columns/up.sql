@@ -0,0 +1,8 @@
+ALTER TABLE songs
+    ADD COLUMN loudness_lufs FLOAT NULL AFTER object_url,
+    ADD COLUMN track_gain FLOAT NULL AFTER loudness_lufs,
+    ADD COLUMN track_peak FLOAT NULL AFTER track_gain;
+
+ALTER TABLE albums
+    ADD COLUMN album_gain FLOAT NULL,
+    ADD COLUMN album_peak FLOAT NULL;
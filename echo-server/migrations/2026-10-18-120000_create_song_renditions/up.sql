@@ -0,0 +1,12 @@
+CREATE TABLE song_renditions (
+    id CHAR(36) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    format VARCHAR(10) NOT NULL,
+    codec VARCHAR(20) NOT NULL,
+    bitrate_kbps INT NULL,
+    size_bytes BIGINT NOT NULL,
+    object_key VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    UNIQUE (song_id, format, bitrate_kbps)
+);
@@ -0,0 +1,2 @@
+-- Separate API password for Subsonic clients, whose token auth needs the plain secret
+ALTER TABLE users ADD COLUMN subsonic_password VARCHAR(64) NULL AFTER password_hash;
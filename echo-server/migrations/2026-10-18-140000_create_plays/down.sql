@@ -0,0 +1,2 @@
+DROP TABLE now_playing;
+DROP TABLE plays;
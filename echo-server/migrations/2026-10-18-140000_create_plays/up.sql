@@ -0,0 +1,21 @@
+CREATE TABLE plays (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    played_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    seconds_listened INT NULL,
+    client VARCHAR(100) NULL,
+    source VARCHAR(10) NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    INDEX idx_plays_user_played (user_id, played_at)
+);
+
+CREATE TABLE now_playing (
+    user_id CHAR(36) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    client VARCHAR(100) NULL,
+    started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
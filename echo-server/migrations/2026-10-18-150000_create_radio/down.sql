@@ -0,0 +1,3 @@
+DROP TABLE skips;
+DROP TABLE radio_tracks;
+DROP TABLE radio_stations;
@@ -0,0 +1,30 @@
+CREATE TABLE radio_stations (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    seed_type VARCHAR(10) NOT NULL,
+    seed_id VARCHAR(36) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Tracks handed out by a station, so continuation tokens can be replayed
+CREATE TABLE radio_tracks (
+    station_id CHAR(36) NOT NULL,
+    position INT NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    PRIMARY KEY (station_id, position),
+    FOREIGN KEY (station_id) REFERENCES radio_stations(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
+
+CREATE TABLE skips (
+    id CHAR(36) PRIMARY KEY,
+    user_id CHAR(36) NOT NULL,
+    song_id CHAR(36) NOT NULL,
+    station_id CHAR(36) NULL,
+    skipped_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE,
+    FOREIGN KEY (station_id) REFERENCES radio_stations(id) ON DELETE SET NULL,
+    INDEX idx_skips_user_skipped (user_id, skipped_at)
+);
@@ -0,0 +1,4 @@
+DROP INDEX ft_playlists_name_description ON playlists;
+DROP INDEX ft_artists_name ON artists;
+DROP INDEX ft_albums_name ON albums;
+DROP INDEX ft_songs_title ON songs;
@@ -0,0 +1,6 @@
+-- Full-text indexes for /api/search, kept up to date by InnoDB on every write.
+-- Matching follows the column collation, which ignores case and accents with MySQL 8's default (utf8mb4_0900_ai_ci).
+CREATE FULLTEXT INDEX ft_songs_title ON songs(title);
+CREATE FULLTEXT INDEX ft_albums_name ON albums(name);
+CREATE FULLTEXT INDEX ft_artists_name ON artists(name);
+CREATE FULLTEXT INDEX ft_playlists_name_description ON playlists(name, description);
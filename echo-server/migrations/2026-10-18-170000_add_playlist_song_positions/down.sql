@@ -0,0 +1 @@
+ALTER TABLE playlist_songs DROP INDEX uq_playlist_songs_position;
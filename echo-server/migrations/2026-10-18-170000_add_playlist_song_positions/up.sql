@@ -0,0 +1,11 @@
+-- Positions of a playlist run from 0 to the number of songs minus one, managed by the server.
+-- Renumber existing playlists (ties and gaps came from client-chosen positions) before enforcing it.
+UPDATE playlist_songs ps
+JOIN (
+    SELECT playlist_id, song_id,
+           ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY position, added_at, song_id) - 1 AS new_position
+    FROM playlist_songs
+) ordered ON ordered.playlist_id = ps.playlist_id AND ordered.song_id = ps.song_id
+SET ps.position = ordered.new_position;
+
+ALTER TABLE playlist_songs ADD UNIQUE KEY uq_playlist_songs_position (playlist_id, position);
@@ -0,0 +1,3 @@
+ALTER TABLE playlist_songs DROP FOREIGN KEY fk_playlist_songs_added_by;
+ALTER TABLE playlist_songs DROP COLUMN added_by;
+DROP TABLE playlist_members;
@@ -0,0 +1,15 @@
+-- Users a playlist is shared with: editors change its songs, viewers only see it
+CREATE TABLE playlist_members (
+    playlist_id CHAR(36) NOT NULL,
+    user_id CHAR(36) NOT NULL,
+    role VARCHAR(10) NOT NULL,
+    added_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (playlist_id, user_id),
+    FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    INDEX idx_playlist_members_user (user_id)
+);
+
+-- Who added each song; songs added before this are left unknown
+ALTER TABLE playlist_songs ADD COLUMN added_by CHAR(36) NULL;
+ALTER TABLE playlist_songs ADD CONSTRAINT fk_playlist_songs_added_by FOREIGN KEY (added_by) REFERENCES users(id) ON DELETE SET NULL;
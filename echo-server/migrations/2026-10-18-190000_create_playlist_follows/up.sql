@@ -0,0 +1,10 @@
+-- Public playlists of other users kept in a library
+CREATE TABLE playlist_follows (
+    playlist_id CHAR(36) NOT NULL,
+    user_id CHAR(36) NOT NULL,
+    followed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (playlist_id, user_id),
+    FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    INDEX idx_playlist_follows_user (user_id)
+);
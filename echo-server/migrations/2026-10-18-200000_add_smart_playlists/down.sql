@@ -0,0 +1,3 @@
+ALTER TABLE playlists
+    DROP COLUMN rules,
+    DROP COLUMN kind;
@@ -0,0 +1,4 @@
+-- Smart playlists hold no songs: they are listed from their saved rules on read
+ALTER TABLE playlists
+    ADD COLUMN kind VARCHAR(10) NOT NULL DEFAULT 'regular',
+    ADD COLUMN rules TEXT NULL;
@@ -0,0 +1 @@
+DROP TABLE transcode_cache;
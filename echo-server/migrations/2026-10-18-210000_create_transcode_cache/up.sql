@@ -0,0 +1,6 @@
+CREATE TABLE transcode_cache (
+    object_key VARCHAR(255) PRIMARY KEY,
+    song_id CHAR(36) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (song_id) REFERENCES songs(id) ON DELETE CASCADE
+);
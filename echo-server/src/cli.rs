@@ -0,0 +1,56 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::db::DbPool;
+use crate::storage::ObjectStorage;
+use crate::utils::backup_utils::{restore_archive, write_backup};
+
+const USAGE: &str = "Usage:
+  echo backup <archive.tar> [--credentials]   Write the library to a backup archive
+  echo restore <archive.tar> [--remap-ids]    Restore a backup archive";
+
+fn usage_error() -> Error {
+    Error::new(ErrorKind::InvalidInput, USAGE)
+}
+
+/// Run the subcommand in `args` (without the program name) instead of the server
+pub async fn run(args: &[String], pool: &DbPool, storage: &dyn ObjectStorage) -> std::io::Result<()> {
+    let (command, archive, flags) = match args {
+        [command, archive, flags @ ..] => (command.as_str(), Path::new(archive), flags),
+        _ => return Err(usage_error()),
+    };
+    let flag = |name: &str| flags.iter().any(|f| f == name);
+
+    match command {
+        "backup" => {
+            if flags.iter().any(|f| f != "--credentials") {
+                return Err(usage_error());
+            }
+            let manifest = match write_backup(pool, storage, flag("--credentials"), archive).await {
+                Ok(m) => m,
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(archive).await;
+                    return Err(Error::other(e));
+                }
+            };
+            let rows: usize = manifest.tables.iter().map(|t| t.rows).sum();
+            println!(
+                "Backup written to {}: {} rows, {} audio objects",
+                archive.display(),
+                rows,
+                manifest.objects.len()
+            );
+        }
+        "restore" => {
+            if flags.iter().any(|f| f != "--remap-ids") {
+                return Err(usage_error());
+            }
+            let report = restore_archive(pool, storage, archive, flag("--remap-ids"))
+                .await
+                .map_err(Error::other)?;
+            println!("Restored {}: {:?}", archive.display(), report);
+        }
+        _ => return Err(usage_error()),
+    }
+    Ok(())
+}
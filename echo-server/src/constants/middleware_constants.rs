@@ -16,5 +16,16 @@ pub static ADMIN_ONLY_ROUTES: Lazy<HashSet<(&'static str, Method)>> = Lazy::new(
     set.insert(("/api/albums/{album_id}", Method::PUT));
     set.insert(("/api/albums/{album_id}", Method::DELETE));
 
+    set.insert(("/api/artists", Method::POST));
+    set.insert(("/api/artists/{artist_id}", Method::PUT));
+    set.insert(("/api/artists/{artist_id}", Method::DELETE));
+
+    set.insert(("/api/genres", Method::POST));
+    set.insert(("/api/genres/{genre_id}", Method::PUT));
+    set.insert(("/api/genres/{genre_id}", Method::DELETE));
+
+    set.insert(("/api/backup", Method::GET));
+    set.insert(("/api/backup/restore", Method::POST));
+
     set
 });
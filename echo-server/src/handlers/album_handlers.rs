@@ -1,17 +1,26 @@
+use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse, Responder, ResponseError};
 use diesel::result::DatabaseErrorKind;
 use diesel::QueryDsl;
 use diesel::prelude::*;
 use uuid::Uuid;
-use diesel::sql_types::Text;
 
 use crate::models::album_models::NewAlbum;
 use crate::models::album_models::UpdateAlbum;
 use crate::models::pagination_models::Pagination;
-use crate::models::song_models::SongResponse;
+use crate::models::song_models::SongQuery;
+use crate::models::token_models::Claims;
+use crate::utils::auth_utils::is_admin;
+use crate::utils::song_query_utils::{contains_pattern, song_listing, SongSource};
+use crate::utils::pagination_utils::{load_page, KeysetQuery, SortKey};
+use crate::utils::sql_utils::BindValue;
 use crate::{db::{get_conn, DbPool}, models::{album_models::{Album, AlbumQuery}}, utils::pagination_utils::validate_pagination};
 use crate::schema::albums::dsl::*;
 
+/// Columns of `al` for `Album`
+pub const ALBUM_COLUMNS: &str =
+    "al.id, al.name, al.artist_id, al.release_year, al.cover_url, al.created_at, al.album_gain, al.album_peak";
+
 pub async fn list_albums(
     pool: web::Data<DbPool>,
     query: web::Query<AlbumQuery>,
@@ -24,26 +33,28 @@ pub async fn list_albums(
     let term = query.q.clone().unwrap_or_default().to_lowercase();
     let pagination = &query.pagination;
 
-    let (limit, offset) = match validate_pagination(pagination) {
-        Ok(v) => v,
+    let page = match validate_pagination(pagination) {
+        Ok(p) => p,
         Err(e) => return e.error_response(),
     };
 
-    let mut query_builder = albums.into_boxed();
+    let mut keyset = KeysetQuery {
+        select: ALBUM_COLUMNS.to_string(),
+        from: "FROM albums al".to_string(),
+        conditions: Vec::new(),
+        binds: Vec::new(),
+        keys: vec![SortKey::asc("al.name"), SortKey::asc("al.id")],
+        shuffle: false,
+    };
 
     if !term.is_empty() {
-        query_builder = query_builder.filter(name.like(format!("%{}%", term)));
+        keyset.conditions.push("al.name LIKE ?".to_string());
+        keyset.binds.push(BindValue::Text(contains_pattern(term)));
     }
 
-    let result = query_builder
-        .select(Album::as_select())
-        .limit(limit)
-        .offset(offset)
-        .load::<Album>(&mut conn);
-
-    match result {
-        Ok(list) => HttpResponse::Ok().json(list),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    match load_page::<Album>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
     }
 }
 
@@ -195,7 +206,8 @@ pub async fn delete_album(
 pub async fn get_album_songs(
     pool: web::Data<DbPool>,
     album_id_param: web::Path<String>,
-    query: web::Query<Pagination>,
+    query: web::Query<SongQuery>,
+    claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
@@ -204,45 +216,30 @@ pub async fn get_album_songs(
 
     let album_id = album_id_param.into_inner();
 
-    let pagination = query.into_inner();
-    match validate_pagination(&pagination) {
-        Ok(v) => v,
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
         Err(e) => return e.error_response(),
     };
 
-    let sql = format!(r#"
-        SELECT 
-            s.id,
-            s.title,
-            s.artist_id,
-            a.name AS artist_name,
-            s.album_id,
-            al.name AS album_name,
-            s.genre_id,
-            g.name AS genre_name,
-            s.duration_seconds,
-            s.object_url,
-            s.created_at,
-            s.updated_at
-        FROM songs s
-        JOIN artists a ON s.artist_id = a.id
-        LEFT JOIN albums al ON s.album_id = al.id
-        LEFT JOIN genres g ON s.genre_id = g.id
-        WHERE s.album_id = $1
-        {}
-    "#, pagination.sql_clause().unwrap());
-
-    match diesel::sql_query(sql)
-        .bind::<Text, _>(album_id)
-        .load::<SongResponse>(&mut conn)
-    {
-        Ok(list) => {
-            if list.is_empty() {
+    let listing = match song_listing(SongSource::Album(album_id), &query) {
+        Ok(l) => l,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    match listing.load_page(&mut conn, &page) {
+        Ok(page) => {
+            if page.total == 0 {
                 HttpResponse::NotFound().body("No songs found for this album")
             } else {
-                HttpResponse::Ok().json(list)
+                let admin = is_admin(&mut conn, &claims.sub);
+                HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
             }
         }
-        Err(_) => HttpResponse::InternalServerError().finish(),
+        Err(e) => e.error_response(),
     }
 }
\ No newline at end of file
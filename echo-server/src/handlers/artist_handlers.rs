@@ -0,0 +1,274 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use uuid::Uuid;
+
+use crate::db::{get_conn, DbPool};
+use crate::handlers::album_handlers::ALBUM_COLUMNS;
+use crate::models::album_models::Album;
+use crate::models::artist_models::{Artist, ArtistQuery, NewArtist, UpdateArtist};
+use crate::models::pagination_models::Pagination;
+use crate::models::token_models::Claims;
+use crate::schema::{artists::dsl as artists_dsl, songs::dsl as songs_dsl};
+use crate::utils::auth_utils::is_admin;
+use crate::utils::pagination_utils::{load_page, validate_pagination, KeysetQuery, SortKey};
+use crate::utils::song_query_utils::{contains_pattern, SongListing, SongSource};
+use crate::utils::sql_utils::BindValue;
+
+pub async fn list_artists(
+    pool: web::Data<DbPool>,
+    query: web::Query<ArtistQuery>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    let mut keyset = KeysetQuery {
+        select: "a.id, a.name, a.bio, a.image_url, a.created_at".to_string(),
+        from: "FROM artists a".to_string(),
+        conditions: Vec::new(),
+        binds: Vec::new(),
+        keys: vec![SortKey::asc("a.name"), SortKey::asc("a.id")],
+        shuffle: false,
+    };
+
+    // Optional search by name (artists.name uses a case-insensitive collation)
+    if let Some(ref term) = query.q {
+        let term = term.trim();
+        if !term.is_empty() {
+            keyset.conditions.push("a.name LIKE ?".to_string());
+            keyset.binds.push(BindValue::Text(contains_pattern(term)));
+        }
+    }
+
+    match load_page::<Artist>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn create_artist(
+    pool: web::Data<DbPool>,
+    payload: web::Json<NewArtist>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    if payload.name.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Artist name cannot be empty");
+    }
+
+    let new_id = Uuid::new_v4().to_string();
+
+    let new_artist = NewArtist {
+        id: new_id.clone(),
+        name: payload.name.trim().to_string(),
+        bio: payload.bio.clone(),
+        image_url: payload.image_url.clone(),
+    };
+
+    match diesel::insert_into(artists_dsl::artists)
+        .values(&new_artist)
+        .execute(&mut conn)
+    {
+        Ok(_) => {
+            // MySQL doesn't support RETURNING; fetch the inserted row by the id we generated.
+            match artists_dsl::artists.find(&new_id).select(Artist::as_select()).first::<Artist>(&mut conn) {
+                Ok(artist) => HttpResponse::Created().json(artist),
+                Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+            }
+        }
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpResponse::Conflict().body("An artist with this name already exists")
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Unexpected error: {}", e)),
+    }
+}
+
+pub async fn get_artist(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let artist_id = path.into_inner();
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let result = artists_dsl::artists
+        .find(&artist_id)
+        .select(Artist::as_select())
+        .first::<Artist>(&mut conn);
+
+    match result {
+        Ok(artist) => HttpResponse::Ok().json(artist),
+        Err(DieselError::NotFound) => HttpResponse::NotFound().body("Artist not found"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn update_artist(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    payload: web::Json<UpdateArtist>,
+) -> impl Responder {
+    let artist_id = path.into_inner();
+    let update_data = payload.into_inner();
+
+    if update_data.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+        return HttpResponse::BadRequest().body("Artist name cannot be empty");
+    }
+    if update_data.name.is_none() && update_data.bio.is_none() && update_data.image_url.is_none() {
+        return HttpResponse::BadRequest().body("Nothing to update");
+    }
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let exec_result = diesel::update(artists_dsl::artists.filter(artists_dsl::id.eq(&artist_id)))
+        .set(&update_data)
+        .execute(&mut conn);
+
+    match exec_result {
+        Ok(_) => {
+            // MySQL reports 0 affected rows when values are unchanged, so check existence by reading back
+            match artists_dsl::artists.find(&artist_id).select(Artist::as_select()).first::<Artist>(&mut conn) {
+                Ok(updated_artist) => HttpResponse::Ok().json(updated_artist),
+                Err(DieselError::NotFound) => HttpResponse::NotFound().body("Artist not found"),
+                Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+            }
+        }
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpResponse::Conflict().body("An artist with this name already exists")
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn delete_artist(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let artist_id = path.into_inner();
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    // Songs cascade with their artist at DB level, which would orphan their audio objects:
+    // require the songs to be deleted first through the songs API.
+    let song_count = songs_dsl::songs
+        .filter(songs_dsl::artist_id.eq(&artist_id))
+        .count()
+        .get_result::<i64>(&mut conn);
+
+    match song_count {
+        Ok(0) => {}
+        Ok(_) => return HttpResponse::Conflict().body("Cannot delete artist: delete their songs first"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match diesel::delete(artists_dsl::artists.filter(artists_dsl::id.eq(&artist_id))).execute(&mut conn) {
+        Ok(0) => HttpResponse::NotFound().body("Artist not found"),
+        Ok(_) => HttpResponse::Ok().body("Artist deleted successfully"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn get_artist_albums(
+    pool: web::Data<DbPool>,
+    artist_id_param: web::Path<String>,
+    query: web::Query<Pagination>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let artist_id = artist_id_param.into_inner();
+
+    let page = match validate_pagination(&query) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    match artist_exists(&mut conn, &artist_id) {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("Artist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let keyset = KeysetQuery {
+        select: ALBUM_COLUMNS.to_string(),
+        from: "FROM albums al".to_string(),
+        conditions: vec!["al.artist_id = ?".to_string()],
+        binds: vec![BindValue::Text(artist_id)],
+        keys: vec![
+            SortKey::desc("al.release_year").nullable(),
+            SortKey::asc("al.name"),
+            SortKey::asc("al.id"),
+        ],
+        shuffle: false,
+    };
+
+    match load_page::<Album>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn get_artist_songs(
+    pool: web::Data<DbPool>,
+    artist_id_param: web::Path<String>,
+    query: web::Query<Pagination>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let artist_id = artist_id_param.into_inner();
+
+    let page = match validate_pagination(&query) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    match artist_exists(&mut conn, &artist_id) {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("Artist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match SongListing::new(SongSource::Artist(artist_id)).load_page(&mut conn, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+fn artist_exists(conn: &mut MysqlConnection, artist_id: &str) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(artists_dsl::artists.filter(artists_dsl::id.eq(artist_id))))
+        .get_result::<bool>(conn)
+}
@@ -0,0 +1,71 @@
+use actix_web::http::header;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use futures::StreamExt;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::models::backup_models::{BackupError, BackupQuery, RestoreQuery};
+use crate::storage::ObjectStorage;
+use crate::utils::backup_utils::{restore_archive, write_backup};
+
+/// Download the whole library as a tar archive
+pub async fn download_backup(
+    pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    query: web::Query<BackupQuery>,
+) -> impl Responder {
+    let path = std::env::temp_dir().join(format!("echo-backup-{}.tar", Uuid::new_v4()));
+    if let Err(e) = write_backup(&pool, storage.get_ref(), query.credentials.unwrap_or(false), &path).await {
+        let _ = tokio::fs::remove_file(&path).await;
+        return e.error_response();
+    }
+
+    let file = tokio::fs::File::open(&path).await;
+    // Unlinked right away: the open file is still streamed, and its space freed once it is closed
+    let _ = tokio::fs::remove_file(&path).await;
+    let file = match file {
+        Ok(f) => f,
+        Err(e) => return BackupError::from(e).error_response(),
+    };
+
+    let filename = format!("echo-backup-{}.tar", Utc::now().format("%Y%m%d-%H%M%S"));
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "application/x-tar"))
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .streaming(ReaderStream::new(file))
+}
+
+/// Write the request body to `path`, without holding the archive in memory
+async fn spool_payload(mut payload: web::Payload, path: &Path) -> Result<(), BackupError> {
+    let mut file = tokio::fs::File::create(path).await?;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| BackupError::Invalid(format!("upload failed: {}", e)))?;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Restore a tar archive sent as the body
+pub async fn restore_backup(
+    pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    query: web::Query<RestoreQuery>,
+    payload: web::Payload,
+) -> impl Responder {
+    let path = std::env::temp_dir().join(format!("echo-restore-{}.tar", Uuid::new_v4()));
+    let result = match spool_payload(payload, &path).await {
+        Ok(()) => restore_archive(&pool, storage.get_ref(), &path, query.remap_ids.unwrap_or(false)).await,
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&path).await;
+
+    match result {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => e.error_response(),
+    }
+}
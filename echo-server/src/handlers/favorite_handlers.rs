@@ -2,7 +2,6 @@ use actix_web::{web, HttpResponse, Responder, ResponseError};
 use actix_web::web::ReqData;
 use diesel::prelude::*;
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
-use diesel::sql_types::Text;
 
 use crate::db::DbPool;
 use crate::db::get_conn;
@@ -10,14 +9,15 @@ use crate::models::favorite_models::{NewFavorite, AddFavoriteRequest};
 use crate::models::pagination_models::Pagination;
 use crate::models::token_models::Claims;
 use crate::schema::favorites::dsl as fav_dsl;
-use crate::models::song_models::{SongResponse};
-use crate::utils::auth_utils::check_ownership;
+use crate::models::song_models::SongQuery;
+use crate::utils::auth_utils::{check_ownership, is_admin};
 use crate::utils::pagination_utils::validate_pagination;
+use crate::utils::song_query_utils::{song_listing, SongSource};
 
 pub async fn list_favorites(
     pool: web::Data<DbPool>,
     user_id_param: web::Path<String>,
-    query: web::Query<Pagination>,
+    query: web::Query<SongQuery>,
     claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
@@ -31,41 +31,27 @@ pub async fn list_favorites(
         Err(resp) => return resp,
     };
 
-    let pagination: Pagination = query.into_inner();
-    match validate_pagination(&pagination) {
-        Ok(v) => v,
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
         Err(e) => return e.error_response(),
     };
 
-    let sql = format!(r#"
-        SELECT 
-            s.id,
-            s.title,
-            s.artist_id,
-            a.name AS artist_name,
-            s.album_id,
-            al.name AS album_name,
-            s.genre_id,
-            g.name AS genre_name,
-            s.duration_seconds,
-            s.object_url,
-            s.created_at,
-            s.updated_at
-        FROM favorites f
-        JOIN songs s ON f.song_id = s.id
-        JOIN artists a ON s.artist_id = a.id
-        LEFT JOIN albums al ON s.album_id = al.id
-        LEFT JOIN genres g ON s.genre_id = g.id
-        WHERE f.user_id = $1
-        {}
-    "#, pagination.sql_clause().unwrap());
-
-    match diesel::sql_query(sql)
-        .bind::<Text, _>(user_id)
-        .load::<SongResponse>(&mut conn)
-    {
-        Ok(list) => HttpResponse::Ok().json(list),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let listing = match song_listing(SongSource::Favorites(user_id.to_string()), &query) {
+        Ok(l) => l,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    match listing.load_page(&mut conn, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
+        },
+        Err(e) => e.error_response(),
     }
 }
 
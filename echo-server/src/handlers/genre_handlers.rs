@@ -0,0 +1,206 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Integer;
+
+use crate::db::{get_conn, DbPool};
+use crate::models::genre_models::{Genre, GenreQuery, NewGenre, UpdateGenre};
+use crate::models::pagination_models::Pagination;
+use crate::models::token_models::Claims;
+use crate::schema::genres::dsl as genres_dsl;
+use crate::utils::auth_utils::is_admin;
+use crate::utils::pagination_utils::{load_page, validate_pagination, KeysetQuery, SortKey};
+use crate::utils::song_query_utils::{contains_pattern, SongListing, SongSource};
+use crate::utils::sql_utils::BindValue;
+
+pub async fn list_genres(
+    pool: web::Data<DbPool>,
+    query: web::Query<GenreQuery>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    let mut keyset = KeysetQuery {
+        select: "g.id, g.name".to_string(),
+        from: "FROM genres g".to_string(),
+        conditions: Vec::new(),
+        binds: Vec::new(),
+        keys: vec![SortKey::asc("g.name"), SortKey::asc("g.id")],
+        shuffle: false,
+    };
+
+    if let Some(ref term) = query.q {
+        let term = term.trim();
+        if !term.is_empty() {
+            keyset.conditions.push("g.name LIKE ?".to_string());
+            keyset.binds.push(BindValue::Text(contains_pattern(term)));
+        }
+    }
+
+    match load_page::<Genre>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn create_genre(
+    pool: web::Data<DbPool>,
+    payload: web::Json<NewGenre>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let genre_name = payload.name.trim().to_string();
+    if genre_name.is_empty() {
+        return HttpResponse::BadRequest().body("Genre name cannot be empty");
+    }
+
+    // genres.name has no unique key, so duplicates are rejected here
+    match genre_name_taken(&mut conn, &genre_name, None) {
+        Ok(false) => {}
+        Ok(true) => return HttpResponse::Conflict().body("A genre with this name already exists"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    if let Err(e) = diesel::insert_into(genres_dsl::genres)
+        .values(&NewGenre { name: genre_name })
+        .execute(&mut conn)
+    {
+        return HttpResponse::InternalServerError().body(format!("Database error: {}", e));
+    }
+
+    // genres.id is AUTO_INCREMENT and LAST_INSERT_ID() is scoped to this connection
+    let result = diesel::select(diesel::dsl::sql::<Integer>("LAST_INSERT_ID()"))
+        .get_result::<i32>(&mut conn)
+        .and_then(|new_id| genres_dsl::genres.find(new_id).select(Genre::as_select()).first::<Genre>(&mut conn));
+
+    match result {
+        Ok(genre) => HttpResponse::Created().json(genre),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn rename_genre(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    payload: web::Json<UpdateGenre>,
+) -> impl Responder {
+    let genre_id = path.into_inner();
+
+    let genre_name = match payload.name.as_deref().map(str::trim) {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => return HttpResponse::BadRequest().body("Genre name cannot be empty"),
+    };
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    match genre_name_taken(&mut conn, &genre_name, Some(genre_id)) {
+        Ok(false) => {}
+        Ok(true) => return HttpResponse::Conflict().body("A genre with this name already exists"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let exec_result = diesel::update(genres_dsl::genres.find(genre_id))
+        .set(genres_dsl::name.eq(&genre_name))
+        .execute(&mut conn);
+
+    match exec_result {
+        Ok(_) => {
+            match genres_dsl::genres.find(genre_id).select(Genre::as_select()).first::<Genre>(&mut conn) {
+                Ok(genre) => HttpResponse::Ok().json(genre),
+                Err(DieselError::NotFound) => HttpResponse::NotFound().body("Genre not found"),
+                Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+            }
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn delete_genre(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let genre_id = path.into_inner();
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    // songs.genre_id is ON DELETE SET NULL, so songs simply lose their genre
+    match diesel::delete(genres_dsl::genres.find(genre_id)).execute(&mut conn) {
+        Ok(0) => HttpResponse::NotFound().body("Genre not found"),
+        Ok(_) => HttpResponse::Ok().body("Genre deleted successfully"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {}", e)),
+    }
+}
+
+pub async fn get_genre_songs(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<Pagination>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let genre_id = path.into_inner();
+
+    let page = match validate_pagination(&query) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    match genre_exists(&mut conn, genre_id) {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().body("Genre not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match SongListing::new(SongSource::Genre(genre_id)).load_page(&mut conn, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Check whether another genre already uses `genre_name`, ignoring `except_id` when renaming.
+fn genre_name_taken(conn: &mut MysqlConnection, genre_name: &str, except_id: Option<i32>) -> QueryResult<bool> {
+    let mut q = genres_dsl::genres
+        .filter(genres_dsl::name.eq(genre_name))
+        .into_boxed();
+
+    if let Some(except_id) = except_id {
+        q = q.filter(genres_dsl::id.ne(except_id));
+    }
+
+    q.count().get_result::<i64>(conn).map(|count| count > 0)
+}
+
+/// Check that a genre exists, used to validate `genre_id` on song writes.
+pub fn genre_exists(conn: &mut MysqlConnection, genre_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(genres_dsl::genres.find(genre_id)))
+        .get_result::<bool>(conn)
+}
@@ -0,0 +1,40 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use diesel::prelude::*;
+
+use crate::db::{get_conn, DbPool};
+use crate::models::ingest_models::{IngestJob, IngestJobResponse};
+use crate::models::token_models::Claims;
+use crate::schema::ingest_jobs;
+use crate::utils::auth_utils::is_admin;
+
+pub async fn get_ingest_job(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let job_id = path.into_inner();
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let job = match ingest_jobs::table
+        .find(&job_id)
+        .select(IngestJob::as_select())
+        .first::<IngestJob>(&mut conn)
+        .optional()
+    {
+        Ok(Some(j)) => j,
+        Ok(None) => return HttpResponse::NotFound().body("Ingest job not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Jobs of other users are reported as missing, unless asked by an admin
+    if job.user_id != claims.sub && !is_admin(&mut conn, &claims.sub) {
+        return HttpResponse::NotFound().body("Ingest job not found");
+    }
+
+    HttpResponse::Ok().json(IngestJobResponse::from(job))
+}
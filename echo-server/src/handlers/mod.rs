@@ -3,4 +3,14 @@ pub mod session_handlers;
 pub mod song_handlers;
 pub mod favorite_handlers;
 pub mod playlist_handlers;
-pub mod album_handlers;
\ No newline at end of file
+pub mod album_handlers;
+pub mod artist_handlers;
+pub mod genre_handlers;
+pub mod ingest_handlers;
+pub mod subsonic_handlers;
+pub mod play_handlers;
+pub mod stats_handlers;
+pub mod recommendation_handlers;
+pub mod radio_handlers;
+pub mod search_handlers;
+pub mod backup_handlers;
\ No newline at end of file
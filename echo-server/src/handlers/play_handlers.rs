@@ -0,0 +1,163 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::{BigInt, Timestamp};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::models::pagination_models::Pagination;
+use crate::models::play_models::{
+    HistoryEntry, NewPlay, NowPlayingEntry, NowPlayingRequest, ReportPlayRequest, PLAY_SOURCE_REPORT,
+};
+use crate::models::token_models::Claims;
+use crate::utils::auth_utils::{check_ownership, is_admin};
+use crate::utils::pagination_utils::{load_page, validate_pagination, KeysetQuery, SortKey};
+use crate::utils::play_utils::{clear_now_playing, client_name, record_play, set_now_playing, NOW_PLAYING_GRACE_SECONDS};
+use crate::utils::song_query_utils::{SONG_COLUMNS, SONG_JOINS};
+use crate::utils::sql_utils::BindValue;
+
+/// Reported plays may start this far in the future, to allow for client clock skew
+const MAX_CLOCK_SKEW_SECONDS: i64 = 300;
+
+/// Report a finished play of a song
+pub async fn report_play(
+    pool: web::Data<DbPool>,
+    payload: web::Json<ReportPlayRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let request = payload.into_inner();
+    let now = Utc::now().naive_utc();
+    let played_at = request.played_at.unwrap_or(now);
+
+    if played_at > now + Duration::seconds(MAX_CLOCK_SKEW_SECONDS) {
+        return HttpResponse::BadRequest().body("played_at cannot be in the future");
+    }
+    if request.seconds_listened.is_some_and(|s| s < 0) {
+        return HttpResponse::BadRequest().body("seconds_listened cannot be negative");
+    }
+
+    let play = NewPlay {
+        id: Uuid::new_v4().to_string(),
+        user_id: claims.sub.clone(),
+        song_id: request.song_id.clone(),
+        played_at,
+        seconds_listened: request.seconds_listened,
+        client: client_name(request.client.as_deref()),
+        source: PLAY_SOURCE_REPORT.to_string(),
+    };
+
+    match record_play(&mut conn, play) {
+        Ok(play_id) => {
+            // The song is no longer playing; a failure here only leaves a stale entry that expires
+            let _ = clear_now_playing(&mut conn, &claims.sub, &request.song_id);
+            match play_id {
+                Some(play_id) => HttpResponse::Created().json(serde_json::json!({ "id": play_id })),
+                // Already recorded
+                None => HttpResponse::Ok().finish(),
+            }
+        }
+        Err(DieselError::NotFound) => HttpResponse::NotFound().body("Song not found"),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Tell others what the user is listening to
+pub async fn update_now_playing(
+    pool: web::Data<DbPool>,
+    payload: web::Json<NowPlayingRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    match set_now_playing(&mut conn, &claims.sub, &payload.song_id, client_name(payload.client.as_deref())) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            HttpResponse::NotFound().body("Song not found")
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// What every user is listening to. Entries expire once the song should have ended.
+pub async fn list_now_playing(pool: web::Data<DbPool>) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let sql = r#"
+        SELECT
+            np.user_id, u.username, np.song_id, s.title, a.name AS artist_name,
+            np.client, np.started_at
+        FROM now_playing np
+        JOIN users u ON np.user_id = u.id
+        JOIN songs s ON np.song_id = s.id
+        JOIN artists a ON s.artist_id = a.id
+        WHERE np.started_at + INTERVAL (s.duration_seconds + ?) SECOND > ?
+        ORDER BY np.started_at DESC
+    "#;
+
+    match diesel::sql_query(sql)
+        .bind::<BigInt, _>(NOW_PLAYING_GRACE_SECONDS)
+        .bind::<Timestamp, _>(Utc::now().naive_utc())
+        .load::<NowPlayingEntry>(&mut conn)
+    {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The user's plays, most recent first
+pub async fn get_history(
+    pool: web::Data<DbPool>,
+    user_id_param: web::Path<String>,
+    query: web::Query<Pagination>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let page = match validate_pagination(&query) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    let keyset = KeysetQuery {
+        select: format!("p.id AS play_id, p.played_at, p.seconds_listened, p.client, p.source, {SONG_COLUMNS}"),
+        from: format!("FROM plays p JOIN songs s ON p.song_id = s.id {SONG_JOINS}"),
+        conditions: vec!["p.user_id = ?".to_string()],
+        binds: vec![BindValue::Text(user_id.to_string())],
+        keys: vec![SortKey::desc("p.played_at"), SortKey::asc("p.id")],
+        shuffle: false,
+    };
+
+    match load_page::<HistoryEntry>(&mut conn, &keyset, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|mut entry| {
+                entry.song = entry.song.for_viewer(admin);
+                entry
+            }))
+        }
+        Err(e) => e.error_response(),
+    }
+}
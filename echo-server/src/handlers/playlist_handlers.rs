@@ -1,20 +1,34 @@
 use actix_web::web::ReqData;
+use actix_web::http::header;
 use actix_web::{web, HttpResponse, Responder, ResponseError};
 use diesel::prelude::*;
-use diesel::sql_types::Text;
 use uuid::Uuid;
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
 
 use crate::db::DbPool;
 use crate::db::get_conn;
 use crate::models::pagination_models::Pagination;
-use crate::models::playlist_models::{NewPlaylist, NewPlaylistSong, AddSongRequest};
-use crate::models::playlist_models::{Playlist, PlaylistQuery};
-use crate::models::song_models::{SongResponse};
+use crate::models::playlist_models::{NewPlaylist, AddSongRequest, MoveSongRequest, PlaylistEntry, ReorderSongsRequest};
+use crate::models::playlist_models::{NewSmartPlaylist, Playlist, PlaylistQuery, SmartRules, KIND_SMART};
+use crate::models::playlist_models::{NewPlaylistMember, PlaylistMemberResponse, SetMemberRequest, ROLE_EDITOR, ROLE_VIEWER};
+use crate::models::playlist_models::{
+    CopyPlaylistRequest, NewPlaylistFollow, NewPlaylistSong, PlaylistSummary, PublicPlaylistQuery,
+};
+use crate::models::playlist_models::{ExportQuery, ImportQuery, ImportReport, PlaylistFormat, UnmatchedEntry};
+use crate::models::song_models::SongQuery;
 use crate::models::token_models::Claims;
-use crate::schema::{playlists::dsl as playlists_dsl, playlist_songs::dsl as ps_dsl};
-use crate::utils::auth_utils::check_ownership;
-use crate::utils::pagination_utils::validate_pagination;
+use crate::schema::{playlist_follows, playlist_members, playlist_songs, playlists::dsl as playlists_dsl, users};
+use crate::utils::auth_utils::{check_ownership, is_admin};
+use crate::utils::pagination_utils::{load_page, validate_pagination, KeysetQuery, SortKey};
+use crate::utils::song_query_utils::{contains_pattern, song_listing, SongSource};
+use crate::utils::playlist_utils::{
+    insert_song, move_song, ordered_listing, playlist_access, playlist_summaries, public_sort_keys, remove_song,
+    reorder_songs, smart_listing, song_order, stored_rules, OrderError, PlaylistAccess,
+};
+use crate::utils::playlist_format_utils::{parse_playlist, render_playlist};
+use crate::utils::signing_utils::{export_stream_url_ttl_seconds, public_base_url, signed_stream_url};
+use crate::utils::song_match_utils::match_song;
+use crate::utils::sql_utils::BindValue;
 
 // --------------------- Playlists ---------------------
 pub async fn list_playlists(
@@ -36,34 +50,42 @@ pub async fn list_playlists(
     let pagination = Pagination {
         limit: query.limit,
         offset: query.offset,
+        cursor: query.cursor.clone(),
     };
-    let (limit, offset) = match validate_pagination(&pagination) {
-        Ok(v) => v,
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
         Err(e) => return e.error_response(),
     };
 
     // Base query: playlists of this user
-    let mut q = playlists_dsl::playlists
-        .filter(playlists_dsl::user_id.eq(&user_id))
-        .into_boxed();
+    let mut keyset = KeysetQuery {
+        select: "pl.id, pl.user_id, pl.name, pl.description, pl.is_public, pl.created_at, pl.updated_at, pl.kind, pl.rules"
+            .to_string(),
+        from: "FROM playlists pl".to_string(),
+        conditions: vec!["pl.user_id = ?".to_string()],
+        binds: vec![BindValue::Text(user_id)],
+        keys: vec![SortKey::asc("pl.name"), SortKey::asc("pl.id")],
+        shuffle: false,
+    };
 
-    // Only public if not owner
+    // Only public or shared with the requester if not owner
     if !is_owner {
-        q = q.filter(playlists_dsl::is_public.eq(true));
+        keyset.conditions.push(
+            "(pl.is_public = TRUE OR EXISTS (SELECT 1 FROM playlist_members m WHERE m.playlist_id = pl.id AND m.user_id = ?))"
+                .to_string(),
+        );
+        keyset.binds.push(BindValue::Text(logged_in_user_id.clone()));
     }
 
     // Optional filtering by name
     if let Some(ref name) = query.name {
-        let like_pattern = format!("%{}%", name);
-        q = q.filter(playlists_dsl::name.like(like_pattern));
+        keyset.conditions.push("pl.name LIKE ?".to_string());
+        keyset.binds.push(BindValue::Text(contains_pattern(name)));
     }
 
-    // Pagination + exec
-    let result = q.limit(limit).offset(offset).load::<Playlist>(&mut conn);
-
-    match result {
-        Ok(list) => HttpResponse::Ok().json(list),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    match load_page::<Playlist>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
     }
 }
 
@@ -90,6 +112,8 @@ pub async fn create_playlist(
         description: payload.description.clone(),
         is_public: payload.is_public,
         id: Uuid::new_v4().to_string(),
+        kind: None,
+        rules: None,
     };
 
     let result = diesel::insert_into(playlists_dsl::playlists)
@@ -105,9 +129,59 @@ pub async fn create_playlist(
     }
 }
 
-pub async fn get_playlist(
+/// Create a playlist whose songs are listed from rules rather than added one by one
+pub async fn create_smart_playlist(
+    pool: web::Data<DbPool>,
+    user_id_param: web::Path<String>,
+    payload: web::Json<NewSmartPlaylist>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let payload = payload.into_inner();
+    if let Err(message) = smart_listing(&payload.rules, user_id) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    let rules = match serde_json::to_string(&payload.rules) {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let new_playlist = NewPlaylist {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        name: payload.name,
+        description: payload.description,
+        is_public: payload.is_public,
+        kind: Some(KIND_SMART.to_string()),
+        rules: Some(rules),
+    };
+
+    let result = diesel::insert_into(playlists_dsl::playlists)
+        .values(&new_playlist)
+        .execute(&mut conn)
+        .and_then(|_| playlists_dsl::playlists.find(&new_playlist.id).first::<Playlist>(&mut conn));
+
+    match result {
+        Ok(playlist) => HttpResponse::Created().json(playlist),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Replace the rules of a smart playlist (owner only)
+pub async fn update_playlist_rules(
     pool: web::Data<DbPool>,
     path: web::Path<(String, String)>,
+    payload: web::Json<SmartRules>,
     claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
@@ -117,27 +191,55 @@ pub async fn get_playlist(
 
     let (user_id_param, playlist_id_param) = path.into_inner();
 
-    let user_id: String = user_id_param.clone();
-    let logged_in_user_id: &String = &claims.sub;
+    let user_id: &str = match check_ownership(&user_id_param, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
 
-    let is_owner: bool = logged_in_user_id == &user_id;
-    
-    // Building a query to find the specific playlist for the user.
-    // Use .into_boxed() to allow for conditionally adding more filters.
-    let mut query = playlists_dsl::playlists
-        .filter(playlists_dsl::id.eq(&playlist_id_param))
-        .filter(playlists_dsl::user_id.eq(&user_id_param))
-        .into_boxed();
-
-    // If the person making the request is NOT the owner, they can only
-    // see the playlist if it's public. We add this as a required condition.
-    if !is_owner {
-        query = query.filter(playlists_dsl::is_public.eq(true));
+    if let Err(message) = smart_listing(&payload, user_id) {
+        return HttpResponse::BadRequest().body(message);
     }
+    let rules = match serde_json::to_string(&payload.into_inner()) {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
 
-    let result = query.first::<Playlist>(&mut conn);
+    let affected = diesel::update(
+        playlists_dsl::playlists
+            .filter(playlists_dsl::user_id.eq(user_id))
+            .filter(playlists_dsl::id.eq(&playlist_id_param))
+            .filter(playlists_dsl::kind.eq(KIND_SMART)),
+    )
+    .set(playlists_dsl::rules.eq(Some(rules)))
+    .execute(&mut conn);
 
-    match result {
+    match affected {
+        Ok(0) => HttpResponse::NotFound().body("Smart playlist not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn get_playlist(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String)>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let (user_id_param, playlist_id_param) = path.into_inner();
+
+    // Owners and members see the playlist, anyone else only if it is public
+    match playlist_access(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match playlists_dsl::playlists.find(&playlist_id_param).first::<Playlist>(&mut conn) {
         Ok(p) => HttpResponse::Ok().json(p),
         Err(_) => HttpResponse::NotFound().finish(),
     }
@@ -212,69 +314,101 @@ pub async fn delete_playlist(
 pub async fn list_playlist_songs(
     pool: web::Data<DbPool>,
     path: web::Path<(String, String)>,
-    query: web::Query<Pagination>,
+    query: web::Query<SongQuery>,
     claims: ReqData<Claims>,
 ) -> impl Responder {
-    // Only the playlist_id is needed from the path for the initial query
-    let (_user_id_param, playlist_id_param) = path.into_inner();
-    let logged_in_user_id = &claims.sub;
+    let (user_id_param, playlist_id_param) = path.into_inner();
 
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
         Err(e) => return e.error_response(),
     };
 
-    // Fetch the playlist by its ID first to check its status (public/private)
-    let playlist = match playlists_dsl::playlists
+    // Allow access if the playlist is public, or to its owner and members.
+    // Private playlists answer 404 to anyone else, to avoid revealing their existence.
+    match playlist_access(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let (kind, rules) = match playlists_dsl::playlists
         .find(&playlist_id_param)
-        .first::<Playlist>(&mut conn)
-        .optional()
+        .select((playlists_dsl::kind, playlists_dsl::rules))
+        .first::<(String, Option<String>)>(&mut conn)
     {
-        Ok(Some(p)) => p, // playlist found
-        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"), // playlist does not exist
-        Err(_) => return HttpResponse::InternalServerError().finish(), // database error
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // The user is authorized. Proceed to fetch songs
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    // Smart playlists are listed from their rules, in their order; only paging applies
+    let listing = if kind == KIND_SMART {
+        smart_listing(&stored_rules(rules.as_deref()), &user_id_param)
+    } else {
+        song_listing(SongSource::Playlist(playlist_id_param), &query)
+    };
+    let listing = match listing {
+        Ok(l) => l,
+        Err(message) => return HttpResponse::BadRequest().body(message),
     };
 
-    let is_public: bool = playlist.is_public.unwrap_or(false);
+    match listing.load_page(&mut conn, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
+        },
+        Err(e) => e.error_response(),
+    }
+}
 
-    // Authorize access
-    // Allow access if the playlist is public OR if the logged-in user is the owner
-    let is_owner: bool = playlist.user_id == *logged_in_user_id;
-    if !is_public && !is_owner {
-        // It's private and the requester is not the owner.
-        // Return 404 to avoid revealing its existence.
-        return HttpResponse::NotFound().body("Playlist not found");
+/// Let through the owner and editors of a playlist of `owner_id`
+fn require_editor(
+    conn: &mut MysqlConnection,
+    playlist_id: &str,
+    owner_id: &str,
+    user_id: &str,
+) -> Result<(), HttpResponse> {
+    match playlist_access(conn, playlist_id, owner_id, user_id) {
+        Ok(Some(access)) if access >= PlaylistAccess::Editor => Ok(()),
+        Ok(Some(_)) => Err(HttpResponse::Forbidden().body("Only the owner and editors of this playlist can do this")),
+        Ok(None) => Err(HttpResponse::NotFound().body("Playlist not found")),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
     }
+}
 
-    // The user is authorized. Proceed to fetch songs
-    let pagination: Pagination = query.into_inner();
-    if let Err(e) = validate_pagination(&pagination) {
-        return e.error_response();
-    };
-    
-    let sql = format!(r#"
-        SELECT 
-            s.id, s.title, s.artist_id, a.name AS artist_name,
-            s.album_id, al.name AS album_name, s.genre_id, g.name AS genre_name,
-            s.duration_seconds, s.object_url, s.created_at, s.updated_at
-        FROM playlist_songs ps
-        JOIN songs s ON ps.song_id = s.id
-        JOIN artists a ON s.artist_id = a.id
-        LEFT JOIN albums al ON s.album_id = al.id
-        LEFT JOIN genres g ON s.genre_id = g.id
-        WHERE ps.playlist_id = $1
-        {}
-    "#, pagination.sql_clause().unwrap_or_default());
-
-    match diesel::sql_query(sql)
-        .bind::<Text, _>(&playlist_id_param)
-        .load::<SongResponse>(&mut conn) 
-    {
-        Ok(list) => HttpResponse::Ok().json(list),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+fn order_error_response(e: OrderError) -> HttpResponse {
+    match e {
+        OrderError::Position(max) => {
+            HttpResponse::BadRequest().body(format!("position must be between 0 and {}", max))
+        }
+        OrderError::AlreadyInPlaylist => HttpResponse::Conflict().body("Song already in playlist"),
+        OrderError::NotInPlaylist => HttpResponse::NotFound().body("Song not found"),
+        OrderError::Mismatch => {
+            HttpResponse::BadRequest().body("song_ids must list every song of the playlist exactly once")
+        }
+        OrderError::SmartPlaylist => {
+            HttpResponse::Conflict().body("The songs of a smart playlist come from its rules and cannot be changed")
+        }
+        OrderError::Database(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            HttpResponse::NotFound().body("Song not found")
+        }
+        OrderError::Database(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+/// Add a song at `position`, or at the end when none is given. Open to the owner and editors,
+/// like every change to the songs.
 pub async fn add_song_to_playlist(
     pool: web::Data<DbPool>,
     path: web::Path<(String, String)>,
@@ -288,45 +422,83 @@ pub async fn add_song_to_playlist(
 
     let (user_id_param, playlist_id_param) = path.into_inner();
 
-    let user_id: String = user_id_param.clone();
-    let user_id: &str = match check_ownership(&user_id, &claims) {
-        Ok(id) => id,
-        Err(resp) => return resp,
+    if let Err(resp) = require_editor(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        return resp;
+    }
+
+    let result = conn.transaction::<_, OrderError, _>(|conn| {
+        insert_song(conn, &playlist_id_param, &payload.song_id, payload.position, &claims.sub)
+    });
+
+    match result {
+        Ok(position) => HttpResponse::Created().json(PlaylistEntry {
+            song_id: payload.song_id.clone(),
+            position,
+        }),
+        Err(e) => order_error_response(e),
+    }
+}
+
+/// Move a song to another position, shifting the songs in between
+pub async fn move_playlist_song(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<MoveSongRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
     };
 
-    // Ensure the playlist actually belongs to the user
-    let playlist_owned = match playlists_dsl::playlists
-        .filter(playlists_dsl::id.eq(&playlist_id_param))
-        .filter(playlists_dsl::user_id.eq(user_id))
-        .first::<Playlist>(&mut conn)
-        .optional() {
-            Ok(opt) => opt,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
+    let (user_id_param, playlist_id_param, song_id_param) = path.into_inner();
 
-    if playlist_owned.is_none() {
-        return HttpResponse::NotFound().body("Playlist not found or not owned by user");
+    if let Err(resp) = require_editor(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        return resp;
     }
 
-    let new_song = NewPlaylistSong {
-        playlist_id: playlist_id_param,
-        song_id: payload.song_id.clone(),
-        position: payload.position,
+    let result = conn.transaction::<_, OrderError, _>(|conn| {
+        move_song(conn, &playlist_id_param, &song_id_param, payload.position)
+    });
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(PlaylistEntry {
+            song_id: song_id_param,
+            position: payload.position,
+        }),
+        Err(e) => order_error_response(e),
+    }
+}
+
+/// Put every song of the playlist in the given order
+pub async fn reorder_playlist_songs(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<ReorderSongsRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
     };
 
-    let result = diesel::insert_into(ps_dsl::playlist_songs)
-        .values(&new_song)
-        .execute(&mut conn);
+    let (user_id_param, playlist_id_param) = path.into_inner();
+
+    if let Err(resp) = require_editor(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        return resp;
+    }
+
+    let result = conn.transaction::<_, OrderError, _>(|conn| {
+        reorder_songs(conn, &playlist_id_param, &payload.song_ids)
+    });
 
     match result {
-        Ok(_) => HttpResponse::Created().finish(),
-        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
-            HttpResponse::Conflict().body("Song already in playlist")
-        }
-        Err(_) => HttpResponse::InternalServerError().finish(),
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => order_error_response(e),
     }
 }
 
+/// Remove a song; the songs after it move up
 pub async fn remove_song_from_playlist(
     pool: web::Data<DbPool>,
     path: web::Path<(String, String, String)>,
@@ -339,34 +511,539 @@ pub async fn remove_song_from_playlist(
 
     let (user_id_param, playlist_id_param, song_id_param) = path.into_inner();
 
-    let user_id: String = user_id_param.clone();
+    if let Err(resp) = require_editor(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        return resp;
+    }
+
+    let result = conn.transaction::<_, OrderError, _>(|conn| {
+        remove_song(conn, &playlist_id_param, &song_id_param)
+    });
+
+    match result {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => order_error_response(e),
+    }
+}
+
+// --------------------- Playlist Members ---------------------
+/// Members of a playlist, for its owner and editors
+pub async fn list_playlist_members(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String)>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let (user_id_param, playlist_id_param) = path.into_inner();
+
+    if let Err(resp) = require_editor(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        return resp;
+    }
+
+    let result = playlist_members::table
+        .inner_join(users::table)
+        .filter(playlist_members::playlist_id.eq(&playlist_id_param))
+        .order(playlist_members::added_at.asc())
+        .select((playlist_members::user_id, users::username, playlist_members::role, playlist_members::added_at))
+        .load::<PlaylistMemberResponse>(&mut conn);
+
+    match result {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Share a playlist with a user as editor or viewer, or change their role (owner only)
+pub async fn set_playlist_member(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String, String)>,
+    payload: web::Json<SetMemberRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let (user_id_param, playlist_id_param, member_id_param) = path.into_inner();
+
+    let user_id: &str = match check_ownership(&user_id_param, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    match playlist_access(&mut conn, &playlist_id_param, user_id, user_id) {
+        Ok(Some(PlaylistAccess::Owner)) => {}
+        Ok(_) => return HttpResponse::NotFound().body("Playlist not found or not owned by user"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let role = payload.role.trim().to_lowercase();
+    if role != ROLE_EDITOR && role != ROLE_VIEWER {
+        return HttpResponse::BadRequest().body("role must be \"editor\" or \"viewer\"");
+    }
+    if member_id_param == user_id {
+        return HttpResponse::BadRequest().body("The owner cannot be a member of their own playlist");
+    }
+
+    let result = conn.transaction::<_, DieselError, _>(|conn| {
+        let updated = diesel::update(
+            playlist_members::table
+                .filter(playlist_members::playlist_id.eq(&playlist_id_param))
+                .filter(playlist_members::user_id.eq(&member_id_param)),
+        )
+        .set(playlist_members::role.eq(&role))
+        .execute(conn)?;
+        if updated == 0 {
+            diesel::insert_into(playlist_members::table)
+                .values(&NewPlaylistMember {
+                    playlist_id: playlist_id_param.clone(),
+                    user_id: member_id_param.clone(),
+                    role: role.clone(),
+                })
+                .execute(conn)?;
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            HttpResponse::NotFound().body("User not found")
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Stop sharing a playlist with a user: the owner removes anyone, members can leave
+pub async fn remove_playlist_member(
+    pool: web::Data<DbPool>,
+    path: web::Path<(String, String, String)>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let (user_id_param, playlist_id_param, member_id_param) = path.into_inner();
+
+    match playlist_access(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        Ok(Some(PlaylistAccess::Owner)) => {}
+        Ok(Some(_)) if member_id_param == claims.sub => {}
+        Ok(Some(_)) => return HttpResponse::Forbidden().body("Only the owner can remove other members"),
+        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let affected = diesel::delete(
+        playlist_members::table
+            .filter(playlist_members::playlist_id.eq(&playlist_id_param))
+            .filter(playlist_members::user_id.eq(&member_id_param)),
+    )
+    .execute(&mut conn);
+
+    match affected {
+        Ok(0) => HttpResponse::NotFound().body("Member not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// --------------------- Public Playlists ---------------------
+/// Browse and search the public playlists of every user
+pub async fn list_public_playlists(
+    pool: web::Data<DbPool>,
+    query: web::Query<PublicPlaylistQuery>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let pagination = Pagination {
+        limit: query.limit,
+        offset: query.offset,
+        cursor: query.cursor.clone(),
+    };
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
+
+    let keys = match public_sort_keys(query.sort.as_deref().unwrap_or("popular")) {
+        Some(k) => k,
+        None => return HttpResponse::BadRequest().body("sort must be popular, recent or name"),
+    };
+
+    let mut conditions = vec!["pl.is_public = TRUE".to_string()];
+    let mut binds = Vec::new();
+    if let Some(term) = query.q.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        conditions.push("(pl.name LIKE ? OR pl.description LIKE ?)".to_string());
+        binds.push(BindValue::Text(contains_pattern(term)));
+        binds.push(BindValue::Text(contains_pattern(term)));
+    }
+
+    match load_page::<PlaylistSummary>(&mut conn, &playlist_summaries(conditions, binds, keys), &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Public playlists of other users the user follows, while they stay public
+pub async fn list_followed_playlists(
+    pool: web::Data<DbPool>,
+    user_id_param: web::Path<String>,
+    query: web::Query<Pagination>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let user_id: String = user_id_param.into_inner();
     let user_id: &str = match check_ownership(&user_id, &claims) {
         Ok(id) => id,
         Err(resp) => return resp,
     };
 
-    // Ensure the playlist actually belongs to the user
-    let playlist_owned = match playlists_dsl::playlists
-        .filter(playlists_dsl::id.eq(&playlist_id_param))
-        .filter(playlists_dsl::user_id.eq(user_id))
-        .first::<Playlist>(&mut conn)
-        .optional() {
-            Ok(opt) => opt,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
+    let page = match validate_pagination(&query) {
+        Ok(p) => p,
+        Err(e) => return e.error_response(),
+    };
 
-    if playlist_owned.is_none() {
-        return HttpResponse::NotFound().body("Playlist not found or not owned by user");
+    let keyset = playlist_summaries(
+        vec![
+            "pl.is_public = TRUE".to_string(),
+            "EXISTS (SELECT 1 FROM playlist_follows f WHERE f.playlist_id = pl.id AND f.user_id = ?)".to_string(),
+        ],
+        vec![BindValue::Text(user_id.to_string())],
+        vec![SortKey::asc("pl.name"), SortKey::asc("pl.id")],
+    );
+
+    match load_page::<PlaylistSummary>(&mut conn, &keyset, &page) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
     }
+}
 
-    let affected = diesel::delete(ps_dsl::playlist_songs
-        .filter(ps_dsl::playlist_id.eq(playlist_id_param))
-        .filter(ps_dsl::song_id.eq(song_id_param)))
-        .execute(&mut conn);
+pub async fn follow_playlist(
+    pool: web::Data<DbPool>,
+    playlist_id_param: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let playlist_id = playlist_id_param.into_inner();
+
+    let playlist = match playlists_dsl::playlists.find(&playlist_id).first::<Playlist>(&mut conn).optional() {
+        Ok(Some(p)) if p.is_public.unwrap_or(false) => p,
+        // Private playlists cannot be followed, nor revealed
+        Ok(_) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if playlist.user_id == claims.sub {
+        return HttpResponse::BadRequest().body("You cannot follow your own playlist");
+    }
+
+    let follow = NewPlaylistFollow {
+        playlist_id,
+        user_id: claims.sub.clone(),
+    };
+    match diesel::insert_into(playlist_follows::table).values(&follow).execute(&mut conn) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        // Already followed
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn unfollow_playlist(
+    pool: web::Data<DbPool>,
+    playlist_id_param: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let playlist_id = playlist_id_param.into_inner();
+
+    let affected = diesel::delete(
+        playlist_follows::table
+            .filter(playlist_follows::playlist_id.eq(&playlist_id))
+            .filter(playlist_follows::user_id.eq(&claims.sub)),
+    )
+    .execute(&mut conn);
 
     match affected {
-        Ok(0) => HttpResponse::NotFound().body("Song not found"),
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(0) => HttpResponse::NotFound().body("You do not follow this playlist"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Copy a playlist the user can see into a new private playlist of theirs, songs in the same order.
+/// Copies of smart playlists keep the rules, now matched against the favorites and plays of the user.
+pub async fn copy_playlist(
+    pool: web::Data<DbPool>,
+    playlist_id_param: web::Path<String>,
+    payload: Option<web::Json<CopyPlaylistRequest>>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let playlist_id = playlist_id_param.into_inner();
+
+    let source = match playlists_dsl::playlists.find(&playlist_id).first::<Playlist>(&mut conn).optional() {
+        Ok(Some(p)) => p,
+        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match playlist_access(&mut conn, &source.id, &source.user_id, &claims.sub) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let name = match payload.and_then(|p| p.into_inner().name) {
+        Some(name) => name.trim().to_string(),
+        None => format!("{} (copy)", source.name),
+    };
+    if name.is_empty() {
+        return HttpResponse::BadRequest().body("Playlist name cannot be empty");
+    }
+    // playlists.name is VARCHAR(100)
+    let name: String = name.chars().take(100).collect();
+
+    let new_id = Uuid::new_v4().to_string();
+    let result = conn.transaction::<_, DieselError, _>(|conn| {
+        diesel::insert_into(playlists_dsl::playlists)
+            .values(&NewPlaylist {
+                id: new_id.clone(),
+                user_id: claims.sub.clone(),
+                name,
+                description: source.description.clone(),
+                is_public: Some(false),
+                kind: Some(source.kind.clone()),
+                rules: source.rules.clone(),
+            })
+            .execute(conn)?;
+
+        let entries: Vec<NewPlaylistSong> = song_order(conn, &source.id)?
+            .into_iter()
+            .enumerate()
+            .map(|(position, song_id)| NewPlaylistSong {
+                playlist_id: new_id.clone(),
+                song_id,
+                position: position as i32,
+                added_by: Some(claims.sub.clone()),
+            })
+            .collect();
+        if !entries.is_empty() {
+            diesel::insert_into(playlist_songs::table).values(&entries).execute(conn)?;
+        }
+
+        playlists_dsl::playlists.find(&new_id).first::<Playlist>(conn)
+    });
+
+    match result {
+        Ok(copy) => HttpResponse::Created().json(copy),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// --------------------- Import / Export ---------------------
+/// Most entries read from an imported playlist file
+const MAX_IMPORT_ENTRIES: usize = 1000;
+
+/// Name usable in a `Content-Disposition` header
+fn file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+        .collect();
+    match cleaned.trim() {
+        "" => "playlist".to_string(),
+        cleaned => cleaned.to_string(),
+    }
+}
+
+/// Download a playlist the user can see as M3U8, XSPF or JSPF. Tracks point at signed
+/// stream URLs, so other players can play them without logging in until they expire.
+pub async fn export_playlist(
+    pool: web::Data<DbPool>,
+    secret: web::Data<Vec<u8>>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ExportQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let (user_id_param, playlist_id_param) = path.into_inner();
+
+    let format = match PlaylistFormat::parse(query.format.as_deref().unwrap_or("m3u8")) {
+        Some(f) => f,
+        None => return HttpResponse::BadRequest().body("format must be m3u8, xspf or jspf"),
+    };
+
+    match playlist_access(&mut conn, &playlist_id_param, &user_id_param, &claims.sub) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().body("Playlist not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let playlist = match playlists_dsl::playlists.find(&playlist_id_param).first::<Playlist>(&mut conn) {
+        Ok(p) => p,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let listing = match ordered_listing(&playlist) {
+        Ok(l) => l,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let songs = match listing.load_all(&mut conn) {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let expires = chrono::Utc::now().timestamp() + export_stream_url_ttl_seconds();
+    let base_url = public_base_url();
+    let tracks: Vec<_> = songs
+        .into_iter()
+        .map(|song| {
+            let url = signed_stream_url(&base_url, &song.id, &claims.sub, expires, &secret);
+            (song, url)
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, format.content_type()))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", file_name(&playlist.name), format.extension()),
+        ))
+        .body(render_playlist(format, &playlist.name, &tracks))
+}
+
+/// Create a playlist from an M3U8, XSPF or JSPF file sent as the body. Entries are matched
+/// against the library by title, artist and duration; the report lists those left out.
+pub async fn import_playlist(
+    pool: web::Data<DbPool>,
+    user_id_param: web::Path<String>,
+    query: web::Query<ImportQuery>,
+    body: String,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let format = match query.format.as_deref() {
+        Some(format) => PlaylistFormat::parse(format),
+        None => PlaylistFormat::detect(&body),
+    };
+    let Some(format) = format else {
+        return HttpResponse::BadRequest().body("format must be m3u8, xspf or jspf");
+    };
+
+    let (title, entries) = match parse_playlist(format, &body) {
+        Ok(parsed) => parsed,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    if entries.is_empty() {
+        return HttpResponse::BadRequest().body("No entries found in the playlist file");
+    }
+    if entries.len() > MAX_IMPORT_ENTRIES {
+        return HttpResponse::BadRequest().body(format!("Playlists can be imported with at most {} entries", MAX_IMPORT_ENTRIES));
+    }
+
+    let name = query
+        .name
+        .as_deref()
+        .or(title.as_deref())
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .unwrap_or("Imported playlist");
+    // playlists.name is VARCHAR(100)
+    let name: String = name.chars().take(100).collect();
+
+    let mut song_ids: Vec<String> = Vec::new();
+    let mut matched = 0;
+    let mut unmatched = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        match match_song(&mut conn, entry) {
+            Ok(Some(song_id)) => {
+                matched += 1;
+                // A song can be in a playlist only once, the first occurrence keeps its place
+                if !song_ids.contains(&song_id) {
+                    song_ids.push(song_id);
+                }
+            }
+            Ok(None) => unmatched.push(UnmatchedEntry { index, entry: entry.clone() }),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    let new_id = Uuid::new_v4().to_string();
+    let result = conn.transaction::<_, DieselError, _>(|conn| {
+        diesel::insert_into(playlists_dsl::playlists)
+            .values(&NewPlaylist {
+                id: new_id.clone(),
+                user_id: user_id.to_string(),
+                name,
+                description: None,
+                is_public: Some(query.is_public.unwrap_or(false)),
+                kind: None,
+                rules: None,
+            })
+            .execute(conn)?;
+
+        let rows: Vec<NewPlaylistSong> = song_ids
+            .iter()
+            .enumerate()
+            .map(|(position, song_id)| NewPlaylistSong {
+                playlist_id: new_id.clone(),
+                song_id: song_id.clone(),
+                position: position as i32,
+                added_by: Some(user_id.to_string()),
+            })
+            .collect();
+        if !rows.is_empty() {
+            diesel::insert_into(playlist_songs::table).values(&rows).execute(conn)?;
+        }
+
+        playlists_dsl::playlists.find(&new_id).first::<Playlist>(conn)
+    });
+
+    match result {
+        Ok(playlist) => HttpResponse::Created().json(ImportReport {
+            playlist,
+            entries: entries.len(),
+            matched,
+            added: song_ids.len(),
+            unmatched,
+        }),
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
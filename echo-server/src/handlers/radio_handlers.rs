@@ -0,0 +1,177 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::models::radio_models::{
+    NewSkip, RadioPage, RadioQuery, RadioStation, RadioTrack, SkipRequest, SEED_ARTIST, SEED_GENRE, SEED_SONG,
+};
+use crate::models::token_models::Claims;
+use crate::schema::{radio_stations, skips};
+use crate::utils::auth_utils::is_admin;
+use crate::utils::radio_utils::{
+    encode_continuation, extend_station, load_tracks, next_position, parse_continuation, resolve_seed,
+    RADIO_STATION_DAYS,
+};
+
+const DEFAULT_RADIO_LIMIT: i64 = 10;
+const MAX_RADIO_LIMIT: i64 = 50;
+
+/// The station of a continuation token, if it belongs to the user
+fn find_station(conn: &mut MysqlConnection, station_id: &str, user_id: &str) -> QueryResult<Option<RadioStation>> {
+    radio_stations::table
+        .filter(radio_stations::id.eq(station_id))
+        .filter(radio_stations::user_id.eq(user_id))
+        .select(RadioStation::as_select())
+        .first::<RadioStation>(conn)
+        .optional()
+}
+
+/// Start a station from a seed song, artist or genre, or continue one.
+/// Pages are stored, so a continuation token always gives back the same tracks.
+pub async fn get_radio(
+    pool: web::Data<DbPool>,
+    query: web::Query<RadioQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_RADIO_LIMIT);
+    if !(1..=MAX_RADIO_LIMIT).contains(&limit) {
+        return HttpResponse::BadRequest().body("limit must be between 1 and 50");
+    }
+
+    let (station, position) = if let Some(token) = query.continuation.as_deref() {
+        let Some((station_id, position)) = parse_continuation(token) else {
+            return HttpResponse::BadRequest().body("Invalid continuation token");
+        };
+        let station = match find_station(&mut conn, &station_id, &claims.sub) {
+            Ok(Some(s)) => s,
+            Ok(None) => return HttpResponse::NotFound().body("Radio station not found"),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        match next_position(&mut conn, &station.id) {
+            // Tokens only ever point at generated tracks or right after them
+            Ok(next) if position > next => return HttpResponse::BadRequest().body("Invalid continuation token"),
+            Ok(_) => {}
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+        (station, position)
+    } else {
+        let seeds = [
+            (SEED_SONG, query.seed_song.clone()),
+            (SEED_ARTIST, query.seed_artist.clone()),
+            (SEED_GENRE, query.seed_genre.map(|g| g.to_string())),
+        ];
+        let mut given = seeds.into_iter().filter_map(|(kind, id)| id.map(|id| (kind, id)));
+        let (seed_type, seed_id) = match (given.next(), given.next()) {
+            (Some(seed), None) => seed,
+            _ => {
+                return HttpResponse::BadRequest()
+                    .body("Give exactly one of seed_song, seed_artist, seed_genre, or a continuation")
+            }
+        };
+
+        match resolve_seed(&mut conn, seed_type, &seed_id) {
+            Ok(_) => {}
+            Err(DieselError::NotFound) => return HttpResponse::NotFound().body("Seed not found"),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+
+        let now = Utc::now().naive_utc();
+        // Stations are cheap to recreate, old ones are dropped as new ones start
+        let _ = diesel::delete(
+            radio_stations::table
+                .filter(radio_stations::user_id.eq(&claims.sub))
+                .filter(radio_stations::created_at.lt(now - Duration::days(RADIO_STATION_DAYS))),
+        )
+        .execute(&mut conn);
+
+        let station = RadioStation {
+            id: Uuid::new_v4().to_string(),
+            user_id: claims.sub.clone(),
+            seed_type: seed_type.to_string(),
+            seed_id,
+            created_at: now,
+        };
+        if diesel::insert_into(radio_stations::table).values(&station).execute(&mut conn).is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+        (station, 0)
+    };
+
+    let end = position + limit as i32;
+    match conn.transaction(|conn| extend_station(conn, &station, end)) {
+        Ok(_) => {}
+        // Another request generated the same page first; serve theirs
+        Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+        Err(DieselError::NotFound) => return HttpResponse::NotFound().body("Seed not found"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match load_tracks(&mut conn, &station.id, position, end) {
+        Ok(tracks) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            let tracks: Vec<RadioTrack> = tracks
+                .into_iter()
+                .map(|mut track| {
+                    track.song = track.song.for_viewer(admin);
+                    track
+                })
+                .collect();
+            HttpResponse::Ok().json(RadioPage {
+                next: encode_continuation(&station.id, end),
+                station_id: station.id,
+                seed_type: station.seed_type,
+                seed_id: station.seed_id,
+                tracks,
+            })
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Record that the user skipped a song, so radio plays it and its artist less
+pub async fn report_skip(
+    pool: web::Data<DbPool>,
+    payload: web::Json<SkipRequest>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let request = payload.into_inner();
+    if let Some(station_id) = request.station_id.as_deref() {
+        match find_station(&mut conn, station_id, &claims.sub) {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::NotFound().body("Radio station not found"),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    let skip = NewSkip {
+        id: Uuid::new_v4().to_string(),
+        user_id: claims.sub.clone(),
+        song_id: request.song_id,
+        station_id: request.station_id,
+        skipped_at: Utc::now().naive_utc(),
+    };
+
+    match diesel::insert_into(skips::table).values(&skip).execute(&mut conn) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+            HttpResponse::NotFound().body("Song not found")
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,114 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use diesel::prelude::*;
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::models::recommendation_models::{Mix, Recommendation, RecommendationQuery};
+use crate::models::token_models::Claims;
+use crate::schema::songs;
+use crate::utils::auth_utils::{check_ownership, is_admin};
+use crate::utils::recommendation_utils::{mix_songs, similar_songs, top_genres, MixKind};
+
+const DEFAULT_RECOMMENDATION_LIMIT: i64 = 20;
+const MAX_RECOMMENDATION_LIMIT: i64 = 100;
+
+/// Genre mixes offered, for the user's favorite genres
+const GENRE_MIX_COUNT: i64 = 3;
+
+fn recommendation_limit(query: &RecommendationQuery) -> Result<i64, HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_RECOMMENDATION_LIMIT);
+    if !(1..=MAX_RECOMMENDATION_LIMIT).contains(&limit) {
+        return Err(HttpResponse::BadRequest().body("limit must be between 1 and 100"));
+    }
+    Ok(limit)
+}
+
+fn for_viewer(list: Vec<Recommendation>, admin: bool) -> Vec<Recommendation> {
+    list.into_iter()
+        .map(|mut r| {
+            r.song = r.song.for_viewer(admin);
+            r
+        })
+        .collect()
+}
+
+/// Songs related to a song, best match first
+pub async fn get_similar_songs(
+    pool: web::Data<DbPool>,
+    song_id_param: web::Path<String>,
+    query: web::Query<RecommendationQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let song_id: String = song_id_param.into_inner();
+    let limit = match recommendation_limit(&query) {
+        Ok(l) => l,
+        Err(resp) => return resp,
+    };
+
+    match songs::table.filter(songs::id.eq(&song_id)).count().get_result::<i64>(&mut conn) {
+        Ok(0) => return HttpResponse::NotFound().body("Song not found"),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match similar_songs(&mut conn, &song_id, limit) {
+        Ok(list) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(for_viewer(list, admin))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The user's personal mixes: one per favorite genre, then songs they have not heard yet.
+/// Mixes without songs are left out.
+pub async fn get_mixes(
+    pool: web::Data<DbPool>,
+    user_id_param: web::Path<String>,
+    query: web::Query<RecommendationQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let limit = match recommendation_limit(&query) {
+        Ok(l) => l,
+        Err(resp) => return resp,
+    };
+
+    let genres = match top_genres(&mut conn, user_id, GENRE_MIX_COUNT) {
+        Ok(g) => g,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let mut mixes: Vec<(String, String, MixKind)> = genres
+        .into_iter()
+        .map(|g| (format!("genre-{}", g.id), format!("{} Mix", g.name), MixKind::Genre(g.id)))
+        .collect();
+    mixes.push(("discover".to_string(), "Discover".to_string(), MixKind::Discover));
+
+    let admin = is_admin(&mut conn, &claims.sub);
+    let mut result: Vec<Mix> = Vec::new();
+    for (id, name, kind) in mixes {
+        match mix_songs(&mut conn, user_id, kind, limit) {
+            Ok(list) if list.is_empty() => {}
+            Ok(list) => result.push(Mix { id, name, songs: for_viewer(list, admin) }),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    HttpResponse::Ok().json(result)
+}
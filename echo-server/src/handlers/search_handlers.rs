@@ -0,0 +1,42 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::models::search_models::SearchQuery;
+use crate::models::token_models::Claims;
+use crate::utils::auth_utils::is_admin;
+use crate::utils::search_utils::search_all;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 5;
+const MAX_SEARCH_LIMIT: i64 = 50;
+
+/// Search songs, albums, artists and playlists at once
+pub async fn search(
+    pool: web::Data<DbPool>,
+    query: web::Query<SearchQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let q = query.q.trim();
+    if q.is_empty() {
+        return HttpResponse::BadRequest().body("q must not be empty");
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+        return HttpResponse::BadRequest().body("limit must be between 1 and 50");
+    }
+
+    match search_all(&mut conn, q, &claims.sub, limit) {
+        Ok(mut results) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            results.songs = results.songs.into_iter().map(|s| s.for_viewer(admin)).collect();
+            HttpResponse::Ok().json(results)
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,113 +1,79 @@
-use actix_web::{web, HttpResponse, Responder, ResponseError};
+use actix_web::web::ReqData;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
 use actix_multipart::Multipart;
 use diesel::prelude::*;
 use diesel::sql_types::Text;
+use actix_web::web::Bytes;
+use futures::stream::BoxStream;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use uuid::Uuid;
 
 use crate::db::DbPool;
 use crate::db::get_conn;
+use crate::handlers::genre_handlers::genre_exists;
 use crate::models::pagination_models::Pagination;
+use crate::models::rendition_models::{Rendition, RenditionFormat, RenditionProfile, RenditionQuery};
 use crate::models::song_models::SongQuery;
-use crate::models::song_models::{Song, SongResponse, NewSong, UpdateSong};
+use crate::models::stream_models::{SignedStreamQuery, StreamMode, StreamUrlResponse};
+use crate::models::token_models::Claims;
+use crate::models::song_models::{Song, SongResponse, UpdateSong, UploadMetadata};
+use crate::models::ingest_models::{IngestItem, IngestJobAccepted, NewIngestJob, JOB_QUEUED};
+use crate::schema::{ingest_jobs, song_renditions, transcode_cache};
 use crate::schema::songs::dsl::*;
-use crate::utils::audio_utils::normalize_song_async;
+use crate::storage::{content_type_for_key, ObjectStorage, StorageError};
+use crate::utils::auth_utils::is_admin;
+use crate::utils::audio_utils::LoudnessSettings;
+use crate::utils::catalog_utils::refresh_album_gain;
 use crate::utils::pagination_utils::validate_pagination;
+use crate::utils::play_utils::{client_name, PlayRecorder};
+use crate::utils::rendition_utils::{accepted_formats, accepts_audio, choose_rendition};
+use crate::utils::probe_utils::{duration_mismatch, probe_audio_async, AudioProbe};
+use crate::utils::song_query_utils::{song_listing, SongSource};
+use crate::utils::signing_utils::{public_base_url, signed_stream_url, stream_url_ttl_seconds, verify_stream_signature};
+use crate::utils::stream_utils::{proxy_object, storage_error_response};
+use crate::utils::transcode_utils::{transcode_stream, Transcoder, TRANSCODE_RETRY_AFTER_SECONDS};
+use crate::workers::ingest_worker::{discard_objects, IngestQueue};
 
 pub async fn list_songs(
     pool: web::Data<DbPool>,
     query: web::Query<SongQuery>,
+    claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
         Err(e) => return e.error_response(),
     };
 
-    // Build WHERE clause
-    let mut filters = Vec::new();
-
-    if let Some(ref name) = query.name {
-        filters.push(format!("LOWER(s.title) LIKE LOWER('%{}%')", name.replace("'", "''")));
-    }
-    if let Some(ref genre) = query.genre {
-        filters.push(format!("LOWER(g.name) LIKE LOWER('%{}%')", genre.replace("'", "''")));
-    }
-    if let Some(ref artist) = query.artist {
-        filters.push(format!("LOWER(a.name) LIKE LOWER('%{}%')", artist.replace("'", "''")));
-    }   
-
-    let where_clause = if filters.is_empty() {
-        "".to_string()
-    } else {
-        format!("WHERE {}", filters.join(" AND "))
-    };
-
-    // Sorting or Random
-    let mut order_clause = String::new();
-    if query.random.unwrap_or(false) {
-        order_clause = "ORDER BY RAND()".to_string(); 
-    } else if let Some(ref sort) = query.sort {
-        let (field, direction) = if sort.starts_with('-') {
-            (&sort[1..], "DESC")
-        } else {
-            (sort.as_str(), "ASC")
-        };
-
-        match field {
-            "release_date" => order_clause = format!("ORDER BY s.created_at {}", direction),
-            "name" => order_clause = format!("ORDER BY s.title {}", direction),
-            "artist" => order_clause = format!("ORDER BY a.name {}", direction),
-            _ => {}
-        }
-    }
-
     let pagination = Pagination {
         limit: query.limit,
         offset: query.offset,
+        cursor: query.cursor.clone(),
     };
-    match validate_pagination(&pagination) {
-        Ok(_) => {}
+    let page = match validate_pagination(&pagination) {
+        Ok(p) => p,
         Err(e) => return e.error_response(),
     };
 
-    let sql = format!(
-        r#"
-        SELECT
-            s.id,
-            s.title,
-            s.artist_id,
-            a.name AS artist_name,
-            s.album_id,
-            al.name AS album_name,
-            s.genre_id,
-            g.name AS genre_name,
-            s.duration_seconds,
-            s.object_url,
-            s.created_at,
-            s.updated_at
-        FROM songs s
-        JOIN artists a ON s.artist_id = a.id
-        LEFT JOIN albums al ON s.album_id = al.id
-        LEFT JOIN genres g ON s.genre_id = g.id
-        {where_clause}
-        {order_clause}
-        {pagination_clause}
-        "#,
-        where_clause = where_clause,
-        order_clause = order_clause,
-        pagination_clause = pagination.sql_clause().unwrap()
-    );
-
-    match diesel::sql_query(sql).load::<SongResponse>(&mut conn) {
-        Ok(list) => HttpResponse::Ok().json(list),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let listing = match song_listing(SongSource::All, &query) {
+        Ok(l) => l,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    match listing.load_page(&mut conn, &page) {
+        Ok(page) => {
+            let admin = is_admin(&mut conn, &claims.sub);
+            HttpResponse::Ok().json(page.map(|s| s.for_viewer(admin)))
+        },
+        Err(e) => e.error_response(),
     }
 }
 
 pub async fn get_song(
     pool: web::Data<DbPool>,
-    song_id_param: web::Path<String>
+    song_id_param: web::Path<String>,
+    claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
@@ -126,15 +92,20 @@ pub async fn get_song(
             al.name AS album_name,
             s.genre_id,
             g.name AS genre_name,
+            s.track_number,
             s.duration_seconds,
             s.object_url,
+            s.track_gain,
+            s.track_peak,
+            al.album_gain,
+            al.album_peak,
             s.created_at,
             s.updated_at
         FROM songs s
         JOIN artists a ON s.artist_id = a.id
         LEFT JOIN albums al ON s.album_id = al.id
         LEFT JOIN genres g ON s.genre_id = g.id
-        WHERE s.id = $1
+        WHERE s.id = ?
     "#;
 
     match diesel::sql_query(sql)
@@ -143,7 +114,8 @@ pub async fn get_song(
     {
         Ok(mut list) => {
             if let Some(song) = list.pop() {
-                HttpResponse::Ok().json(song)
+                let admin = is_admin(&mut conn, &claims.sub);
+                HttpResponse::Ok().json(song.for_viewer(admin))
             } else {
                 HttpResponse::NotFound().finish()
             }
@@ -152,9 +124,16 @@ pub async fn get_song(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_song(
+    req: HttpRequest,
     pool: web::Data<DbPool>,
-    song_id_param: web::Path<String>
+    storage: web::Data<dyn ObjectStorage>,
+    stream_mode: web::Data<StreamMode>,
+    transcoder: web::Data<Transcoder>,
+    song_id_param: web::Path<String>,
+    query: web::Query<RenditionQuery>,
+    claims: ReqData<Claims>,
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
@@ -165,29 +144,318 @@ pub async fn stream_song(
     let song_record = songs.filter(id.eq(song_id))
         .first::<crate::models::song_models::Song>(&mut conn);
 
-    match song_record {
-        Ok(song) => {
+    let song = match song_record {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    let requested_format = match query.format.as_deref() {
+        Some(f) => match RenditionFormat::parse(f) {
+            Some(format) => Some(format),
+            None => return HttpResponse::BadRequest().body(format!("Unsupported format: {}", f)),
+        },
+        None => None,
+    };
+    if query.max_bitrate.is_some_and(|b| b <= 0) {
+        return HttpResponse::BadRequest().body("maxBitrate must be positive");
+    }
+
+    let play = PlayRecorder {
+        pool: pool.get_ref().clone(),
+        user_id: claims.sub.clone(),
+        song_id: song.id.clone(),
+        client: request_client_name(&req),
+    };
+
+    let admin = is_admin(&mut conn, &claims.sub);
+    stream_song_response(&req, &mut conn, &storage, **stream_mode, admin, &transcoder, &song, requested_format, query.max_bitrate, Some(play)).await
+}
+
+/// Client name recorded with plays: `X-Client-Name` if sent, otherwise the user agent
+fn request_client_name(req: &HttpRequest) -> Option<String> {
+    let header_value = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    client_name(header_value(header::HeaderName::from_static("x-client-name")).or_else(|| header_value(header::USER_AGENT)))
+}
+
+/// Serve a song in the requested format and bitrate, from a stored rendition, the transcode
+/// cache or a live transcode. Also used by the Subsonic `stream` method.
+/// `play` is recorded when the audio is sent to the end; redirects cannot be followed, so
+/// clients streaming that way report their plays. Only admins, who can see object URLs
+/// anyway, are redirected; everyone else is proxied so storage URLs never leak.
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_song_response(
+    req: &HttpRequest,
+    conn: &mut MysqlConnection,
+    storage: &web::Data<dyn ObjectStorage>,
+    stream_mode: StreamMode,
+    admin: bool,
+    transcoder: &Transcoder,
+    song: &Song,
+    requested_format: Option<RenditionFormat>,
+    max_bitrate: Option<i32>,
+    play: Option<PlayRecorder>,
+) -> HttpResponse {
+    let original_key = match storage.key_for_url(&song.object_url) {
+        Some(k) => k,
+        None => return HttpResponse::InternalServerError().body("Song object is not managed by the configured storage"),
+    };
+
+    let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()).unwrap_or("*/*");
+
+    // Explicit query parameters pick a stored rendition, or a transcode when none fits.
+    // Otherwise the original is served unless the Accept header rules out its type.
+    let source = if requested_format.is_some() || max_bitrate.is_some() {
+        let renditions = match load_renditions(conn, &song.id) {
+            Ok(list) => list,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        match choose_rendition(&renditions, requested_format, max_bitrate) {
+            Some(r) => StreamSource::Stored(r.object_key.clone(), rendition_etag(r)),
+            None => StreamSource::Transcode(RenditionProfile::for_transcode(
+                requested_format.unwrap_or(RenditionFormat::Mp3),
+                max_bitrate,
+            )),
+        }
+    } else if accepts_audio(accept, content_type_for_key(&original_key)) {
+        StreamSource::Stored(original_key.clone(), song_etag(song))
+    } else {
+        let formats = accepted_formats(accept);
+        if formats.is_empty() {
+            return HttpResponse::NotAcceptable().body("No supported audio format in the Accept header");
+        }
+        let renditions = match load_renditions(conn, &song.id) {
+            Ok(list) => list,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        match formats.iter().find_map(|format| choose_rendition(&renditions, Some(*format), None)) {
+            Some(r) => StreamSource::Stored(r.object_key.clone(), rendition_etag(r)),
+            None => StreamSource::Transcode(RenditionProfile::for_transcode(formats[0], None)),
+        }
+    };
+
+    let (key, etag) = match source {
+        StreamSource::Stored(key, etag) => (key, etag),
+        StreamSource::Transcode(profile) => {
+            // A previous transcode of this profile is served like a stored rendition
+            let cache_key = profile.cache_key(&song.id);
+            match storage.head(&cache_key).await {
+                Ok(meta) => {
+                    let etag = meta.etag.unwrap_or_else(|| format!("\"{}\"", cache_key));
+                    (cache_key, etag)
+                }
+                Err(StorageError::NotFound) => {
+                    // Recorded before the object exists, so deleting the song finds it
+                    // whichever backend it is on
+                    let tracked = diesel::insert_or_ignore_into(transcode_cache::table)
+                        .values((transcode_cache::object_key.eq(&cache_key), transcode_cache::song_id.eq(&song.id)))
+                        .execute(conn);
+                    if tracked.is_err() {
+                        return HttpResponse::InternalServerError().finish();
+                    }
+                    let mut response = transcode_live(storage, transcoder, &original_key, profile, cache_key, play).await;
+                    response.headers_mut().insert(header::VARY, header::HeaderValue::from_static("Accept"));
+                    return response;
+                }
+                Err(e) => return storage_error_response(e),
+            }
+        }
+    };
+
+    // Backends without client-reachable URLs (e.g. local disk) are always proxied
+    let redirect = if stream_mode == StreamMode::Redirect && admin { storage.public_url(&key) } else { None };
+    let mut response = match redirect {
+        Some(url) => {
             HttpResponse::Found() // 302 redirect
-                .append_header(("Location", song.object_url)) 
+                .append_header(("Location", url))
                 .finish()
-        },
-        Err(_) => HttpResponse::NotFound().finish(),
+        }
+        _ => proxy_object(req, storage.get_ref(), &key, etag, play).await,
+    };
+    response.headers_mut().insert(header::VARY, header::HeaderValue::from_static("Accept"));
+    response
+}
+
+/// Where `stream_song_response` gets the audio from
+enum StreamSource {
+    /// An object in storage, with the ETag to use if the backend provides none
+    Stored(String, String),
+    /// An on-the-fly transcode of the original
+    Transcode(RenditionProfile),
+}
+
+/// Transcode the original while streaming the output. Without Content-Length the response is
+/// sent chunked and cannot honor Range; once cached, later requests can.
+async fn transcode_live(
+    storage: &web::Data<dyn ObjectStorage>,
+    transcoder: &Transcoder,
+    original_key: &str,
+    profile: RenditionProfile,
+    cache_key: String,
+    play: Option<PlayRecorder>,
+) -> HttpResponse {
+    let permit = match transcoder.try_acquire() {
+        Some(p) => p,
+        None => {
+            return HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, TRANSCODE_RETRY_AFTER_SECONDS.to_string()))
+                .body("Too many transcodes in progress, retry later");
+        }
+    };
+
+    let source = match storage.get(original_key, None).await {
+        Ok(body) => body.stream,
+        Err(e) => return storage_error_response(e),
+    };
+
+    let content_type = content_type_for_key(&cache_key);
+    let stream = match transcode_stream(source, profile, storage.clone().into_inner(), cache_key, permit) {
+        Ok(stream) => stream,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Failed to start transcoding: {}", e)),
+    };
+    let stream: BoxStream<'static, Result<Bytes, std::io::Error>> = match play {
+        Some(recorder) => Box::pin(recorder.on_completion(stream)),
+        None => Box::pin(stream),
+    };
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((header::ACCEPT_RANGES, "none"))
+        .insert_header((header::CACHE_CONTROL, "private, no-transform"))
+        .streaming(stream)
+}
+
+/// Available renditions of a song, e.g. for clients to pick a format and bitrate
+pub async fn list_song_renditions(
+    pool: web::Data<DbPool>,
+    song_id_param: web::Path<String>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let song_id = song_id_param.into_inner();
+
+    match songs.filter(id.eq(&song_id)).count().get_result::<i64>(&mut conn) {
+        Ok(0) => return HttpResponse::NotFound().finish(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match load_renditions(&mut conn, &song_id) {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+fn load_renditions(conn: &mut MysqlConnection, song: &str) -> QueryResult<Vec<Rendition>> {
+    song_renditions::table
+        .filter(song_renditions::song_id.eq(song))
+        .order((song_renditions::format.asc(), song_renditions::bitrate_kbps.desc()))
+        .select(Rendition::as_select())
+        .load::<Rendition>(conn)
+}
+
+/// Renditions are never rewritten, so their id is a strong validator
+fn rendition_etag(rendition: &Rendition) -> String {
+    format!("\"{}\"", rendition.id)
+}
+
+/// Issue a short-lived signed URL for streaming a song without a bearer token,
+/// e.g. from an `<audio>` element or a cast device.
+pub async fn get_stream_url(
+    pool: web::Data<DbPool>,
+    secret: web::Data<Vec<u8>>,
+    song_id_param: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let song_id = song_id_param.into_inner();
+
+    match songs.filter(id.eq(&song_id)).count().get_result::<i64>(&mut conn) {
+        Ok(0) => return HttpResponse::NotFound().finish(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     }
+
+    let expires = chrono::Utc::now() + chrono::Duration::seconds(stream_url_ttl_seconds());
+    let url = signed_stream_url(&public_base_url(), &song_id, &claims.sub, expires.timestamp(), &secret);
+
+    HttpResponse::Ok().json(StreamUrlResponse {
+        url,
+        expires_at: expires.naive_utc(),
+    })
+}
+
+/// Serve a song from a signed URL. The route is exempt from session auth: the HMAC
+/// signature is the authorization. Audio is always proxied so the storage URL stays private.
+pub async fn stream_signed_song(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    secret: web::Data<Vec<u8>>,
+    song_id_param: web::Path<String>,
+    query: web::Query<SignedStreamQuery>,
+) -> impl Responder {
+    let song_id = song_id_param.into_inner();
+
+    if !verify_stream_signature(&song_id, &query.uid, query.exp, &query.sig, &secret) {
+        return HttpResponse::Forbidden().body("Invalid or expired stream URL");
+    }
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let song = match songs.filter(id.eq(&song_id)).first::<Song>(&mut conn) {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    match storage.key_for_url(&song.object_url) {
+        Some(key) => {
+            let play = PlayRecorder {
+                pool: pool.get_ref().clone(),
+                user_id: query.uid.clone(),
+                song_id: song.id.clone(),
+                client: request_client_name(&req),
+            };
+            proxy_object(&req, storage.get_ref(), &key, song_etag(&song), Some(play)).await
+        }
+        None => HttpResponse::InternalServerError().body("Song object is not managed by the configured storage"),
+    }
+}
+
+/// Strong ETag used when the object store does not provide one
+fn song_etag(song: &Song) -> String {
+    format!(
+        "\"{}-{}\"",
+        song.id,
+        song.updated_at.map(|t| t.and_utc().timestamp()).unwrap_or(0)
+    )
 }
 
 pub async fn create_one_or_more_songs(
     pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    queue: web::Data<IngestQueue>,
+    claims: ReqData<Claims>,
     mut payload: Multipart,
 ) -> impl Responder {
     // Temp storage for each song
     struct SongData {
         file: Vec<u8>,
-        metadata: NewSong,
+        metadata: UploadMetadata,
     }
     let mut songs_batch: Vec<SongData> = Vec::new();
 
     let mut current_file: Option<Vec<u8>> = None;
-    let mut current_meta: Option<NewSong> = None;
+    let mut current_meta: Option<UploadMetadata> = None;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
         let name = field.content_disposition().unwrap().get_name().unwrap();
@@ -215,7 +483,7 @@ pub async fn create_one_or_more_songs(
                     }
                 }
             }
-            let meta_result: Result<NewSong, _> = serde_json::from_slice(&bytes);
+            let meta_result: Result<UploadMetadata, _> = serde_json::from_slice(&bytes);
             match meta_result {
                 Ok(meta) => current_meta = Some(meta),
                 Err(e) => {
@@ -241,70 +509,138 @@ pub async fn create_one_or_more_songs(
             .body("You must upload between 1 and 10 songs per request");
     }
 
-    let write_base: String = std::env::var("OBJECT_STORAGE_WRITE_BASE_URL")
-        .expect("OBJECT_STORAGE_WRITE_BASE_URL not set");
-    let read_base: String = std::env::var("OBJECT_STORAGE_READ_BASE_URL")
-        .expect("OBJECT_STORAGE_READ_BASE_URL not set");
-
     let mut conn = match get_conn(&pool) {
         Ok(c) => c,
         Err(e) => return e.error_response(),
     };
 
-    // Process each song sequentially (or in parallel with join_all)
-    // Return inserted IDs (Vec<String>) which are serializable by serde
-    let mut results: Vec<String> = Vec::new();
-    for mut song in songs_batch {
-        song.metadata.id = Uuid::new_v4().to_string();
+    // Reject unknown genres before doing any audio work
+    for song in &songs_batch {
+        if let Some(gid) = song.metadata.genre_id {
+            match genre_exists(&mut conn, gid) {
+                Ok(true) => {}
+                Ok(false) => return HttpResponse::BadRequest().body(format!("Invalid genre_id: {} does not exist", gid)),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            }
+        }
+    }
 
-        // Normalize
-        let normalized_file = match normalize_song_async(&song.file).await {
-            Ok(f) => f,
+    // Fill in missing metadata from the files themselves. Every item is checked before
+    // anything is written, so a bad item rejects the whole batch.
+    let mut probes: Vec<AudioProbe> = Vec::new();
+    for (index, song) in songs_batch.iter_mut().enumerate() {
+        let probe = match probe_audio_async(&song.file).await {
+            Ok(p) => p,
             Err(e) => {
-                return HttpResponse::InternalServerError().body(format!("Audio normalization error: {}", e));
+                return HttpResponse::UnprocessableEntity()
+                    .body(format!("Song {}: unreadable audio file: {}", index, e));
             }
         };
 
-        // Upload
-        let object_name = format!("{}.mp3", song.metadata.id);
-        let upload_url = format!("{}/{}", write_base, object_name);
-        let client = reqwest::Client::new();
-        let res = match client
-            .put(&upload_url)
-            .header("Content-Type", "audio/mpeg")
-            .body(normalized_file)
-            .send()
-            .await
-        {
-            Ok(r) => r,
-            Err(_) => {
-                return HttpResponse::InternalServerError().body("Failed to upload");
+        let meta = &mut song.metadata;
+        meta.duration_seconds = match (meta.duration_seconds, probe.duration_seconds) {
+            (Some(stated), Some(probed)) if duration_mismatch(stated, probed) => {
+                return HttpResponse::UnprocessableEntity().body(format!(
+                    "Song {}: stated duration of {}s does not match the file's {}s",
+                    index, stated, probed
+                ));
             }
+            (Some(stated), _) => Some(stated),
+            (None, probed) => probed,
         };
-
-        if !res.status().is_success() {
-            return HttpResponse::InternalServerError().body("Failed to upload to Object Storage");
+        if meta.duration_seconds.is_none() {
+            return HttpResponse::BadRequest()
+                .body(format!("Song {}: duration could not be read from the file, duration_seconds is required", index));
         }
+        if non_blank(&meta.title).or_else(|| probe.title.clone()).is_none() {
+            return HttpResponse::BadRequest()
+                .body(format!("Song {}: title is missing from both metadata and file tags", index));
+        }
+        if meta.artist_id.is_none() && non_blank(&meta.artist).or_else(|| probe.artist.clone()).is_none() {
+            return HttpResponse::BadRequest()
+                .body(format!("Song {}: artist is missing from both metadata and file tags", index));
+        }
+
+        probes.push(probe);
+    }
 
-        song.metadata.object_url = format!("{}/{}", read_base, object_name);
+    // Stage the raw uploads; the ingest workers normalize and publish them later
+    let job_id = Uuid::new_v4().to_string();
+    let mut items: Vec<IngestItem> = Vec::new();
+    let mut staged_keys: Vec<String> = Vec::new();
 
-        // Insert DB
-        if let Err(_) = diesel::insert_into(songs)
-            .values(&song.metadata)
-            .execute(&mut conn)
-        {
-            return HttpResponse::InternalServerError().finish();
+    for (index, (song, probe)) in songs_batch.into_iter().zip(probes).enumerate() {
+        let staging_key = format!("ingest/{}/{}", job_id, index);
+        if let Err(e) = storage.put(&staging_key, song.file, "application/octet-stream").await {
+            discard_objects(storage.as_ref(), &staged_keys).await;
+            return HttpResponse::BadGateway().body(format!("Failed to stage upload: {}", e));
         }
+        staged_keys.push(staging_key.clone());
 
-        // Collect result as the new song ID
-        results.push(song.metadata.id.clone());
+        items.push(ingest_item(staging_key, &song.metadata, &probe));
     }
 
-    HttpResponse::Created().json(results)
+    let new_job = NewIngestJob {
+        id: job_id.clone(),
+        user_id: claims.sub.clone(),
+        status: JOB_QUEUED.to_string(),
+        total_items: items.len() as i32,
+        items_json: match serde_json::to_string(&items) {
+            Ok(json) => json,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    if let Err(e) = diesel::insert_into(ingest_jobs::table)
+        .values(&new_job)
+        .execute(&mut conn)
+    {
+        discard_objects(storage.as_ref(), &staged_keys).await;
+        return HttpResponse::InternalServerError().body(format!("Failed to queue ingest job: {}", e));
+    }
+
+    queue.wake();
+
+    let status_url = format!("/api/ingest/jobs/{}", job_id);
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, status_url.clone()))
+        .json(IngestJobAccepted {
+            job_id,
+            status: JOB_QUEUED.to_string(),
+            status_url,
+        })
+}
+
+/// Merge explicit upload metadata with the probed tags into the item to queue.
+/// Explicit values win; names are only resolved to catalog rows by the ingest worker.
+fn ingest_item(staging_key: String, meta: &UploadMetadata, probe: &AudioProbe) -> IngestItem {
+    let artist_id = meta.artist_id.clone();
+    let album_id = meta.album_id.clone();
+    let genre_id = meta.genre_id;
+
+    IngestItem {
+        staging_key,
+        extension: probe.extension.map(str::to_string),
+        title: non_blank(&meta.title).or_else(|| probe.title.clone()).unwrap_or_default(),
+        artist: artist_id.is_none().then(|| non_blank(&meta.artist).or_else(|| probe.artist.clone())).flatten(),
+        artist_id,
+        album: album_id.is_none().then(|| non_blank(&meta.album).or_else(|| probe.album.clone())).flatten(),
+        album_id,
+        year: meta.year.or(probe.year),
+        genre: genre_id.is_none().then(|| non_blank(&meta.genre).or_else(|| probe.genre.clone())).flatten(),
+        genre_id,
+        track_number: meta.track_number.or(probe.track_number),
+        duration_seconds: meta.duration_seconds.unwrap_or_default(),
+    }
+}
+
+fn non_blank(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
 }
 
 pub async fn update_song(
     pool: web::Data<DbPool>,
+    loudness: web::Data<LoudnessSettings>,
     song_id_param: web::Path<String>,
     payload: web::Json<UpdateSong>
 ) -> impl Responder {
@@ -315,12 +651,35 @@ pub async fn update_song(
 
     let song_id_param = song_id_param.into_inner();
 
+    if let Some(gid) = payload.genre_id {
+        match genre_exists(&mut conn, gid) {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::BadRequest().body(format!("Invalid genre_id: {} does not exist", gid)),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    // Moving a song changes the album gain of both its old and new album
+    let previous_album: Option<String> = match payload.album_id {
+        Some(_) => songs
+            .filter(id.eq(&song_id_param))
+            .select(album_id)
+            .first::<Option<String>>(&mut conn)
+            .ok()
+            .flatten(),
+        None => None,
+    };
+
     let updated = diesel::update(songs.filter(id.eq(song_id_param.clone())))
         .set(&*payload)
         .execute(&mut conn);
 
     match updated {
         Ok(_) => {
+            for album in previous_album.iter().chain(payload.album_id.iter()) {
+                let _ = refresh_album_gain(&mut conn, album, loudness.target_lufs);
+            }
+
             match songs.filter(id.eq(song_id_param)).first::<Song>(&mut conn) {
                 Ok(updated_song) => HttpResponse::Ok().json(SongResponse::from(updated_song)),
                 Err(_) => HttpResponse::Ok().finish(),
@@ -332,6 +691,8 @@ pub async fn update_song(
 
 pub async fn delete_song(
     pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    loudness: web::Data<LoudnessSettings>,
     song_id_param: web::Path<String>
 ) -> impl Responder {
     let mut conn = match get_conn(&pool) {
@@ -349,24 +710,42 @@ pub async fn delete_song(
         Err(_) => return HttpResponse::NotFound().finish(),
     };
 
-    // Delete object from Object Storage via signed URL
-    let client = reqwest::Client::new();
-    let res = client
-        .delete(&song_record.object_url)
-        .send()
-        .await;
+    // Delete object from Object Storage; an already missing object is not an error
+    if let Some(key) = storage.key_for_url(&song_record.object_url) {
+        match storage.delete(&key).await {
+            Ok(()) | Err(StorageError::NotFound) => {}
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Failed to delete object from storage: {}", e));
+            }
+        }
+    }
 
-    if res.is_err() || !res.as_ref().unwrap().status().is_success() {
-        return HttpResponse::InternalServerError()
-            .body("Failed to delete object from storage");
+    // Renditions rows go with the song (ON DELETE CASCADE), their objects are removed here
+    if let Ok(renditions) = load_renditions(&mut conn, &song_id) {
+        let keys: Vec<String> = renditions.into_iter().map(|r| r.object_key).collect();
+        discard_objects(storage.as_ref(), &keys).await;
     }
-    
+    // Cached transcodes likewise, from the keys recorded when they were made
+    if let Ok(keys) = transcode_cache::table
+        .filter(transcode_cache::song_id.eq(&song_id))
+        .select(transcode_cache::object_key)
+        .load::<String>(&mut conn)
+    {
+        discard_objects(storage.as_ref(), &keys).await;
+    }
+
     // Delete DB record
     let deleted = diesel::delete(songs.filter(id.eq(song_id)))
         .execute(&mut conn);
 
     match deleted {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(_) => {
+            if let Some(ref album) = song_record.album_id {
+                let _ = refresh_album_gain(&mut conn, album, loudness.target_lufs);
+            }
+            HttpResponse::Ok().finish()
+        }
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
@@ -0,0 +1,230 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::models::stats_models::{StatsQuery, TopKind, YearlyStatsQuery, YearlySummary};
+use crate::models::token_models::Claims;
+use crate::utils::auth_utils::check_ownership;
+use crate::utils::stats_utils::{
+    busiest_day, listening_by, listening_summary, top_items, BucketUnit, StatsCache, StatsWindow,
+};
+
+/// Days covered when `from` is not given
+const DEFAULT_STATS_DAYS: i64 = 30;
+
+const DEFAULT_TOP_LIMIT: i64 = 10;
+const MAX_TOP_LIMIT: i64 = 100;
+
+/// Entries per ranking in the yearly summary
+const YEARLY_TOP_LIMIT: i64 = 5;
+/// Years a yearly summary can be asked for
+const MIN_YEAR: i32 = 1;
+const MAX_YEAR: i32 = 9999;
+
+/// Offsets of real time zones, in minutes (UTC-12:00 to UTC+14:00)
+const MIN_TZ_OFFSET: i32 = -720;
+const MAX_TZ_OFFSET: i32 = 840;
+
+fn tz_offset(value: Option<i32>) -> Result<i32, HttpResponse> {
+    let offset = value.unwrap_or(0);
+    if !(MIN_TZ_OFFSET..=MAX_TZ_OFFSET).contains(&offset) {
+        return Err(HttpResponse::BadRequest().body("tz_offset must be between -720 and 840 minutes"));
+    }
+    Ok(offset)
+}
+
+/// Window of the query; the last 30 days up to today (in the user's time zone) by default
+fn stats_window(user_id: &str, query: &StatsQuery) -> Result<StatsWindow, HttpResponse> {
+    let offset = tz_offset(query.tz_offset)?;
+    let today = (Utc::now().naive_utc() + Duration::minutes(i64::from(offset))).date();
+    let to = query.to.unwrap_or(today);
+    let from = match query.from {
+        Some(from) => from,
+        None => to
+            .checked_sub_signed(Duration::days(DEFAULT_STATS_DAYS - 1))
+            .ok_or_else(|| HttpResponse::BadRequest().body("to is out of range"))?,
+    };
+    if from > to {
+        return Err(HttpResponse::BadRequest().body("from must not be after to"));
+    }
+    StatsWindow::for_days(user_id, from, to, offset)
+        .ok_or_else(|| HttpResponse::BadRequest().body("from and to are out of range"))
+}
+
+fn top_limit(query: &StatsQuery) -> Result<i64, HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_TOP_LIMIT);
+    if !(1..=MAX_TOP_LIMIT).contains(&limit) {
+        return Err(HttpResponse::BadRequest().body("limit must be between 1 and 100"));
+    }
+    Ok(limit)
+}
+
+/// Serve a statistic from the cache when the window is long enough to be worth caching,
+/// otherwise compute it (and cache it)
+fn cached_stat<T, F>(pool: &DbPool, cache: &StatsCache, window: &StatsWindow, statistic: &str, compute: F) -> HttpResponse
+where
+    T: Serialize,
+    F: FnOnce(&mut MysqlConnection) -> QueryResult<T>,
+{
+    let key = window.cache_key(statistic);
+    if window.is_expensive()
+        && let Some(value) = cache.get(&key)
+    {
+        return HttpResponse::Ok().json(value);
+    }
+
+    let mut conn = match get_conn(pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    match compute(&mut conn).map(serde_json::to_value) {
+        Ok(Ok(value)) => {
+            if window.is_expensive() {
+                cache.put(key, value.clone());
+            }
+            HttpResponse::Ok().json(value)
+        }
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Plays, listening time and variety over the window
+pub async fn get_stats_summary(
+    pool: web::Data<DbPool>,
+    cache: web::Data<StatsCache>,
+    user_id_param: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let window = match stats_window(user_id, &query) {
+        Ok(w) => w,
+        Err(resp) => return resp,
+    };
+
+    cached_stat(&pool, &cache, &window, "summary", |conn| listening_summary(conn, &window))
+}
+
+/// Most played songs, artists, albums or genres over the window
+pub async fn get_top(
+    pool: web::Data<DbPool>,
+    cache: web::Data<StatsCache>,
+    path: web::Path<(String, String)>,
+    query: web::Query<StatsQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let (user_id, kind) = path.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let Some(kind) = TopKind::parse(&kind) else {
+        return HttpResponse::BadRequest().body("kind must be one of songs, artists, albums, genres");
+    };
+    let window = match stats_window(user_id, &query) {
+        Ok(w) => w,
+        Err(resp) => return resp,
+    };
+    let limit = match top_limit(&query) {
+        Ok(l) => l,
+        Err(resp) => return resp,
+    };
+
+    let statistic = format!("top-{}-{}", kind.name(), limit);
+    cached_stat(&pool, &cache, &window, &statistic, |conn| top_items(conn, &window, kind, limit))
+}
+
+/// Plays per hour of the day (0-23), in the user's time zone
+pub async fn get_listening_by_hour(
+    pool: web::Data<DbPool>,
+    cache: web::Data<StatsCache>,
+    user_id_param: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let window = match stats_window(user_id, &query) {
+        Ok(w) => w,
+        Err(resp) => return resp,
+    };
+
+    cached_stat(&pool, &cache, &window, "hours", |conn| listening_by(conn, &window, BucketUnit::HourOfDay))
+}
+
+/// Plays per day of the week (0 = Monday), in the user's time zone
+pub async fn get_listening_by_weekday(
+    pool: web::Data<DbPool>,
+    cache: web::Data<StatsCache>,
+    user_id_param: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let user_id: String = user_id_param.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let window = match stats_window(user_id, &query) {
+        Ok(w) => w,
+        Err(resp) => return resp,
+    };
+
+    cached_stat(&pool, &cache, &window, "weekdays", |conn| listening_by(conn, &window, BucketUnit::DayOfWeek))
+}
+
+/// A year of listening in one document
+pub async fn get_yearly_summary(
+    pool: web::Data<DbPool>,
+    cache: web::Data<StatsCache>,
+    path: web::Path<(String, i32)>,
+    query: web::Query<YearlyStatsQuery>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let (user_id, year) = path.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let offset = match tz_offset(query.tz_offset) {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+    if !(MIN_YEAR..=MAX_YEAR).contains(&year) {
+        return HttpResponse::BadRequest().body("year must be between 1 and 9999");
+    }
+    let window = match NaiveDate::from_ymd_opt(year, 1, 1)
+        .zip(NaiveDate::from_ymd_opt(year, 12, 31))
+        .and_then(|(from, to)| StatsWindow::for_days(user_id, from, to, offset))
+    {
+        Some(w) => w,
+        None => return HttpResponse::BadRequest().body("Invalid year"),
+    };
+
+    cached_stat(&pool, &cache, &window, "year", |conn| {
+        Ok(YearlySummary {
+            year,
+            summary: listening_summary(conn, &window)?,
+            top_songs: top_items(conn, &window, TopKind::Songs, YEARLY_TOP_LIMIT)?,
+            top_artists: top_items(conn, &window, TopKind::Artists, YEARLY_TOP_LIMIT)?,
+            top_albums: top_items(conn, &window, TopKind::Albums, YEARLY_TOP_LIMIT)?,
+            top_genres: top_items(conn, &window, TopKind::Genres, YEARLY_TOP_LIMIT)?,
+            by_month: listening_by(conn, &window, BucketUnit::Month)?,
+            by_hour: listening_by(conn, &window, BucketUnit::HourOfDay)?,
+            by_weekday: listening_by(conn, &window, BucketUnit::DayOfWeek)?,
+            busiest_day: busiest_day(conn, &window)?,
+        })
+    })
+}
@@ -0,0 +1,672 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::{BigInt, Text};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::db::get_conn;
+use crate::handlers::song_handlers::stream_song_response;
+use crate::models::favorite_models::NewFavorite;
+use crate::models::play_models::{NewPlay, PLAY_SOURCE_REPORT};
+use crate::models::playlist_models::{NewPlaylist, NewPlaylistSong, KIND_SMART};
+use crate::models::rendition_models::RenditionFormat;
+use crate::models::song_models::{Song, SongResponse};
+use crate::models::stream_models::StreamMode;
+use crate::models::subsonic_models::{
+    ArtistIndex, ArtistsId3, License, MusicFolder, MusicFolders, Playlists, ReplayGain, SubsonicAlbum,
+    SubsonicAlbumRow, SubsonicArtist, SubsonicArtistRow, SubsonicError, SubsonicFormat, SubsonicParams,
+    SubsonicPlaylist, SubsonicPlaylistRow, SubsonicSearchResult, SubsonicSong, SUBSONIC_ERROR_GENERIC,
+    SUBSONIC_ERROR_NOT_AUTHORIZED,
+};
+use crate::schema::{favorites, playlist_songs, playlists, songs};
+use crate::storage::{content_type_for_key, ObjectStorage};
+use crate::utils::auth_utils::is_admin;
+use crate::utils::play_utils::{clear_now_playing, client_name, record_play, set_now_playing, PlayRecorder};
+use crate::utils::playlist_utils::{playlist_access, smart_listing, stored_rules};
+use crate::utils::subsonic_utils::{authenticate, subsonic_response, subsonic_timestamp};
+use crate::utils::song_query_utils::contains_pattern;
+use crate::utils::transcode_utils::Transcoder;
+
+/// Payload of a successful call: the element name and its content, or nothing (e.g. `ping`)
+type SubsonicResult = Result<Option<(&'static str, Value)>, SubsonicError>;
+
+/// Default and maximum number of results per kind in `search3`
+const SEARCH_DEFAULT_COUNT: i64 = 20;
+const SEARCH_MAX_COUNT: i64 = 500;
+
+const SONG_SELECT: &str = r#"
+    SELECT
+        s.id, s.title, s.artist_id, a.name AS artist_name,
+        s.album_id, al.name AS album_name, s.genre_id, g.name AS genre_name,
+        s.track_number, s.duration_seconds, s.object_url,
+        s.track_gain, s.track_peak, al.album_gain, al.album_peak,
+        s.created_at, s.updated_at
+    FROM songs s
+    JOIN artists a ON s.artist_id = a.id
+    LEFT JOIN albums al ON s.album_id = al.id
+    LEFT JOIN genres g ON s.genre_id = g.id
+"#;
+
+const ALBUM_SELECT: &str = r#"
+    SELECT
+        al.id, al.name, al.artist_id, a.name AS artist_name, al.release_year,
+        COUNT(s.id) AS song_count,
+        CAST(COALESCE(SUM(s.duration_seconds), 0) AS SIGNED) AS duration,
+        al.created_at
+    FROM albums al
+    JOIN artists a ON al.artist_id = a.id
+    LEFT JOIN songs s ON s.album_id = al.id
+"#;
+
+const ARTIST_SELECT: &str = r#"
+    SELECT a.id, a.name, COUNT(al.id) AS album_count
+    FROM artists a
+    LEFT JOIN albums al ON al.artist_id = a.id
+"#;
+
+const PLAYLIST_SELECT: &str = r#"
+    SELECT
+        p.id, p.name, p.description, p.is_public, p.user_id, u.username AS owner,
+        COUNT(s.id) AS song_count,
+        CAST(COALESCE(SUM(s.duration_seconds), 0) AS SIGNED) AS duration,
+        p.created_at, p.updated_at
+    FROM playlists p
+    JOIN users u ON p.user_id = u.id
+    LEFT JOIN playlist_songs ps ON ps.playlist_id = p.id
+    LEFT JOIN songs s ON ps.song_id = s.id
+"#;
+
+/// Entry point of the Subsonic-compatible API, `/rest/{method}` (with or without `.view`).
+/// The route is exempt from session auth: every call carries Subsonic credentials instead.
+#[allow(clippy::too_many_arguments)]
+pub async fn subsonic(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    storage: web::Data<dyn ObjectStorage>,
+    stream_mode: web::Data<StreamMode>,
+    transcoder: web::Data<Transcoder>,
+    method_param: web::Path<String>,
+    query: web::Query<Vec<(String, String)>>,
+    form: Option<web::Form<Vec<(String, String)>>>,
+) -> HttpResponse {
+    // Clients may send parameters in the query string, as a form body, or both
+    let mut pairs = query.into_inner();
+    if let Some(form) = form {
+        pairs.extend(form.into_inner());
+    }
+    let params = SubsonicParams::new(pairs);
+    let format = SubsonicFormat::from_params(&params);
+
+    let method = method_param.into_inner();
+    let method = method.strip_suffix(".view").unwrap_or(&method);
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(_) => return subsonic_response(&format, Err(SubsonicError::database())),
+    };
+
+    let user_id = match authenticate(&mut conn, &params) {
+        Ok(id) => id,
+        Err(e) => return subsonic_response(&format, Err(e)),
+    };
+
+    let result = match method {
+        "ping" => Ok(None),
+        "getLicense" => payload("license", License { valid: true }),
+        "getMusicFolders" => payload("musicFolders", MusicFolders {
+            music_folder: vec![MusicFolder { id: 1, name: "Music".to_string() }],
+        }),
+        "getArtists" => get_artists(&mut conn),
+        "getAlbum" => get_album(&mut conn, storage.get_ref(), &user_id, &params),
+        "getSong" => get_song(&mut conn, storage.get_ref(), &user_id, &params),
+        "search3" => search3(&mut conn, storage.get_ref(), &user_id, &params),
+        "getPlaylists" => get_playlists(&mut conn, &user_id),
+        "getPlaylist" => params
+            .require("id")
+            .and_then(|playlist_id| get_playlist(&mut conn, storage.get_ref(), &user_id, playlist_id)),
+        "createPlaylist" => create_playlist(&mut conn, storage.get_ref(), &user_id, &params),
+        "scrobble" => scrobble(&mut conn, &user_id, &params),
+        "star" => star(&mut conn, &user_id, &params),
+        "unstar" => unstar(&mut conn, &user_id, &params),
+        "getStarred" => get_starred(&mut conn, storage.get_ref(), &user_id, "starred"),
+        "getStarred2" => get_starred(&mut conn, storage.get_ref(), &user_id, "starred2"),
+        "stream" => {
+            // Audio on success; errors are reported as regular Subsonic responses
+            return match stream_params(&mut conn, &params) {
+                Ok((song, requested_format, max_bitrate)) => {
+                    let play = PlayRecorder {
+                        pool: pool.get_ref().clone(),
+                        user_id: user_id.clone(),
+                        song_id: song.id.clone(),
+                        client: client_name(params.get("c")),
+                    };
+                    let admin = is_admin(&mut conn, &user_id);
+                    stream_song_response(&req, &mut conn, &storage, **stream_mode, admin, &transcoder, &song, requested_format, max_bitrate, Some(play)).await
+                }
+                Err(e) => subsonic_response(&format, Err(e)),
+            };
+        }
+        other => Err(SubsonicError::new(SUBSONIC_ERROR_GENERIC, format!("Unknown method: {}", other))),
+    };
+
+    subsonic_response(&format, result)
+}
+
+fn payload(name: &'static str, value: impl Serialize) -> SubsonicResult {
+    serde_json::to_value(value)
+        .map(|v| Some((name, v)))
+        .map_err(|e| SubsonicError::new(SUBSONIC_ERROR_GENERIC, e.to_string()))
+}
+
+// --------------------- Browsing ---------------------
+
+fn get_artists(conn: &mut MysqlConnection) -> SubsonicResult {
+    let rows = diesel::sql_query(format!("{} GROUP BY a.id ORDER BY a.name", ARTIST_SELECT))
+        .load::<SubsonicArtistRow>(conn)
+        .map_err(|_| SubsonicError::database())?;
+
+    // Indexed by first letter, everything else under "#"
+    let mut index: BTreeMap<String, Vec<SubsonicArtist>> = BTreeMap::new();
+    for row in rows {
+        let letter = match row.name.chars().next() {
+            Some(c) if c.is_alphabetic() => c.to_uppercase().to_string(),
+            _ => "#".to_string(),
+        };
+        index.entry(letter).or_default().push(to_subsonic_artist(row));
+    }
+
+    payload("artists", ArtistsId3 {
+        ignored_articles: String::new(),
+        index: index.into_iter().map(|(name, artist)| ArtistIndex { name, artist }).collect(),
+    })
+}
+
+fn get_album(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let album_id = params.require("id")?;
+
+    let row = diesel::sql_query(format!("{} WHERE al.id = ? GROUP BY al.id, a.name", ALBUM_SELECT))
+        .bind::<Text, _>(album_id)
+        .get_result::<SubsonicAlbumRow>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?
+        .ok_or_else(|| SubsonicError::not_found("Album"))?;
+
+    let rows = diesel::sql_query(format!(
+        "{} WHERE s.album_id = ? ORDER BY s.track_number IS NULL, s.track_number, s.title",
+        SONG_SELECT
+    ))
+    .bind::<Text, _>(album_id)
+    .load::<SongResponse>(conn)
+    .map_err(|_| SubsonicError::database())?;
+
+    let mut album = to_subsonic_album(row);
+    album.song = to_subsonic_songs(conn, storage, user_id, rows)?;
+    payload("album", album)
+}
+
+fn get_song(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let song_id = params.require("id")?;
+
+    let row = diesel::sql_query(format!("{} WHERE s.id = ?", SONG_SELECT))
+        .bind::<Text, _>(song_id)
+        .get_result::<SongResponse>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?
+        .ok_or_else(|| SubsonicError::not_found("Song"))?;
+
+    match to_subsonic_songs(conn, storage, user_id, vec![row])?.pop() {
+        Some(song) => payload("song", song),
+        None => Err(SubsonicError::not_found("Song")),
+    }
+}
+
+/// Substring search over artist names, album names and song titles.
+/// An empty query matches everything, which clients use to sync the whole library.
+fn search3(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let query = params.require("query")?.trim().trim_matches('"');
+    let pattern = contains_pattern(query);
+
+    let count = |name: &str| params.get_i64(name).unwrap_or(SEARCH_DEFAULT_COUNT).clamp(0, SEARCH_MAX_COUNT);
+    let offset = |name: &str| params.get_i64(name).unwrap_or(0).max(0);
+
+    let artists = diesel::sql_query(format!(
+        "{} WHERE a.name LIKE ? GROUP BY a.id ORDER BY a.name LIMIT ? OFFSET ?",
+        ARTIST_SELECT
+    ))
+    .bind::<Text, _>(&pattern)
+    .bind::<BigInt, _>(count("artistCount"))
+    .bind::<BigInt, _>(offset("artistOffset"))
+    .load::<SubsonicArtistRow>(conn)
+    .map_err(|_| SubsonicError::database())?;
+
+    let albums = diesel::sql_query(format!(
+        "{} WHERE al.name LIKE ? GROUP BY al.id, a.name ORDER BY al.name LIMIT ? OFFSET ?",
+        ALBUM_SELECT
+    ))
+    .bind::<Text, _>(&pattern)
+    .bind::<BigInt, _>(count("albumCount"))
+    .bind::<BigInt, _>(offset("albumOffset"))
+    .load::<SubsonicAlbumRow>(conn)
+    .map_err(|_| SubsonicError::database())?;
+
+    let song_rows = diesel::sql_query(format!("{} WHERE s.title LIKE ? ORDER BY s.title LIMIT ? OFFSET ?", SONG_SELECT))
+        .bind::<Text, _>(&pattern)
+        .bind::<BigInt, _>(count("songCount"))
+        .bind::<BigInt, _>(offset("songOffset"))
+        .load::<SongResponse>(conn)
+        .map_err(|_| SubsonicError::database())?;
+
+    payload("searchResult3", SubsonicSearchResult {
+        artist: artists.into_iter().map(to_subsonic_artist).collect(),
+        album: albums.into_iter().map(to_subsonic_album).collect(),
+        song: to_subsonic_songs(conn, storage, user_id, song_rows)?,
+    })
+}
+
+/// Song, format and bitrate of a `stream` call. `format=raw` asks for the original and
+/// `maxBitRate=0` means no limit.
+fn stream_params(
+    conn: &mut MysqlConnection,
+    params: &SubsonicParams,
+) -> Result<(Song, Option<RenditionFormat>, Option<i32>), SubsonicError> {
+    let song_id = params.require("id")?;
+
+    let song = songs::table
+        .filter(songs::id.eq(song_id))
+        .first::<Song>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?
+        .ok_or_else(|| SubsonicError::not_found("Song"))?;
+
+    if params.get("format") == Some("raw") {
+        return Ok((song, None, None));
+    }
+    let requested_format = match params.get("format") {
+        Some(f) => Some(RenditionFormat::parse(f).ok_or_else(|| {
+            SubsonicError::new(SUBSONIC_ERROR_GENERIC, format!("Unsupported format: {}", f))
+        })?),
+        None => None,
+    };
+    let max_bitrate = params
+        .get_i64("maxBitRate")
+        .filter(|b| *b > 0)
+        .map(|b| b.min(i32::MAX as i64) as i32);
+
+    Ok((song, requested_format, max_bitrate))
+}
+
+// --------------------- Playlists ---------------------
+
+/// The user's playlists, those shared with them and everyone's public ones
+fn get_playlists(conn: &mut MysqlConnection, user_id: &str) -> SubsonicResult {
+    let rows = diesel::sql_query(format!(
+        "{} WHERE p.user_id = ? OR p.is_public = TRUE \
+         OR EXISTS (SELECT 1 FROM playlist_members m WHERE m.playlist_id = p.id AND m.user_id = ?) \
+         GROUP BY p.id, u.username ORDER BY p.name",
+        PLAYLIST_SELECT
+    ))
+    .bind::<Text, _>(user_id)
+    .bind::<Text, _>(user_id)
+    .load::<SubsonicPlaylistRow>(conn)
+    .map_err(|_| SubsonicError::database())?;
+
+    payload("playlists", Playlists {
+        playlist: rows.into_iter().map(to_subsonic_playlist).collect(),
+    })
+}
+
+fn get_playlist(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, playlist_id: &str) -> SubsonicResult {
+    let row = diesel::sql_query(format!("{} WHERE p.id = ? GROUP BY p.id, u.username", PLAYLIST_SELECT))
+        .bind::<Text, _>(playlist_id)
+        .get_result::<SubsonicPlaylistRow>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?
+        .ok_or_else(|| SubsonicError::not_found("Playlist"))?;
+    // Private playlists not shared with the user are reported as missing
+    if playlist_access(conn, playlist_id, &row.user_id, user_id)
+        .map_err(|_| SubsonicError::database())?
+        .is_none()
+    {
+        return Err(SubsonicError::not_found("Playlist"));
+    }
+
+    let smart_rules = playlists::table
+        .find(playlist_id)
+        .filter(playlists::kind.eq(KIND_SMART))
+        .select(playlists::rules)
+        .first::<Option<String>>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?;
+
+    let rows = match smart_rules {
+        Some(rules) => smart_listing(&stored_rules(rules.as_deref()), &row.user_id)
+            .map_err(|message| SubsonicError::new(SUBSONIC_ERROR_GENERIC, message))?
+            .load_all(conn)
+            .map_err(|_| SubsonicError::database())?,
+        None => diesel::sql_query(format!(
+            "{} JOIN playlist_songs ps ON ps.song_id = s.id WHERE ps.playlist_id = ? ORDER BY ps.position",
+            SONG_SELECT
+        ))
+        .bind::<Text, _>(playlist_id)
+        .load::<SongResponse>(conn)
+        .map_err(|_| SubsonicError::database())?,
+    };
+
+    let mut playlist = to_subsonic_playlist(row);
+    playlist.song_count = rows.len() as i64;
+    playlist.duration = rows.iter().map(|s| i64::from(s.duration_seconds)).sum();
+    playlist.entry = to_subsonic_songs(conn, storage, user_id, rows)?;
+    payload("playlist", playlist)
+}
+
+/// Create a playlist from `name` and `songId`s, or with `playlistId` replace the songs
+/// (and the name, if given) of one of the user's playlists
+fn create_playlist(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    // A song can be in a playlist only once, the first occurrence keeps its place
+    let mut song_ids: Vec<&str> = Vec::new();
+    for song_id in params.get_all("songId") {
+        if !song_ids.contains(&song_id) {
+            song_ids.push(song_id);
+        }
+    }
+
+    let creating = params.get("playlistId").is_none();
+    let name = match params.get("name") {
+        Some(name) => Some(name),
+        None if creating => return Err(SubsonicError::missing("name")),
+        None => None,
+    };
+
+    let playlist_id = match params.get("playlistId") {
+        Some(existing) => {
+            let (owner, kind) = playlists::table
+                .filter(playlists::id.eq(existing))
+                .select((playlists::user_id, playlists::kind))
+                .first::<(String, String)>(conn)
+                .optional()
+                .map_err(|_| SubsonicError::database())?
+                .ok_or_else(|| SubsonicError::not_found("Playlist"))?;
+            if owner != user_id {
+                return Err(SubsonicError::new(SUBSONIC_ERROR_NOT_AUTHORIZED, "Only the owner can modify a playlist"));
+            }
+            if kind == KIND_SMART {
+                return Err(SubsonicError::new(SUBSONIC_ERROR_GENERIC, "The songs of a smart playlist cannot be changed"));
+            }
+            existing.to_string()
+        }
+        None => Uuid::new_v4().to_string(),
+    };
+    let known_songs = songs::table
+        .filter(songs::id.eq_any(song_ids.iter().copied()))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(|_| SubsonicError::database())?;
+    if known_songs != song_ids.len() as i64 {
+        return Err(SubsonicError::not_found("Song"));
+    }
+
+    let entries: Vec<NewPlaylistSong> = song_ids
+        .iter()
+        .enumerate()
+        .map(|(position, song_id)| NewPlaylistSong {
+            playlist_id: playlist_id.clone(),
+            song_id: song_id.to_string(),
+            position: position as i32,
+            added_by: Some(user_id.to_string()),
+        })
+        .collect();
+
+    conn.transaction::<_, DieselError, _>(|conn| {
+        if creating {
+            diesel::insert_into(playlists::table)
+                .values(&NewPlaylist {
+                    id: playlist_id.clone(),
+                    user_id: user_id.to_string(),
+                    name: name.unwrap_or_default().to_string(),
+                    description: None,
+                    is_public: Some(false),
+                    kind: None,
+                    rules: None,
+                })
+                .execute(conn)?;
+        } else {
+            if let Some(name) = name {
+                diesel::update(playlists::table.filter(playlists::id.eq(&playlist_id)))
+                    .set(playlists::name.eq(name))
+                    .execute(conn)?;
+            }
+            diesel::delete(playlist_songs::table.filter(playlist_songs::playlist_id.eq(&playlist_id))).execute(conn)?;
+        }
+        if !entries.is_empty() {
+            diesel::insert_into(playlist_songs::table).values(&entries).execute(conn)?;
+        }
+        Ok(())
+    })
+    .map_err(|_| SubsonicError::database())?;
+
+    get_playlist(conn, storage, user_id, &playlist_id)
+}
+
+// --------------------- Plays ---------------------
+
+/// Record finished plays (`submission=true`, the default) or set the now-playing song.
+/// `time` gives each play's start in milliseconds since the epoch.
+fn scrobble(conn: &mut MysqlConnection, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let song_ids = params.get_all("id");
+    if song_ids.is_empty() {
+        return Err(SubsonicError::missing("id"));
+    }
+    let times = params.get_all("time");
+    let submission = params.get("submission") != Some("false");
+    let client = client_name(params.get("c"));
+
+    for (index, song_id) in song_ids.iter().enumerate() {
+        let result = if submission {
+            let played_at = times
+                .get(index)
+                .and_then(|t| t.parse::<i64>().ok())
+                .and_then(DateTime::from_timestamp_millis)
+                .unwrap_or_else(Utc::now)
+                .naive_utc();
+            let play = NewPlay {
+                id: Uuid::new_v4().to_string(),
+                user_id: user_id.to_string(),
+                song_id: song_id.to_string(),
+                played_at,
+                seconds_listened: None,
+                client: client.clone(),
+                source: PLAY_SOURCE_REPORT.to_string(),
+            };
+            record_play(conn, play).and_then(|_| clear_now_playing(conn, user_id, song_id))
+        } else {
+            set_now_playing(conn, user_id, song_id, client.clone())
+        };
+
+        match result {
+            Ok(_) => {}
+            Err(DieselError::NotFound) | Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                return Err(SubsonicError::not_found("Song"));
+            }
+            Err(_) => return Err(SubsonicError::database()),
+        }
+    }
+    Ok(None)
+}
+
+// --------------------- Favorites ---------------------
+
+/// Only songs can be starred: favorites have no albums or artists
+fn starred_song_ids(params: &SubsonicParams) -> Result<Vec<&str>, SubsonicError> {
+    if params.get("albumId").is_some() || params.get("artistId").is_some() {
+        return Err(SubsonicError::new(SUBSONIC_ERROR_GENERIC, "Only songs can be starred"));
+    }
+    let ids = params.get_all("id");
+    if ids.is_empty() {
+        return Err(SubsonicError::missing("id"));
+    }
+    Ok(ids)
+}
+
+fn star(conn: &mut MysqlConnection, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let mut song_ids = starred_song_ids(params)?;
+    song_ids.sort_unstable();
+    song_ids.dedup();
+
+    let known_songs = songs::table
+        .filter(songs::id.eq_any(song_ids.iter().copied()))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(|_| SubsonicError::database())?;
+    if known_songs != song_ids.len() as i64 {
+        return Err(SubsonicError::not_found("Song"));
+    }
+
+    let new_favorites: Vec<NewFavorite> = song_ids
+        .iter()
+        .map(|song_id| NewFavorite {
+            user_id: user_id.to_string(),
+            song_id: song_id.to_string(),
+        })
+        .collect();
+
+    // Starring a song twice is not an error
+    diesel::insert_or_ignore_into(favorites::table)
+        .values(&new_favorites)
+        .execute(conn)
+        .map_err(|_| SubsonicError::database())?;
+    Ok(None)
+}
+
+fn unstar(conn: &mut MysqlConnection, user_id: &str, params: &SubsonicParams) -> SubsonicResult {
+    let song_ids = starred_song_ids(params)?;
+
+    diesel::delete(
+        favorites::table
+            .filter(favorites::user_id.eq(user_id))
+            .filter(favorites::song_id.eq_any(song_ids)),
+    )
+    .execute(conn)
+    .map_err(|_| SubsonicError::database())?;
+    Ok(None)
+}
+
+fn get_starred(conn: &mut MysqlConnection, storage: &dyn ObjectStorage, user_id: &str, element: &'static str) -> SubsonicResult {
+    let rows = diesel::sql_query(format!(
+        "{} JOIN favorites f ON f.song_id = s.id WHERE f.user_id = ? ORDER BY f.added_at DESC",
+        SONG_SELECT
+    ))
+    .bind::<Text, _>(user_id)
+    .load::<SongResponse>(conn)
+    .map_err(|_| SubsonicError::database())?;
+
+    payload(element, SubsonicSearchResult {
+        artist: Vec::new(),
+        album: Vec::new(),
+        song: to_subsonic_songs(conn, storage, user_id, rows)?,
+    })
+}
+
+/// When the user starred each of `song_ids`, for those in their favorites
+fn starred_at(conn: &mut MysqlConnection, user_id: &str, song_ids: &[String]) -> QueryResult<HashMap<String, NaiveDateTime>> {
+    let rows = favorites::table
+        .filter(favorites::user_id.eq(user_id))
+        .filter(favorites::song_id.eq_any(song_ids))
+        .select((favorites::song_id, favorites::added_at))
+        .load::<(String, Option<NaiveDateTime>)>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(song_id, added_at)| added_at.map(|at| (song_id, at)))
+        .collect())
+}
+
+// --------------------- Conversions ---------------------
+
+fn to_subsonic_artist(row: SubsonicArtistRow) -> SubsonicArtist {
+    SubsonicArtist {
+        id: row.id,
+        name: row.name,
+        album_count: row.album_count,
+    }
+}
+
+fn to_subsonic_album(row: SubsonicAlbumRow) -> SubsonicAlbum {
+    SubsonicAlbum {
+        id: row.id,
+        name: row.name,
+        artist: row.artist_name,
+        artist_id: row.artist_id,
+        song_count: row.song_count,
+        duration: row.duration,
+        year: row.release_year,
+        created: subsonic_timestamp(row.created_at),
+        song: Vec::new(),
+    }
+}
+
+fn to_subsonic_playlist(row: SubsonicPlaylistRow) -> SubsonicPlaylist {
+    SubsonicPlaylist {
+        id: row.id,
+        name: row.name,
+        comment: row.description,
+        owner: row.owner,
+        public: row.is_public.unwrap_or(false),
+        song_count: row.song_count,
+        duration: row.duration,
+        created: subsonic_timestamp(row.created_at),
+        changed: subsonic_timestamp(row.updated_at),
+        entry: Vec::new(),
+    }
+}
+
+/// Convert songs, marking the ones the user starred. Suffix and content type describe the
+/// original file, which is what `stream` serves when no format is requested.
+fn to_subsonic_songs(
+    conn: &mut MysqlConnection,
+    storage: &dyn ObjectStorage,
+    user_id: &str,
+    rows: Vec<SongResponse>,
+) -> Result<Vec<SubsonicSong>, SubsonicError> {
+    let song_ids: Vec<String> = rows.iter().map(|s| s.id.clone()).collect();
+    let starred = starred_at(conn, user_id, &song_ids).map_err(|_| SubsonicError::database())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|s| {
+            let key = storage.key_for_url(&s.object_url);
+            let replay_gain = match (s.track_gain, s.album_gain) {
+                (None, None) => None,
+                _ => Some(ReplayGain {
+                    track_gain: s.track_gain,
+                    track_peak: s.track_peak,
+                    album_gain: s.album_gain,
+                    album_peak: s.album_peak,
+                }),
+            };
+            SubsonicSong {
+                starred: subsonic_timestamp(starred.get(&s.id).copied()),
+                parent: s.album_id.clone(),
+                is_dir: false,
+                title: s.title,
+                album: s.album_name,
+                artist: s.artist_name,
+                track: s.track_number,
+                genre: s.genre_name,
+                content_type: key.as_deref().map(|k| content_type_for_key(k).to_string()),
+                suffix: key.as_deref().and_then(|k| k.rsplit_once('.')).map(|(_, ext)| ext.to_ascii_lowercase()),
+                duration: s.duration_seconds,
+                album_id: s.album_id,
+                artist_id: s.artist_id,
+                media_type: "music".to_string(),
+                created: subsonic_timestamp(s.created_at),
+                replay_gain,
+                id: s.id,
+            }
+        })
+        .collect())
+}
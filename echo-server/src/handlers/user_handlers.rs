@@ -3,8 +3,9 @@ use actix_web::{web, Responder, HttpResponse, ResponseError};
 use diesel::prelude::*;
 use uuid::Uuid;
 use bcrypt::hash;
+use rand::RngCore;
 
-use crate::models::user_models::{CreateUser, NewUser, UpdateUser, User, UserResponse, UserRow};
+use crate::models::user_models::{CreateUser, NewUser, SubsonicPasswordResponse, UpdateUser, User, UserResponse, UserRow};
 use crate::db::DbPool;
 use crate::db::get_conn;
 use crate::schema::users::dsl::*;
@@ -128,4 +129,69 @@ pub async fn create_user(
         }
         Err(_) => HttpResponse::InternalServerError().body("Failed to create user"),
     }
-}
\ No newline at end of file
+}
+/// Generate a new password for Subsonic clients, replacing any previous one.
+/// Subsonic token auth hashes the password with a salt on the client, so the server
+/// must keep it in clear: it is random and separate from the login password for that reason.
+pub async fn regenerate_subsonic_password(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let user_id: String = path.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(valid_id) => valid_id,
+        Err(resp) => return resp,
+    };
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    let mut secret = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let new_password = hex::encode(secret);
+
+    match diesel::update(users.filter(id.eq(user_id)))
+        .set(subsonic_password.eq(&new_password))
+        .execute(&mut conn)
+    {
+        Ok(0) => HttpResponse::NotFound().body("User not found"),
+        Ok(_) => match users.filter(id.eq(user_id)).select(username).first::<String>(&mut conn) {
+            Ok(name) => HttpResponse::Ok().json(SubsonicPasswordResponse {
+                username: name,
+                subsonic_password: new_password,
+            }),
+            Err(_) => HttpResponse::InternalServerError().body("Failed to fetch user"),
+        },
+        Err(_) => HttpResponse::InternalServerError().body("Failed to update user"),
+    }
+}
+
+/// Revoke Subsonic access for the user
+pub async fn delete_subsonic_password(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    claims: ReqData<Claims>,
+) -> impl Responder {
+    let user_id: String = path.into_inner();
+    let user_id: &str = match check_ownership(&user_id, &claims) {
+        Ok(valid_id) => valid_id,
+        Err(resp) => return resp,
+    };
+
+    let mut conn = match get_conn(&pool) {
+        Ok(c) => c,
+        Err(e) => return e.error_response(),
+    };
+
+    match diesel::update(users.filter(id.eq(user_id)))
+        .set(subsonic_password.eq(None::<String>))
+        .execute(&mut conn)
+    {
+        Ok(0) => HttpResponse::NotFound().body("User not found"),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().body("Failed to update user"),
+    }
+}
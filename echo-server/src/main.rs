@@ -6,11 +6,15 @@ mod schema;
 mod utils;
 mod middleware;
 mod constants;
+mod storage;
+mod workers;
+mod cli;
 
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpRequest, HttpServer, Responder};
 use diesel::r2d2::{self, ConnectionManager};
 use diesel::MysqlConnection;
+use std::sync::Arc;
 
 #[actix_web::get("/health")]
 async fn health(_req: HttpRequest) -> impl Responder {
@@ -21,9 +25,6 @@ async fn health(_req: HttpRequest) -> impl Responder {
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
 
-    let port: u16 = 8080;
-    println!("Starting server on port {port}");
-
     // Setup DB pool from DATABASE_URL env
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env");
     let manager = ConnectionManager::<MysqlConnection>::new(database_url);
@@ -32,23 +33,77 @@ async fn main() -> std::io::Result<()> {
         .build(manager)
         .expect("Failed to create DB pool");
 
+    let storage = storage::from_env();
+
+    // `echo backup ...` and `echo restore ...` run instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args, &pool, storage.as_ref()).await;
+    }
+
+    let port: u16 = 8080;
+    println!("Starting server on port {port}");
+
     let jwt_secret = std::env::var("JWT_SECRET")
         .expect("JWT_SECRET must be set in .env")
         .into_bytes();
 
     let secret_data = web::Data::new(jwt_secret);
 
-    HttpServer::new(move || {
+    let stream_mode = models::stream_models::StreamMode::from_env();
+    println!("Streaming mode: {:?}", stream_mode);
+    let stream_mode_data = web::Data::new(stream_mode);
+
+    let storage_data: web::Data<dyn storage::ObjectStorage> = web::Data::from(storage.clone());
+
+    // Background ingestion of uploaded songs
+    let ingest_queue = Arc::new(workers::ingest_worker::IngestQueue::default());
+    let ingest_workers = workers::ingest_worker::ingest_workers_from_env();
+    println!("Ingest workers: {}", ingest_workers);
+    let ingest_settings = workers::ingest_worker::IngestSettings::from_env();
+    println!("Ingest settings: {:?}", ingest_settings);
+    let loudness_data = web::Data::new(ingest_settings.loudness);
+    actix_web::rt::spawn(workers::ingest_worker::run(
+        pool.clone(),
+        storage,
+        ingest_queue.clone(),
+        ingest_workers,
+        ingest_settings,
+    ));
+    let ingest_queue_data = web::Data::from(ingest_queue);
+
+    let transcoder_data = web::Data::new(utils::transcode_utils::Transcoder::from_env());
+    let stats_cache_data = web::Data::new(utils::stats_utils::StatsCache::from_env());
+
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(pool.clone()))
             .app_data(secret_data.clone())
+            .app_data(stream_mode_data.clone())
+            .app_data(storage_data.clone())
+            .app_data(ingest_queue_data.clone())
+            .app_data(loudness_data.clone())
+            .app_data(transcoder_data.clone())
+            .app_data(stats_cache_data.clone())
             .wrap(middleware::session_middleware::SessionMiddlewareFactory)
             .service(health)
             .service(web::scope("/api").configure(routes::configure))
+            .service(web::scope("/rest").configure(routes::subsonic_routes::configure))
     })
-        .bind(("0.0.0.0", port))?
-        .workers(1)
-        .run()
-        .await
+        .bind(("0.0.0.0", port))?;
+
+    // Defaults to one worker per CPU core
+    let server = match http_workers_from_env() {
+        Some(n) => server.workers(n),
+        None => server,
+    };
+    server.run().await
+}
+
+fn http_workers_from_env() -> Option<usize> {
+    std::env::var("HTTP_WORKERS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
 }
\ No newline at end of file
@@ -6,12 +6,16 @@ use actix_web::{
 };
 use diesel::prelude::*;
 use futures::future::{ready, LocalBoxFuture, Ready};
+use once_cell::sync::Lazy;
 use std::sync::Arc;
 use regex::Regex;
 use crate::{
     constants::middleware_constants::ADMIN_ONLY_ROUTES, db::DbPool, models::{session_models::Session, token_models::Claims, user_models::PublicUser}, schema::{sessions, users}, utils::token_utils::verify_jwt
 };
 
+// Matches an escaped path placeholder such as `\{album_id\}` in a route template
+static ROUTE_PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\\\{[a-z_]+\\\}").unwrap());
+
 pub struct SessionMiddlewareFactory;
 
 impl<S, B> Transform<S, ServiceRequest> for SessionMiddlewareFactory
@@ -65,6 +69,16 @@ where
                 return service.call(req).await;
             }
 
+            // Signed stream URLs carry their own HMAC authorization, verified by the handler
+            if path.starts_with("/api/stream/") && method == Method::GET {
+                return service.call(req).await;
+            }
+
+            // Subsonic clients authenticate every call with their own scheme, checked by the handler
+            if path.starts_with("/rest/") {
+                return service.call(req).await;
+            }
+
             let token_value = auth_header.strip_prefix("Bearer ").unwrap_or("");
             
             let pool = pool_option.ok_or_else(|| actix_web::error::ErrorInternalServerError("Database pool not configured"))?;
@@ -106,8 +120,9 @@ where
                     if route_method != &method { return false; }
 
                     // Convert route template to regex, e.g., /api/songs/{song_id} -> ^/api/songs/[^/]+$
+                    let escaped_path: String = regex::escape(route_path);
                     let regex_pattern: Regex = Regex::new(
-                        &format!("^{}$", regex::escape(route_path).replace(r"\{song_id\}", r"[^/]+"))
+                        &format!("^{}$", ROUTE_PLACEHOLDER.replace_all(&escaped_path, r"[^/]+"))
                     ).unwrap();
 
                     regex_pattern.is_match(&path)
@@ -1,10 +1,10 @@
 use diesel::prelude::Insertable;
-use diesel::{Queryable, Selectable};
+use diesel::{Queryable, QueryableByName, Selectable};
 use serde::{Serialize, Deserialize};
 
 use crate::models::pagination_models::Pagination;
 
-#[derive(Queryable, Insertable, Serialize, Deserialize, Selectable)]
+#[derive(Queryable, QueryableByName, Insertable, Serialize, Deserialize, Selectable)]
 #[diesel(table_name = crate::schema::albums)]
 pub struct Album {
     pub id: String,
@@ -13,6 +13,8 @@ pub struct Album {
     pub release_year: Option<i32>,
     pub cover_url: Option<String>,
     pub created_at: Option<chrono::NaiveDateTime>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -0,0 +1,39 @@
+use diesel::prelude::{AsChangeset, Insertable};
+use diesel::{Queryable, QueryableByName, Selectable};
+use serde::{Serialize, Deserialize};
+
+#[derive(Queryable, QueryableByName, Serialize, Deserialize, Selectable)]
+#[diesel(table_name = crate::schema::artists)]
+pub struct Artist {
+    pub id: String,
+    pub name: String,
+    pub bio: Option<String>,
+    pub image_url: Option<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable, Deserialize)]
+#[diesel(table_name = crate::schema::artists)]
+pub struct NewArtist {
+    #[serde(skip_deserializing)]
+    pub id: String,
+    pub name: String,
+    pub bio: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(AsChangeset, Deserialize)]
+#[diesel(table_name = crate::schema::artists)]
+pub struct UpdateArtist {
+    pub name: Option<String>,
+    pub bio: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ArtistQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub cursor: Option<String>,
+}
@@ -0,0 +1,286 @@
+use actix_web::{HttpResponse, ResponseError};
+use chrono::NaiveDateTime;
+use diesel::prelude::{Insertable, Queryable, Selectable};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::db::DbError;
+use crate::storage::StorageError;
+
+/// `format` of every manifest, to tell Echo archives from other tarballs
+pub const BACKUP_FORMAT: &str = "echo-backup";
+/// Version of the archive layout; restores refuse versions they do not know
+pub const BACKUP_VERSION: u32 = 1;
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Tables of an archive, in the order they are restored
+pub const BACKUP_TABLES: [&str; 10] = [
+    "users",
+    "genres",
+    "artists",
+    "albums",
+    "songs",
+    "playlists",
+    "playlist_songs",
+    "playlist_members",
+    "playlist_follows",
+    "favorites",
+];
+
+// --------------------- Archive Manifest ---------------------
+/// `manifest.json`: what the archive holds and the SHA-256 of each file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    /// Whether users come with their password hashes
+    pub credentials: bool,
+    pub tables: Vec<ManifestTable>,
+    pub objects: Vec<ManifestObject>,
+}
+
+/// Rows of a table, as a JSON array in `file`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestTable {
+    pub name: String,
+    pub file: String,
+    pub rows: usize,
+    pub sha256: String,
+}
+
+/// Audio object of a song, stored in `file` under its storage key
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestObject {
+    pub song_id: String,
+    pub key: String,
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+// --------------------- Archived Rows ---------------------
+// One struct per table, kept apart from the API models so the archive layout only
+// changes with `BACKUP_VERSION`.
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::users)]
+pub struct UserRecord {
+    pub id: String,
+    pub username: String,
+    /// Empty in archives made without credentials. Subsonic passwords are stored in
+    /// plain text and never archived: restored users generate a new one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub password_hash: String,
+    pub avatar_url: Option<String>,
+    pub is_admin: bool,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::genres)]
+pub struct GenreRecord {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::artists)]
+pub struct ArtistRecord {
+    pub id: String,
+    pub name: String,
+    pub bio: Option<String>,
+    pub image_url: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::albums)]
+pub struct AlbumRecord {
+    pub id: String,
+    pub name: String,
+    pub artist_id: String,
+    pub release_year: Option<i32>,
+    pub cover_url: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::songs)]
+pub struct SongRecord {
+    pub id: String,
+    pub title: String,
+    pub artist_id: String,
+    pub album_id: Option<String>,
+    pub genre_id: Option<i32>,
+    pub track_number: Option<i32>,
+    pub duration_seconds: i32,
+    /// URL on the instance the archive comes from; rebuilt from the object key on restore
+    pub object_url: String,
+    pub loudness_lufs: Option<f32>,
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::playlists)]
+pub struct PlaylistRecord {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_public: Option<bool>,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub kind: String,
+    pub rules: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::playlist_songs)]
+pub struct PlaylistSongRecord {
+    pub playlist_id: String,
+    pub song_id: String,
+    pub position: i32,
+    pub added_at: Option<NaiveDateTime>,
+    pub added_by: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::playlist_members)]
+pub struct PlaylistMemberRecord {
+    pub playlist_id: String,
+    pub user_id: String,
+    pub role: String,
+    pub added_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::playlist_follows)]
+pub struct PlaylistFollowRecord {
+    pub playlist_id: String,
+    pub user_id: String,
+    pub followed_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::favorites)]
+pub struct FavoriteRecord {
+    pub user_id: String,
+    pub song_id: String,
+    pub added_at: Option<NaiveDateTime>,
+}
+
+// --------------------- Requests / Responses ---------------------
+#[derive(Deserialize)]
+pub struct BackupQuery {
+    /// Include password hashes (default false); without them, restored users cannot
+    /// log in until an admin sets a new password
+    pub credentials: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    /// Give every restored row a new id (default false). Users, artists, genres, albums and
+    /// songs already in the library are then reused by name instead of being duplicated;
+    /// the playlists and favorites of reused users are left out.
+    pub remap_ids: Option<bool>,
+}
+
+/// Rows written by a restore, per table, and the audio objects uploaded
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub users: usize,
+    pub genres: usize,
+    pub artists: usize,
+    pub albums: usize,
+    pub songs: usize,
+    pub playlists: usize,
+    pub playlist_songs: usize,
+    pub playlist_members: usize,
+    pub playlist_follows: usize,
+    pub favorites: usize,
+    pub objects: usize,
+    /// Library rows matched to existing ones instead of being inserted (with `remap_ids`)
+    pub reused: usize,
+}
+
+// --------------------- Errors ---------------------
+/// Failure of a backup or restore
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    Connection(DbError),
+    Database(DieselError),
+    Storage(StorageError),
+    /// Not an archive this version can restore, or a file not matching its checksum
+    Invalid(String),
+    /// Rows of the archive colliding with rows already in the database
+    Conflict(String),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "I/O error: {}", e),
+            BackupError::Connection(e) => write!(f, "{}", e),
+            BackupError::Database(e) => write!(f, "Database error: {}", e),
+            BackupError::Storage(e) => write!(f, "{}", e),
+            BackupError::Invalid(msg) => write!(f, "Invalid archive: {}", msg),
+            BackupError::Conflict(msg) => write!(f, "Conflicting rows: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<DbError> for BackupError {
+    fn from(e: DbError) -> Self {
+        BackupError::Connection(e)
+    }
+}
+
+impl From<DieselError> for BackupError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                BackupError::Conflict(info.message().to_string())
+            }
+            e => BackupError::Database(e),
+        }
+    }
+}
+
+impl From<StorageError> for BackupError {
+    fn from(e: StorageError) -> Self {
+        BackupError::Storage(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> Self {
+        BackupError::Invalid(e.to_string())
+    }
+}
+
+impl ResponseError for BackupError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            BackupError::Invalid(_) => HttpResponse::BadRequest().body(self.to_string()),
+            BackupError::Conflict(_) => HttpResponse::Conflict().body(self.to_string()),
+            _ => HttpResponse::InternalServerError().body(self.to_string()),
+        }
+    }
+}
@@ -1,11 +1,11 @@
-use diesel::prelude::{AsChangeset, Insertable, Queryable};
+use diesel::prelude::{AsChangeset, Insertable, Queryable, QueryableByName};
 use serde::{Serialize, Deserialize};
 use diesel::Selectable;
 
 use crate::schema::genres;
 
-#[allow(dead_code)]
-#[derive(Queryable, Serialize, Debug, Selectable)]
+#[derive(Queryable, QueryableByName, Serialize, Debug, Selectable)]
+#[diesel(table_name = genres)]
 pub struct Genre {
     pub id: i32,
     pub name: String,
@@ -22,3 +22,11 @@ pub struct NewGenre {
 pub struct UpdateGenre {
     pub name: Option<String>,
 }
+
+#[derive(Deserialize)]
+pub struct GenreQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub cursor: Option<String>,
+}
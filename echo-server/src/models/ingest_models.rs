@@ -0,0 +1,111 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::{Insertable, Queryable};
+use diesel::Selectable;
+use serde::{Deserialize, Serialize};
+
+use crate::models::song_models::UploadItemResult;
+
+// Job lifecycle: queued -> processing -> succeeded | failed
+pub const JOB_QUEUED: &str = "queued";
+pub const JOB_PROCESSING: &str = "processing";
+pub const JOB_SUCCEEDED: &str = "succeeded";
+pub const JOB_FAILED: &str = "failed";
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::ingest_jobs)]
+pub struct IngestJob {
+    pub id: String,
+    pub user_id: String,
+    pub status: String,
+    pub total_items: i32,
+    pub processed_items: i32,
+    pub items_json: String,
+    pub report_json: Option<String>,
+    pub song_ids_json: Option<String>,
+    pub error_message: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::ingest_jobs)]
+pub struct NewIngestJob {
+    pub id: String,
+    pub user_id: String,
+    pub status: String,
+    pub total_items: i32,
+    pub items_json: String,
+}
+
+/// A song waiting in a job: its raw upload is staged in object storage under `staging_key`.
+/// Artists, albums and genres given by name are looked up, or created, by the worker in
+/// the same transaction as the song, so a failed job leaves no catalog rows behind.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IngestItem {
+    pub staging_key: String,
+    /// Extension of the uploaded format, if it can be stored as is
+    pub extension: Option<String>,
+    pub title: String,
+    pub artist_id: Option<String>,
+    /// Used when `artist_id` is not set
+    pub artist: Option<String>,
+    pub album_id: Option<String>,
+    /// Used when `album_id` is not set
+    pub album: Option<String>,
+    pub year: Option<i32>,
+    pub genre_id: Option<i32>,
+    /// Used when `genre_id` is not set
+    pub genre: Option<String>,
+    pub track_number: Option<i32>,
+    pub duration_seconds: i32,
+}
+
+#[derive(Serialize)]
+pub struct IngestJobAccepted {
+    pub job_id: String,
+    pub status: String,
+    pub status_url: String,
+}
+
+#[derive(Serialize)]
+pub struct IngestJobResponse {
+    pub id: String,
+    pub status: String,
+    /// Fraction of items processed, from 0.0 to 1.0
+    pub progress: f32,
+    pub total_items: i32,
+    pub processed_items: i32,
+    pub error_message: Option<String>,
+    pub song_ids: Vec<String>,
+    pub items: Option<Vec<UploadItemResult>>,
+    pub created_at: Option<NaiveDateTime>,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+impl From<IngestJob> for IngestJobResponse {
+    fn from(job: IngestJob) -> Self {
+        let progress = if job.total_items > 0 {
+            job.processed_items as f32 / job.total_items as f32
+        } else {
+            0.0
+        };
+
+        IngestJobResponse {
+            id: job.id,
+            status: job.status,
+            progress,
+            total_items: job.total_items,
+            processed_items: job.processed_items,
+            error_message: job.error_message,
+            song_ids: job.song_ids_json
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            items: job.report_json.and_then(|json| serde_json::from_str(&json).ok()),
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+        }
+    }
+}
@@ -6,4 +6,15 @@ pub mod favorite_models;
 pub mod token_models;
 pub mod genre_models;
 pub mod pagination_models;
-pub mod album_models;
\ No newline at end of file
+pub mod album_models;
+pub mod artist_models;
+pub mod stream_models;
+pub mod ingest_models;
+pub mod rendition_models;
+pub mod subsonic_models;
+pub mod play_models;
+pub mod stats_models;
+pub mod recommendation_models;
+pub mod radio_models;
+pub mod search_models;
+pub mod backup_models;
\ No newline at end of file
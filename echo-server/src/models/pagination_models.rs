@@ -1,11 +1,14 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use actix_web::{HttpResponse, ResponseError};
+use diesel::result::Error as DieselError;
 use std::fmt;
 
 #[derive(Debug, Deserialize)]
 pub struct Pagination {
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// `next_cursor` or `prev_cursor` of a previous page
+    pub cursor: Option<String>,
 }
 
 /// Errore di paginazione
@@ -25,25 +28,57 @@ impl ResponseError for PaginationError {
 }
 
 impl Pagination {
-    pub const DEFAULT_LIMIT: i64 = 100;
-    pub const MAX_LIMIT: i64 = 100;
-
-    pub fn limit(&self) -> Result<i64, PaginationError> {
-        let l = self.limit.unwrap_or(Self::DEFAULT_LIMIT);
-        if l > Self::MAX_LIMIT {
-            return Err(PaginationError(format!(
-                "Limit too high: maximum allowed is {}",
-                Self::MAX_LIMIT
-            )));
+    pub const DEFAULT_LIMIT: i64 = 50;
+    pub const MAX_LIMIT: i64 = 200;
+}
+
+/// One page of a listing. Cursors are opaque; `total` counts every item of the listing.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_cursor: self.next_cursor,
+            prev_cursor: self.prev_cursor,
         }
-        Ok(l)
     }
+}
+
+/// Failure loading a page: a cursor that does not belong to the listing, or the database
+#[derive(Debug)]
+pub enum PageError {
+    InvalidCursor,
+    Database(DieselError),
+}
+
+impl From<DieselError> for PageError {
+    fn from(e: DieselError) -> Self {
+        PageError::Database(e)
+    }
+}
 
-    pub fn offset(&self) -> i64 {
-        self.offset.unwrap_or(0)
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageError::InvalidCursor => write!(f, "Invalid cursor"),
+            PageError::Database(e) => write!(f, "Database error: {}", e),
+        }
     }
+}
 
-    pub fn sql_clause(&self) -> Result<String, PaginationError> {
-        Ok(format!("LIMIT {} OFFSET {}", self.limit()?, self.offset()))
+impl ResponseError for PageError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PageError::InvalidCursor => HttpResponse::BadRequest().body("Invalid cursor"),
+            PageError::Database(_) => HttpResponse::InternalServerError().finish(),
+        }
     }
 }
@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::{Insertable, Queryable};
+use diesel::sql_types::{Integer, Nullable, Text, Timestamp};
+use diesel::{QueryableByName, Selectable};
+use serde::{Deserialize, Serialize};
+
+use crate::models::song_models::SongResponse;
+
+// How a play was recorded
+/// Reported by the client once playback finished
+pub const PLAY_SOURCE_REPORT: &str = "report";
+/// Recorded by the server when a proxied or transcoded stream was sent to the end
+pub const PLAY_SOURCE_STREAM: &str = "stream";
+
+#[derive(Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::plays)]
+pub struct Play {
+    pub id: String,
+    pub user_id: String,
+    pub song_id: String,
+    pub played_at: NaiveDateTime,
+    pub seconds_listened: Option<i32>,
+    pub client: Option<String>,
+    pub source: String,
+}
+
+#[derive(Insertable, Clone)]
+#[diesel(table_name = crate::schema::plays)]
+pub struct NewPlay {
+    pub id: String,
+    pub user_id: String,
+    pub song_id: String,
+    pub played_at: NaiveDateTime,
+    pub seconds_listened: Option<i32>,
+    pub client: Option<String>,
+    pub source: String,
+}
+
+/// Body of `POST /api/plays`
+#[derive(Deserialize)]
+pub struct ReportPlayRequest {
+    pub song_id: String,
+    /// When playback started, for plays reported late (e.g. offline); now by default
+    pub played_at: Option<NaiveDateTime>,
+    pub seconds_listened: Option<i32>,
+    pub client: Option<String>,
+}
+
+/// Body of `POST /api/plays/now-playing`
+#[derive(Deserialize)]
+pub struct NowPlayingRequest {
+    pub song_id: String,
+    pub client: Option<String>,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct NowPlayingEntry {
+    #[diesel(sql_type = Text)]
+    pub user_id: String,
+    #[diesel(sql_type = Text)]
+    pub username: String,
+    #[diesel(sql_type = Text)]
+    pub song_id: String,
+    #[diesel(sql_type = Text)]
+    pub title: String,
+    #[diesel(sql_type = Text)]
+    pub artist_name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub client: Option<String>,
+    #[diesel(sql_type = Timestamp)]
+    pub started_at: NaiveDateTime,
+}
+
+/// One play of `/api/users/{user_id}/history`, with the song played
+#[derive(QueryableByName, Serialize)]
+pub struct HistoryEntry {
+    #[diesel(sql_type = Text)]
+    pub play_id: String,
+    #[diesel(sql_type = Timestamp)]
+    pub played_at: NaiveDateTime,
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub seconds_listened: Option<i32>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub client: Option<String>,
+    #[diesel(sql_type = Text)]
+    pub source: String,
+    #[diesel(embed)]
+    pub song: SongResponse,
+}
@@ -1,6 +1,7 @@
-use diesel::prelude::{AsChangeset, Associations, Identifiable, Insertable, Queryable};
+use diesel::prelude::{AsChangeset, Associations, Identifiable, Insertable, Queryable, QueryableByName};
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamp};
 use chrono::{NaiveDateTime};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use uuid::Uuid;
 
 // --------------------- Playlist Models ---------------------
@@ -8,10 +9,11 @@ use uuid::Uuid;
 pub struct PlaylistQuery {
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    pub cursor: Option<String>,
     pub name: Option<String>, 
 }
 
-#[derive(Queryable, Identifiable, Associations, Serialize)]
+#[derive(Queryable, QueryableByName, Identifiable, Associations, Serialize)]
 #[diesel(table_name = crate::schema::playlists)]
 #[diesel(belongs_to(crate::models::user_models::UserResponse, foreign_key = user_id))]
 pub struct Playlist {
@@ -22,6 +24,22 @@ pub struct Playlist {
     pub is_public: Option<bool>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
+    /// `KIND_REGULAR` or `KIND_SMART`
+    pub kind: String,
+    /// Rules of a smart playlist, stored as JSON
+    #[serde(serialize_with = "as_json")]
+    pub rules: Option<String>,
+}
+
+/// Playlists holding the songs added to them
+pub const KIND_REGULAR: &str = "regular";
+/// Playlists whose songs are listed from their rules on read
+pub const KIND_SMART: &str = "smart";
+
+/// Write stored JSON as JSON rather than as a string
+fn as_json<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    let parsed = value.as_deref().and_then(|v| serde_json::from_str::<serde_json::Value>(v).ok());
+    parsed.serialize(serializer)
 }
 
 #[derive(Insertable, Deserialize)]
@@ -34,6 +52,48 @@ pub struct NewPlaylist {
     pub name: String,
     pub description: Option<String>,
     pub is_public: Option<bool>,
+    /// Regular when missing
+    #[serde(skip_deserializing)]
+    pub kind: Option<String>,
+    #[serde(skip_deserializing)]
+    pub rules: Option<String>,
+}
+
+/// What the songs of a smart playlist must match, and how many are kept in which order.
+/// Favorites and play counts are those of the owner.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmartRules {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre_id: Option<i32>,
+    /// Songs by any of these artists
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artist_ids: Vec<String>,
+    /// Songs added to the library in the last days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_within_days: Option<i32>,
+    /// Longest duration in seconds, inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_max: Option<i32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub in_favorites: bool,
+    /// Songs played more times than this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub play_count_above: Option<i64>,
+    /// How many songs are kept, at most
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// Sort keys, as for song listings (`-release_date`, `artist,name`...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewSmartPlaylist {
+    pub name: String,
+    pub description: Option<String>,
+    pub is_public: Option<bool>,
+    pub rules: SmartRules,
 }
 
 #[derive(AsChangeset, Deserialize)]
@@ -55,10 +115,30 @@ pub struct PlaylistSong {
     pub song_id: String,
     pub position: i32,
     pub added_at: Option<NaiveDateTime>,
+    pub added_by: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct AddSongRequest {
+    pub song_id: String,
+    /// Where to insert the song; at the end if missing
+    pub position: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveSongRequest {
+    pub position: i32,
+}
+
+/// Every song of a playlist, in the wanted order
+#[derive(Deserialize)]
+pub struct ReorderSongsRequest {
+    pub song_ids: Vec<String>,
+}
+
+/// Where a song sits in a playlist
+#[derive(Serialize)]
+pub struct PlaylistEntry {
     pub song_id: String,
     pub position: i32,
 }
@@ -69,6 +149,176 @@ pub struct NewPlaylistSong {
     pub playlist_id: String,
     pub song_id: String,
     pub position: i32,
+    pub added_by: Option<String>,
+}
+
+// --------------------- Playlist Members Models ---------------------
+/// Members who can add, move and remove songs
+pub const ROLE_EDITOR: &str = "editor";
+/// Members who can only see the playlist, even when it is private
+pub const ROLE_VIEWER: &str = "viewer";
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::playlist_members)]
+pub struct NewPlaylistMember {
+    pub playlist_id: String,
+    pub user_id: String,
+    pub role: String,
+}
+
+#[derive(Deserialize)]
+pub struct SetMemberRequest {
+    pub role: String,
+}
+
+#[derive(Queryable, Serialize)]
+pub struct PlaylistMemberResponse {
+    pub user_id: String,
+    pub username: String,
+    pub role: String,
+    pub added_at: NaiveDateTime,
+}
+
+// --------------------- Public Playlists Models ---------------------
+#[derive(Deserialize)]
+pub struct PublicPlaylistQuery {
+    /// Words of the name or description
+    pub q: Option<String>,
+    /// "popular" (most followed, default), "recent" or "name"
+    pub sort: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::playlist_follows)]
+pub struct NewPlaylistFollow {
+    pub playlist_id: String,
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct CopyPlaylistRequest {
+    /// Name of the copy; the original name followed by " (copy)" if missing
+    pub name: Option<String>,
+}
+
+/// A playlist with its owner's name, and how many songs and followers it has
+#[derive(QueryableByName, Serialize)]
+pub struct PlaylistSummary {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub user_id: String,
+    #[diesel(sql_type = Text)]
+    pub owner: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub description: Option<String>,
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub created_at: Option<NaiveDateTime>,
+    #[diesel(sql_type = BigInt)]
+    pub song_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub follower_count: i64,
+}
+
+// --------------------- Import / Export Models ---------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u8,
+    Xspf,
+    Jspf,
+}
+
+impl PlaylistFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "m3u8" | "m3u" => Some(PlaylistFormat::M3u8),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            "jspf" => Some(PlaylistFormat::Jspf),
+            _ => None,
+        }
+    }
+
+    /// Format of a playlist file, from how it starts
+    pub fn detect(body: &str) -> Option<Self> {
+        let start = body.trim_start_matches('\u{feff}').trim_start();
+        if start.starts_with('{') {
+            Some(PlaylistFormat::Jspf)
+        } else if start.starts_with('<') {
+            Some(PlaylistFormat::Xspf)
+        } else if !start.is_empty() {
+            // M3U, with or without its #EXTM3U header
+            Some(PlaylistFormat::M3u8)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "audio/x-mpegurl; charset=utf-8",
+            PlaylistFormat::Xspf => "application/xspf+xml; charset=utf-8",
+            PlaylistFormat::Jspf => "application/jspf+json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u8 => "m3u8",
+            PlaylistFormat::Xspf => "xspf",
+            PlaylistFormat::Jspf => "jspf",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// "m3u8" (default), "xspf" or "jspf"
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// Guessed from the file when missing
+    pub format: Option<String>,
+    /// Name of the new playlist; the title in the file, if any, when missing
+    pub name: Option<String>,
+    pub is_public: Option<bool>,
+}
+
+/// A track of an imported playlist file, as much as the file tells of it
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportEntry {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_seconds: Option<i32>,
+    pub location: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UnmatchedEntry {
+    /// Place of the entry in the file, from 0
+    pub index: usize,
+    #[serde(flatten)]
+    pub entry: ImportEntry,
+}
+
+/// The playlist created by an import, and the entries no song was found for
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub playlist: Playlist,
+    pub entries: usize,
+    pub matched: usize,
+    /// Songs added; entries matching a song already added are not added twice
+    pub added: usize,
+    pub unmatched: Vec<UnmatchedEntry>,
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,94 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::{Insertable, Queryable};
+use diesel::sql_types::{BigInt, Integer, Text};
+use diesel::{QueryableByName, Selectable};
+use serde::{Deserialize, Serialize};
+
+use crate::models::song_models::SongResponse;
+
+// What a station was started from
+pub const SEED_SONG: &str = "song";
+pub const SEED_ARTIST: &str = "artist";
+pub const SEED_GENRE: &str = "genre";
+
+/// `GET /api/radio`: exactly one seed to start a station, or the `continuation` of a previous page
+#[derive(Deserialize)]
+pub struct RadioQuery {
+    pub seed_song: Option<String>,
+    pub seed_artist: Option<String>,
+    pub seed_genre: Option<i32>,
+    pub continuation: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Clone)]
+#[diesel(table_name = crate::schema::radio_stations)]
+pub struct RadioStation {
+    pub id: String,
+    pub user_id: String,
+    pub seed_type: String,
+    pub seed_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::radio_tracks)]
+pub struct NewRadioTrack {
+    pub station_id: String,
+    pub position: i32,
+    pub song_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::skips)]
+pub struct NewSkip {
+    pub id: String,
+    pub user_id: String,
+    pub song_id: String,
+    pub station_id: Option<String>,
+    pub skipped_at: NaiveDateTime,
+}
+
+/// Body of `POST /api/radio/skips`
+#[derive(Deserialize)]
+pub struct SkipRequest {
+    pub song_id: String,
+    /// Station the song was skipped on, if any
+    pub station_id: Option<String>,
+}
+
+/// A song a station could play next, with what its weight is made of
+#[derive(QueryableByName)]
+pub struct RadioCandidate {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub artist_id: String,
+    #[diesel(sql_type = BigInt)]
+    pub score: i64,
+    /// 1 if already on the station or played by the user lately
+    #[diesel(sql_type = BigInt)]
+    pub recent: i64,
+    #[diesel(sql_type = BigInt)]
+    pub song_skips: i64,
+    #[diesel(sql_type = BigInt)]
+    pub artist_skips: i64,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct RadioTrack {
+    #[diesel(sql_type = Integer)]
+    pub position: i32,
+    #[diesel(embed)]
+    pub song: SongResponse,
+}
+
+#[derive(Serialize)]
+pub struct RadioPage {
+    pub station_id: String,
+    pub seed_type: String,
+    pub seed_id: String,
+    pub tracks: Vec<RadioTrack>,
+    /// Pass as `continuation` to get the following tracks
+    pub next: String,
+}
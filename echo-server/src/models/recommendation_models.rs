@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::song_models::SongResponse;
+
+/// `?limit=20` on the recommendation endpoints
+#[derive(Deserialize)]
+pub struct RecommendationQuery {
+    pub limit: Option<i64>,
+}
+
+/// A recommended song with its score, higher is better
+#[derive(Serialize)]
+pub struct Recommendation {
+    pub score: i64,
+    pub song: SongResponse,
+}
+
+/// A personal playlist built from the user's taste.
+/// `id` is `discover` or `genre-{genre_id}`.
+#[derive(Serialize)]
+pub struct Mix {
+    pub id: String,
+    pub name: String,
+    pub songs: Vec<Recommendation>,
+}
@@ -0,0 +1,191 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::{Insertable, Queryable};
+use diesel::Selectable;
+use serde::{Deserialize, Serialize};
+
+/// Renditions generated at ingestion when `RENDITION_PROFILES` is not set
+const DEFAULT_RENDITION_PROFILES: &str = "mp3:128,opus:96";
+
+/// Bounds of the bitrate of on-the-fly transcodes, in kbps
+const MIN_TRANSCODE_BITRATE: i32 = 32;
+const MAX_TRANSCODE_BITRATE: i32 = 320;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenditionFormat {
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+}
+
+impl RenditionFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "mp3" => Some(RenditionFormat::Mp3),
+            "opus" => Some(RenditionFormat::Opus),
+            "aac" => Some(RenditionFormat::Aac),
+            "flac" => Some(RenditionFormat::Flac),
+            _ => None,
+        }
+    }
+
+    /// Format for a media type of an `Accept` header
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.trim().to_ascii_lowercase().as_str() {
+            "audio/mpeg" | "audio/mp3" => Some(RenditionFormat::Mp3),
+            "audio/ogg" | "audio/opus" => Some(RenditionFormat::Opus),
+            "audio/aac" | "audio/mp4" => Some(RenditionFormat::Aac),
+            "audio/flac" | "audio/x-flac" => Some(RenditionFormat::Flac),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenditionFormat::Mp3 => "mp3",
+            RenditionFormat::Opus => "opus",
+            RenditionFormat::Aac => "aac",
+            RenditionFormat::Flac => "flac",
+        }
+    }
+
+    /// ffmpeg encoder
+    pub fn codec(&self) -> &'static str {
+        match self {
+            RenditionFormat::Mp3 => "libmp3lame",
+            RenditionFormat::Opus => "libopus",
+            RenditionFormat::Aac => "aac",
+            RenditionFormat::Flac => "flac",
+        }
+    }
+
+    /// ffmpeg muxer
+    pub fn container(&self) -> &'static str {
+        match self {
+            RenditionFormat::Mp3 => "mp3",
+            RenditionFormat::Opus => "ogg",
+            RenditionFormat::Aac => "adts",
+            RenditionFormat::Flac => "flac",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenditionFormat::Mp3 => "mp3",
+            RenditionFormat::Opus => "opus",
+            RenditionFormat::Aac => "aac",
+            RenditionFormat::Flac => "flac",
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, RenditionFormat::Flac)
+    }
+
+    /// Bitrate in kbps used when transcoding on the fly without a `maxBitrate`
+    pub fn default_bitrate(&self) -> Option<i32> {
+        match self {
+            RenditionFormat::Mp3 => Some(192),
+            RenditionFormat::Opus => Some(128),
+            RenditionFormat::Aac => Some(192),
+            RenditionFormat::Flac => None,
+        }
+    }
+}
+
+/// One entry of `RENDITION_PROFILES`, e.g. `opus:96` or `flac`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenditionProfile {
+    pub format: RenditionFormat,
+    /// kbps; `None` for lossless formats
+    pub bitrate_kbps: Option<i32>,
+}
+
+impl RenditionProfile {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().splitn(2, ':');
+        let format = RenditionFormat::parse(parts.next()?)?;
+        let bitrate_kbps = match parts.next() {
+            Some(b) => Some(b.trim().parse::<i32>().ok().filter(|b| *b > 0)?),
+            None => None,
+        };
+
+        // Lossy formats need a bitrate, lossless ones cannot have one
+        if format.is_lossless() != bitrate_kbps.is_none() {
+            return None;
+        }
+        Some(Self { format, bitrate_kbps })
+    }
+
+    /// Parse the comma-separated `RENDITION_PROFILES` list (e.g. "mp3:128,opus:96,flac").
+    /// An empty value disables renditions.
+    pub fn list_from_env() -> Vec<Self> {
+        let value = std::env::var("RENDITION_PROFILES").unwrap_or_else(|_| DEFAULT_RENDITION_PROFILES.to_string());
+        value
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| Self::parse(p).unwrap_or_else(|| panic!("Invalid rendition profile '{}' in RENDITION_PROFILES", p)))
+            .collect()
+    }
+
+    /// Profile for an on-the-fly transcode, with the bitrate capped by `max_bitrate`
+    pub fn for_transcode(format: RenditionFormat, max_bitrate: Option<i32>) -> Self {
+        let bitrate_kbps = match (format.default_bitrate(), max_bitrate) {
+            (Some(default), Some(max)) => Some(default.min(max).clamp(MIN_TRANSCODE_BITRATE, MAX_TRANSCODE_BITRATE)),
+            (default, _) => default,
+        };
+        Self { format, bitrate_kbps }
+    }
+
+    /// Object key of this rendition of a song
+    pub fn object_key(&self, song_id: &str) -> String {
+        self.key_under("renditions", song_id)
+    }
+
+    /// Object key of an on-the-fly transcode of a song kept for repeat requests
+    pub fn cache_key(&self, song_id: &str) -> String {
+        self.key_under("transcode-cache", song_id)
+    }
+
+    fn key_under(&self, prefix: &str, song_id: &str) -> String {
+        match self.bitrate_kbps {
+            Some(bitrate) => format!("{}/{}/{}_{}.{}", prefix, song_id, self.format.name(), bitrate, self.format.extension()),
+            None => format!("{}/{}/{}.{}", prefix, song_id, self.format.name(), self.format.extension()),
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Serialize, Clone)]
+#[diesel(table_name = crate::schema::song_renditions)]
+pub struct Rendition {
+    pub id: String,
+    pub song_id: String,
+    pub format: String,
+    pub codec: String,
+    pub bitrate_kbps: Option<i32>,
+    pub size_bytes: i64,
+    #[serde(skip_serializing)]
+    pub object_key: String,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::song_renditions)]
+pub struct NewRendition {
+    pub id: String,
+    pub song_id: String,
+    pub format: String,
+    pub codec: String,
+    pub bitrate_kbps: Option<i32>,
+    pub size_bytes: i64,
+    pub object_key: String,
+}
+
+/// `?format=opus&maxBitrate=96` on `stream_song`; both are optional.
+/// `bitrate` is accepted as an alias of `maxBitrate`.
+#[derive(Deserialize)]
+pub struct RenditionQuery {
+    pub format: Option<String>,
+    #[serde(rename = "maxBitrate", alias = "bitrate")]
+    pub max_bitrate: Option<i32>,
+}
@@ -0,0 +1,61 @@
+use diesel::sql_types::{Integer, Nullable, Text};
+use diesel::QueryableByName;
+use serde::{Deserialize, Serialize};
+
+use crate::models::song_models::SongResponse;
+
+/// `?q=...&limit=5` on `/api/search`, `limit` applies to each group
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct AlbumHit {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Text)]
+    pub artist_id: String,
+    #[diesel(sql_type = Text)]
+    pub artist_name: String,
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub release_year: Option<i32>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub cover_url: Option<String>,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct ArtistHit {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub image_url: Option<String>,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct PlaylistHit {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub user_id: String,
+    #[diesel(sql_type = Text)]
+    pub owner: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub description: Option<String>,
+}
+
+/// Best matches of each kind, most relevant first
+#[derive(Serialize)]
+pub struct SearchResults {
+    pub songs: Vec<SongResponse>,
+    pub albums: Vec<AlbumHit>,
+    pub artists: Vec<ArtistHit>,
+    pub playlists: Vec<PlaylistHit>,
+}
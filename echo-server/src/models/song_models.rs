@@ -1,16 +1,25 @@
 use diesel::{prelude::QueryableByName, AsChangeset, Insertable, Queryable};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use diesel::sql_types::{Text, Integer, Nullable, Timestamp};
+use diesel::sql_types::{Float, Text, Integer, Nullable, Timestamp};
 
-#[derive(Deserialize)]
+/// Filters, sort and page of a song listing: `/api/songs`, album, favorite and playlist songs
+#[derive(Deserialize, Default)]
 pub struct SongQuery {
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    pub cursor: Option<String>,
     pub name: Option<String>,
     pub genre: Option<String>,
     pub artist: Option<String>,
-    pub sort: Option<String>, // e.g. "release_date" or "-release_date"
+    pub album: Option<String>,
+    /// Release year of the album, inclusive
+    pub year_from: Option<i32>,
+    pub year_to: Option<i32>,
+    /// Duration in seconds, inclusive
+    pub duration_min: Option<i32>,
+    pub duration_max: Option<i32>,
+    pub sort: Option<String>, // e.g. "release_date", "-release_date" or "artist,-year"
     pub random: Option<bool>,
 }
 
@@ -21,8 +30,12 @@ pub struct Song {
     pub artist_id: String,
     pub album_id: Option<String>,
     pub genre_id: Option<i32>,
+    pub track_number: Option<i32>,
     pub duration_seconds: i32,
     pub object_url: String,
+    pub loudness_lufs: Option<f32>,
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
@@ -36,9 +49,36 @@ pub struct NewSong {
     pub artist_id: String,
     pub album_id: Option<String>,
     pub genre_id: Option<i32>,
+    pub track_number: Option<i32>,
     pub duration_seconds: i32,
     #[serde(skip_deserializing)]
     pub object_url: String,
+    #[serde(skip_deserializing)]
+    pub loudness_lufs: Option<f32>,
+    #[serde(skip_deserializing)]
+    pub track_gain: Option<f32>,
+    #[serde(skip_deserializing)]
+    pub track_peak: Option<f32>,
+}
+
+/// `metadata` part of an upload. Every field is optional: whatever is missing is read
+/// from the file itself (duration and tags), explicit values always win over tags.
+#[derive(Deserialize)]
+pub struct UploadMetadata {
+    pub title: Option<String>,
+    pub artist_id: Option<String>,
+    /// Artist name, used when `artist_id` is not given. Unknown artists are created.
+    pub artist: Option<String>,
+    pub album_id: Option<String>,
+    /// Album name, used when `album_id` is not given. Unknown albums are created.
+    pub album: Option<String>,
+    pub genre_id: Option<i32>,
+    /// Genre name, used when `genre_id` is not given. Unknown genres are created.
+    pub genre: Option<String>,
+    /// Release year, recorded on albums created from this upload
+    pub year: Option<i32>,
+    pub track_number: Option<i32>,
+    pub duration_seconds: Option<i32>,
 }
 
 #[derive(AsChangeset, serde::Deserialize)]
@@ -48,6 +88,7 @@ pub struct UpdateSong {
     pub artist_id: Option<String>,
     pub album_id: Option<String>,
     pub genre_id: Option<i32>,
+    pub track_number: Option<i32>,
     pub duration_seconds: Option<i32>,
 }
 
@@ -74,12 +115,27 @@ pub struct SongResponse {
     #[diesel(sql_type = Nullable<Text>)]
     pub genre_name: Option<String>,
 
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub track_number: Option<i32>,
+
     #[diesel(sql_type = Integer)]
     pub duration_seconds: i32,
 
+    // Only admins see the storage URL, everyone else streams through signed URLs
     #[diesel(sql_type = Text)]
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub object_url: String,
 
+    // ReplayGain, for clients to apply: gain in dB towards the loudness target, linear peak
+    #[diesel(sql_type = Nullable<Float>)]
+    pub track_gain: Option<f32>,
+    #[diesel(sql_type = Nullable<Float>)]
+    pub track_peak: Option<f32>,
+    #[diesel(sql_type = Nullable<Float>)]
+    pub album_gain: Option<f32>,
+    #[diesel(sql_type = Nullable<Float>)]
+    pub album_peak: Option<f32>,
+
     #[diesel(sql_type = Nullable<Timestamp>)]
     pub created_at: Option<NaiveDateTime>,
     #[diesel(sql_type = Nullable<Timestamp>)]
@@ -99,12 +155,50 @@ impl From<Song> for SongResponse {
             
             genre_id: s.genre_id,
             genre_name: None, 
+
+            track_number: s.track_number,
             
             duration_seconds: s.duration_seconds,
             object_url: s.object_url,
+
+            track_gain: s.track_gain,
+            track_peak: s.track_peak,
+            album_gain: None,
+            album_peak: None,
             
             created_at: s.created_at,   
             updated_at: s.updated_at, 
         }
     }
 }
+
+impl SongResponse {
+    /// Strip the permanent storage URL unless the viewer is an admin.
+    pub fn for_viewer(mut self, is_admin: bool) -> Self {
+        if !is_admin {
+            self.object_url = String::new();
+        }
+        self
+    }
+}
+
+// --------------------- Upload Report Models ---------------------
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadItemStatus {
+    Created,
+    Failed,
+    /// Processed fine, but undone because another item failed
+    RolledBack,
+    /// Never processed because an earlier item failed
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UploadItemResult {
+    pub index: usize,
+    pub title: String,
+    pub status: UploadItemStatus,
+    pub song_id: Option<String>,
+    pub error: Option<String>,
+}
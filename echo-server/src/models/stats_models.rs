@@ -0,0 +1,115 @@
+use chrono::NaiveDate;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+use diesel::QueryableByName;
+use serde::{Deserialize, Serialize};
+
+/// `?from=2025-01-01&to=2025-03-31&tz_offset=60&limit=10` on the stats endpoints.
+/// Dates are inclusive days in the user's time zone, given as minutes east of UTC.
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub tz_offset: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+/// `?tz_offset=60` on the yearly summary
+#[derive(Deserialize)]
+pub struct YearlyStatsQuery {
+    pub tz_offset: Option<i32>,
+}
+
+/// What `/stats/top/{kind}` ranks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopKind {
+    Songs,
+    Artists,
+    Albums,
+    Genres,
+}
+
+impl TopKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "songs" => Some(TopKind::Songs),
+            "artists" => Some(TopKind::Artists),
+            "albums" => Some(TopKind::Albums),
+            "genres" => Some(TopKind::Genres),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TopKind::Songs => "songs",
+            TopKind::Artists => "artists",
+            TopKind::Albums => "albums",
+            TopKind::Genres => "genres",
+        }
+    }
+}
+
+/// A ranked song, artist, album or genre. `detail` is the artist of songs and albums.
+#[derive(QueryableByName, Serialize)]
+pub struct TopItem {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[diesel(sql_type = BigInt)]
+    pub plays: i64,
+    #[diesel(sql_type = BigInt)]
+    pub seconds: i64,
+}
+
+/// Plays in one hour of the day (0-23), day of the week (0 = Monday) or month (1-12)
+#[derive(QueryableByName, Serialize, Clone, Copy)]
+pub struct StatsBucket {
+    #[diesel(sql_type = Integer)]
+    pub bucket: i32,
+    #[diesel(sql_type = BigInt)]
+    pub plays: i64,
+    #[diesel(sql_type = BigInt)]
+    pub seconds: i64,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct ListeningSummary {
+    #[diesel(sql_type = BigInt)]
+    pub plays: i64,
+    /// Seconds reported by clients, or the song's duration when they did not say
+    #[diesel(sql_type = BigInt)]
+    pub seconds: i64,
+    #[diesel(sql_type = BigInt)]
+    pub distinct_songs: i64,
+    #[diesel(sql_type = BigInt)]
+    pub distinct_artists: i64,
+    #[diesel(sql_type = BigInt)]
+    pub active_days: i64,
+}
+
+#[derive(QueryableByName, Serialize)]
+pub struct BusiestDay {
+    #[diesel(sql_type = Text)]
+    pub day: String,
+    #[diesel(sql_type = BigInt)]
+    pub plays: i64,
+}
+
+/// Everything about a year of listening, in one document
+#[derive(Serialize)]
+pub struct YearlySummary {
+    pub year: i32,
+    pub summary: ListeningSummary,
+    pub top_songs: Vec<TopItem>,
+    pub top_artists: Vec<TopItem>,
+    pub top_albums: Vec<TopItem>,
+    pub top_genres: Vec<TopItem>,
+    pub by_month: Vec<StatsBucket>,
+    pub by_hour: Vec<StatsBucket>,
+    pub by_weekday: Vec<StatsBucket>,
+    pub busiest_day: Option<BusiestDay>,
+}
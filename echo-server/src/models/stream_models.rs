@@ -0,0 +1,34 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// How `stream_song` delivers audio, chosen per deployment with `STREAM_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMode {
+    /// 302 redirect to the object URL for admins, proxy for everyone else (default)
+    Redirect,
+    /// Proxy the object through the API with HTTP Range support
+    Proxy,
+}
+
+impl StreamMode {
+    pub fn from_env() -> Self {
+        match std::env::var("STREAM_MODE").as_deref() {
+            Ok("proxy") => StreamMode::Proxy,
+            Ok("redirect") | Err(_) => StreamMode::Redirect,
+            Ok(other) => panic!("Invalid STREAM_MODE '{}': expected 'redirect' or 'proxy'", other),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SignedStreamQuery {
+    pub uid: String,
+    pub exp: i64,
+    pub sig: String,
+}
+
+#[derive(Serialize)]
+pub struct StreamUrlResponse {
+    pub url: String,
+    pub expires_at: NaiveDateTime,
+}
@@ -0,0 +1,321 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text, Timestamp};
+use diesel::QueryableByName;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// Subsonic API version implemented by the `/rest` layer
+pub const SUBSONIC_API_VERSION: &str = "1.16.1";
+
+// Error codes defined by the Subsonic API
+pub const SUBSONIC_ERROR_GENERIC: i32 = 0;
+pub const SUBSONIC_ERROR_MISSING_PARAMETER: i32 = 10;
+pub const SUBSONIC_ERROR_WRONG_CREDENTIALS: i32 = 40;
+pub const SUBSONIC_ERROR_NOT_AUTHORIZED: i32 = 50;
+pub const SUBSONIC_ERROR_NOT_FOUND: i32 = 70;
+
+// --------------------- Request Models ---------------------
+
+/// Query string and form parameters of a Subsonic call. Parameters such as `id` or `songId`
+/// may repeat, so they are kept as a list of pairs.
+pub struct SubsonicParams {
+    pairs: Vec<(String, String)>,
+}
+
+impl SubsonicParams {
+    pub fn new(pairs: Vec<(String, String)>) -> Self {
+        Self { pairs }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.pairs.iter().filter(|(k, _)| k == name).map(|(_, v)| v.as_str()).collect()
+    }
+
+    pub fn require(&self, name: &str) -> Result<&str, SubsonicError> {
+        self.get(name).ok_or_else(|| SubsonicError::missing(name))
+    }
+
+    /// Numeric parameter; malformed values count as absent
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.get(name).and_then(|v| v.trim().parse().ok())
+    }
+}
+
+/// JSONP callbacks are written as-is before the body, so only plain (dotted) identifiers are accepted
+static JSONP_CALLBACK: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z_$][A-Za-z0-9_$.]*$").unwrap());
+
+/// Response format picked by the `f` parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubsonicFormat {
+    Xml,
+    Json,
+    /// JSON wrapped in a call to the named function
+    Jsonp(String),
+}
+
+impl SubsonicFormat {
+    pub fn from_params(params: &SubsonicParams) -> Self {
+        match params.get("f") {
+            Some("json") => SubsonicFormat::Json,
+            // Without a usable callback the body is plain JSON
+            Some("jsonp") => match params.get("callback") {
+                Some(callback) if JSONP_CALLBACK.is_match(callback) => SubsonicFormat::Jsonp(callback.to_string()),
+                _ => SubsonicFormat::Json,
+            },
+            _ => SubsonicFormat::Xml,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubsonicError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl SubsonicError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn missing(parameter: &str) -> Self {
+        Self::new(SUBSONIC_ERROR_MISSING_PARAMETER, format!("Required parameter is missing: {}", parameter))
+    }
+
+    pub fn not_found(what: &str) -> Self {
+        Self::new(SUBSONIC_ERROR_NOT_FOUND, format!("{} not found", what))
+    }
+
+    pub fn database() -> Self {
+        Self::new(SUBSONIC_ERROR_GENERIC, "Database error")
+    }
+}
+
+// --------------------- Row Models ---------------------
+
+#[derive(QueryableByName)]
+pub struct SubsonicArtistRow {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = BigInt)]
+    pub album_count: i64,
+}
+
+#[derive(QueryableByName)]
+pub struct SubsonicAlbumRow {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Text)]
+    pub artist_id: String,
+    #[diesel(sql_type = Text)]
+    pub artist_name: String,
+    #[diesel(sql_type = Nullable<Integer>)]
+    pub release_year: Option<i32>,
+    #[diesel(sql_type = BigInt)]
+    pub song_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub duration: i64,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(QueryableByName)]
+pub struct SubsonicPlaylistRow {
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub description: Option<String>,
+    #[diesel(sql_type = Nullable<Bool>)]
+    pub is_public: Option<bool>,
+    #[diesel(sql_type = Text)]
+    pub user_id: String,
+    #[diesel(sql_type = Text)]
+    pub owner: String,
+    #[diesel(sql_type = BigInt)]
+    pub song_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub duration: i64,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub created_at: Option<NaiveDateTime>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+// --------------------- Response Models ---------------------
+// Field names follow the Subsonic API. In XML, scalar fields become attributes and
+// lists become repeated child elements named after the field.
+
+#[derive(Serialize)]
+pub struct License {
+    pub valid: bool,
+}
+
+#[derive(Serialize)]
+pub struct MusicFolder {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct MusicFolders {
+    #[serde(rename = "musicFolder")]
+    pub music_folder: Vec<MusicFolder>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsonicArtist {
+    pub id: String,
+    pub name: String,
+    pub album_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct ArtistIndex {
+    pub name: String,
+    pub artist: Vec<SubsonicArtist>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistsId3 {
+    pub ignored_articles: String,
+    pub index: Vec<ArtistIndex>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayGain {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_gain: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track_peak: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_gain: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_peak: Option<f32>,
+}
+
+/// A song, called "Child" by the Subsonic API
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsonicSong {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub is_dir: bool,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    pub duration: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album_id: Option<String>,
+    pub artist_id: String,
+    #[serde(rename = "type")]
+    pub media_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starred: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay_gain: Option<ReplayGain>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsonicAlbum {
+    pub id: String,
+    pub name: String,
+    pub artist: String,
+    pub artist_id: String,
+    pub song_count: i64,
+    pub duration: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// Only filled by `getAlbum`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub song: Vec<SubsonicSong>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsonicPlaylist {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub owner: String,
+    pub public: bool,
+    pub song_count: i64,
+    pub duration: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<String>,
+    /// Only filled by `getPlaylist` and `createPlaylist`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entry: Vec<SubsonicSong>,
+}
+
+#[derive(Serialize)]
+pub struct Playlists {
+    pub playlist: Vec<SubsonicPlaylist>,
+}
+
+/// Body of `searchResult3`, `starred` and `starred2`
+#[derive(Serialize)]
+pub struct SubsonicSearchResult {
+    pub artist: Vec<SubsonicArtist>,
+    pub album: Vec<SubsonicAlbum>,
+    pub song: Vec<SubsonicSong>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_for(callback: &str) -> SubsonicFormat {
+        let params = SubsonicParams::new(vec![
+            ("f".to_string(), "jsonp".to_string()),
+            ("callback".to_string(), callback.to_string()),
+        ]);
+        SubsonicFormat::from_params(&params)
+    }
+
+    #[test]
+    fn jsonp_accepts_identifiers() {
+        assert_eq!(format_for("cb"), SubsonicFormat::Jsonp("cb".to_string()));
+        assert_eq!(format_for("$jq.cb_1"), SubsonicFormat::Jsonp("$jq.cb_1".to_string()));
+    }
+
+    #[test]
+    fn jsonp_rejects_scripts() {
+        assert_eq!(format_for("alert(1);x"), SubsonicFormat::Json);
+        assert_eq!(format_for("1cb"), SubsonicFormat::Json);
+        assert_eq!(format_for(""), SubsonicFormat::Json);
+        assert_eq!(format_for("cb\n"), SubsonicFormat::Json);
+    }
+}
@@ -18,6 +18,7 @@ pub struct User {
     pub id: String,
     pub username: String,
     pub password_hash: String,
+    pub subsonic_password: Option<String>,
     pub avatar_url: Option<String>,
     pub is_admin: bool,
     pub created_at: Option<chrono::NaiveDateTime>,
@@ -33,6 +34,13 @@ pub struct UserResponse {
     pub updated_at: Option<NaiveDateTime>,
 }
 
+// Returned once when a Subsonic password is generated
+#[derive(Serialize)]
+pub struct SubsonicPasswordResponse {
+    pub username: String,
+    pub subsonic_password: String,
+}
+
 // Payload for creating a new user
 #[derive(Deserialize)]
 pub struct CreateUser {
@@ -0,0 +1,19 @@
+use actix_web::web;
+
+use crate::handlers::artist_handlers::{
+    list_artists, create_artist, get_artist, update_artist, delete_artist,
+    get_artist_albums, get_artist_songs
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/artists")
+            .route("", web::get().to(list_artists))
+            .route("", web::post().to(create_artist))
+            .route("/{artist_id}", web::get().to(get_artist))
+            .route("/{artist_id}", web::put().to(update_artist))
+            .route("/{artist_id}", web::delete().to(delete_artist))
+            .route("/{artist_id}/albums", web::get().to(get_artist_albums))
+            .route("/{artist_id}/songs", web::get().to(get_artist_songs))
+    );
+}
@@ -0,0 +1,11 @@
+use actix_web::web;
+
+use crate::handlers::backup_handlers::{download_backup, restore_backup};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/backup")
+            .route("", web::get().to(download_backup))
+            .route("/restore", web::post().to(restore_backup))
+    );
+}
@@ -0,0 +1,16 @@
+use actix_web::web;
+
+use crate::handlers::genre_handlers::{
+    list_genres, create_genre, rename_genre, delete_genre, get_genre_songs
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/genres")
+            .route("", web::get().to(list_genres))
+            .route("", web::post().to(create_genre))
+            .route("/{genre_id}", web::put().to(rename_genre))
+            .route("/{genre_id}", web::delete().to(delete_genre))
+            .route("/{genre_id}/songs", web::get().to(get_genre_songs))
+    );
+}
@@ -0,0 +1,10 @@
+use actix_web::web;
+
+use crate::handlers::ingest_handlers::get_ingest_job;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/ingest/jobs")
+            .route("/{job_id}", web::get().to(get_ingest_job))
+    );
+}
@@ -4,14 +4,37 @@ pub mod session_routes;
 pub mod user_routes;
 pub mod song_routes;
 pub mod album_routes;
+pub mod artist_routes;
+pub mod genre_routes;
+pub mod ingest_routes;
+pub mod subsonic_routes;
+pub mod play_routes;
+pub mod stats_routes;
+pub mod recommendation_routes;
+pub mod radio_routes;
+pub mod search_routes;
+pub mod public_playlist_routes;
+pub mod backup_routes;
 
 use actix_web::web;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     playlist_routes::configure(cfg);
     favorite_routes::configure(cfg);
+    // Before user_routes, whose `/users` scope would otherwise take `/users/{user_id}/history`,
+    // `/users/{user_id}/stats` and `/users/{user_id}/mixes`
+    play_routes::configure(cfg);
+    stats_routes::configure(cfg);
+    recommendation_routes::configure(cfg);
     session_routes::configure(cfg);
     user_routes::configure(cfg);
     song_routes::configure(cfg);
     album_routes::configure(cfg);
+    artist_routes::configure(cfg);
+    genre_routes::configure(cfg);
+    ingest_routes::configure(cfg);
+    radio_routes::configure(cfg);
+    search_routes::configure(cfg);
+    public_playlist_routes::configure(cfg);
+    backup_routes::configure(cfg);
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+use actix_web::web;
+
+use crate::handlers::play_handlers::{get_history, list_now_playing, report_play, update_now_playing};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/plays")
+            .route("", web::post().to(report_play))
+            .route("/now-playing", web::get().to(list_now_playing))
+            .route("/now-playing", web::post().to(update_now_playing))
+    );
+    cfg.service(
+        web::scope("/users/{user_id}/history")
+            .route("", web::get().to(get_history))
+    );
+}
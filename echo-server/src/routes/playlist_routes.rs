@@ -2,7 +2,9 @@ use actix_web::web;
 
 use crate::handlers::playlist_handlers::{
     list_playlists, create_playlist, get_playlist, update_playlist, delete_playlist,
-    list_playlist_songs, add_song_to_playlist, remove_song_from_playlist
+    list_playlist_songs, add_song_to_playlist, move_playlist_song, reorder_playlist_songs, remove_song_from_playlist,
+    list_playlist_members, set_playlist_member, remove_playlist_member, list_followed_playlists,
+    create_smart_playlist, update_playlist_rules, import_playlist, export_playlist
 };
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -10,14 +12,26 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
     web::scope("/users/{user_id}/playlists")
         .route("", web::get().to(list_playlists))
         .route("", web::post().to(create_playlist))
+        // Before "/{playlist_id}", which would take it
+        .route("/followed", web::get().to(list_followed_playlists))
+        .route("/smart", web::post().to(create_smart_playlist))
+        .route("/import", web::post().to(import_playlist))
         .route("/{playlist_id}", web::get().to(get_playlist))
         .route("/{playlist_id}", web::put().to(update_playlist))
         .route("/{playlist_id}", web::delete().to(delete_playlist))
+        .route("/{playlist_id}/rules", web::put().to(update_playlist_rules))
+        .route("/{playlist_id}/export", web::get().to(export_playlist))
+        // Members (shared with other users)
+        .route("/{playlist_id}/members", web::get().to(list_playlist_members))
+        .route("/{playlist_id}/members/{member_id}", web::put().to(set_playlist_member))
+        .route("/{playlist_id}/members/{member_id}", web::delete().to(remove_playlist_member))
         // Songs in Playlist
         .service(
             web::scope("/{playlist_id}/songs")
                 .route("", web::get().to(list_playlist_songs))
                 .route("", web::post().to(add_song_to_playlist))
+                .route("", web::put().to(reorder_playlist_songs))
+                .route("/{song_id}", web::patch().to(move_playlist_song))
                 .route("/{song_id}", web::delete().to(remove_song_from_playlist))
         )
 );
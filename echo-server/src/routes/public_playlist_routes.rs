@@ -0,0 +1,13 @@
+use actix_web::web;
+
+use crate::handlers::playlist_handlers::{copy_playlist, follow_playlist, list_public_playlists, unfollow_playlist};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/playlists")
+            .route("/public", web::get().to(list_public_playlists))
+            .route("/{playlist_id}/follow", web::post().to(follow_playlist))
+            .route("/{playlist_id}/follow", web::delete().to(unfollow_playlist))
+            .route("/{playlist_id}/copy", web::post().to(copy_playlist))
+    );
+}
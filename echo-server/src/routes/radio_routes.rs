@@ -0,0 +1,11 @@
+use actix_web::web;
+
+use crate::handlers::radio_handlers::{get_radio, report_skip};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/radio")
+            .route("", web::get().to(get_radio))
+            .route("/skips", web::post().to(report_skip))
+    );
+}
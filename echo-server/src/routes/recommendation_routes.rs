@@ -0,0 +1,10 @@
+use actix_web::web;
+
+use crate::handlers::recommendation_handlers::get_mixes;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/mixes")
+            .route("", web::get().to(get_mixes))
+    );
+}
@@ -1,8 +1,10 @@
 use actix_web::web;
 
 use crate::handlers::song_handlers::{
-    list_songs, get_song, create_one_or_more_songs, update_song, delete_song, stream_song
+    list_songs, get_song, create_one_or_more_songs, update_song, delete_song, stream_song,
+    get_stream_url, stream_signed_song, list_song_renditions
 };
+use crate::handlers::recommendation_handlers::get_similar_songs;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -13,5 +15,13 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("/{song_id}", web::put().to(update_song))
             .route("/{song_id}", web::delete().to(delete_song))
             .route("/{song_id}/stream", web::get().to(stream_song))
+            .route("/{song_id}/stream-url", web::get().to(get_stream_url))
+            .route("/{song_id}/renditions", web::get().to(list_song_renditions))
+            .route("/{song_id}/similar", web::get().to(get_similar_songs))
+    );
+    // Signed, session-less stream URLs issued by `/songs/{song_id}/stream-url`
+    cfg.service(
+        web::scope("/stream")
+            .route("/{song_id}", web::get().to(stream_signed_song))
     );
 }
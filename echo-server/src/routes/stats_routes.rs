@@ -0,0 +1,16 @@
+use actix_web::web;
+
+use crate::handlers::stats_handlers::{
+    get_listening_by_hour, get_listening_by_weekday, get_stats_summary, get_top, get_yearly_summary,
+};
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/users/{user_id}/stats")
+            .route("/summary", web::get().to(get_stats_summary))
+            .route("/top/{kind}", web::get().to(get_top))
+            .route("/hours", web::get().to(get_listening_by_hour))
+            .route("/weekdays", web::get().to(get_listening_by_weekday))
+            .route("/years/{year}", web::get().to(get_yearly_summary))
+    );
+}
@@ -0,0 +1,13 @@
+use actix_web::web;
+
+use crate::handlers::subsonic_handlers::subsonic;
+
+/// Subsonic-compatible API, mounted at `/rest` outside of `/api`.
+/// Clients call `/rest/{method}` or `/rest/{method}.view`, with GET or form POST.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/{method}")
+            .route(web::get().to(subsonic))
+            .route(web::post().to(subsonic))
+    );
+}
@@ -1,11 +1,13 @@
 use actix_web::web::{self};
 
-use crate::handlers::user_handlers::{update_user, create_user};
+use crate::handlers::user_handlers::{update_user, create_user, regenerate_subsonic_password, delete_subsonic_password};
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .route("", web::post().to(create_user))
             .route("/{user_id}", web::patch().to(update_user))
+            .route("/{user_id}/subsonic-password", web::post().to(regenerate_subsonic_password))
+            .route("/{user_id}/subsonic-password", web::delete().to(delete_subsonic_password))
     );
 }
\ No newline at end of file
@@ -11,6 +11,8 @@ diesel::table! {
         release_year -> Nullable<Integer>,
         cover_url -> Nullable<Text>,
         created_at -> Nullable<Timestamp>,
+        album_gain -> Nullable<Float>,
+        album_peak -> Nullable<Float>,
     }
 }
 
@@ -44,6 +46,60 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    ingest_jobs (id) {
+        #[max_length = 36]
+        id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 20]
+        status -> Varchar,
+        total_items -> Integer,
+        processed_items -> Integer,
+        items_json -> Text,
+        report_json -> Nullable<Text>,
+        song_ids_json -> Nullable<Text>,
+        error_message -> Nullable<Text>,
+        created_at -> Nullable<Timestamp>,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    now_playing (user_id) {
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 36]
+        song_id -> Char,
+        #[max_length = 100]
+        client -> Nullable<Varchar>,
+        started_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    playlist_follows (playlist_id, user_id) {
+        #[max_length = 36]
+        playlist_id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        followed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    playlist_members (playlist_id, user_id) {
+        #[max_length = 36]
+        playlist_id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 10]
+        role -> Varchar,
+        added_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     playlist_songs (playlist_id, song_id) {
         #[max_length = 36]
@@ -52,6 +108,8 @@ diesel::table! {
         song_id -> Char,
         position -> Integer,
         added_at -> Nullable<Timestamp>,
+        #[max_length = 36]
+        added_by -> Nullable<Char>,
     }
 }
 
@@ -67,6 +125,50 @@ diesel::table! {
         is_public -> Nullable<Bool>,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
+        #[max_length = 10]
+        kind -> Varchar,
+        rules -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    plays (id) {
+        #[max_length = 36]
+        id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 36]
+        song_id -> Char,
+        played_at -> Timestamp,
+        seconds_listened -> Nullable<Integer>,
+        #[max_length = 100]
+        client -> Nullable<Varchar>,
+        #[max_length = 10]
+        source -> Varchar,
+    }
+}
+
+diesel::table! {
+    radio_stations (id) {
+        #[max_length = 36]
+        id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 10]
+        seed_type -> Varchar,
+        #[max_length = 36]
+        seed_id -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    radio_tracks (station_id, position) {
+        #[max_length = 36]
+        station_id -> Char,
+        position -> Integer,
+        #[max_length = 36]
+        song_id -> Char,
     }
 }
 
@@ -83,6 +185,38 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    skips (id) {
+        #[max_length = 36]
+        id -> Char,
+        #[max_length = 36]
+        user_id -> Char,
+        #[max_length = 36]
+        song_id -> Char,
+        #[max_length = 36]
+        station_id -> Nullable<Char>,
+        skipped_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    song_renditions (id) {
+        #[max_length = 36]
+        id -> Char,
+        #[max_length = 36]
+        song_id -> Char,
+        #[max_length = 10]
+        format -> Varchar,
+        #[max_length = 20]
+        codec -> Varchar,
+        bitrate_kbps -> Nullable<Integer>,
+        size_bytes -> Bigint,
+        #[max_length = 255]
+        object_key -> Varchar,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     songs (id) {
         #[max_length = 36]
@@ -94,14 +228,28 @@ diesel::table! {
         #[max_length = 36]
         album_id -> Nullable<Char>,
         genre_id -> Nullable<Integer>,
+        track_number -> Nullable<Integer>,
         duration_seconds -> Integer,
         #[max_length = 255]
         object_url -> Varchar,
+        loudness_lufs -> Nullable<Float>,
+        track_gain -> Nullable<Float>,
+        track_peak -> Nullable<Float>,
         created_at -> Nullable<Timestamp>,
         updated_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::table! {
+    transcode_cache (object_key) {
+        #[max_length = 255]
+        object_key -> Varchar,
+        #[max_length = 36]
+        song_id -> Char,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         #[max_length = 36]
@@ -109,6 +257,8 @@ diesel::table! {
         #[max_length = 50]
         username -> Varchar,
         password_hash -> Text,
+        #[max_length = 64]
+        subsonic_password -> Nullable<Varchar>,
         avatar_url -> Nullable<Text>,
         is_admin -> Bool,
         created_at -> Nullable<Timestamp>,
@@ -119,22 +269,50 @@ diesel::table! {
 diesel::joinable!(albums -> artists (artist_id));
 diesel::joinable!(favorites -> songs (song_id));
 diesel::joinable!(favorites -> users (user_id));
+diesel::joinable!(ingest_jobs -> users (user_id));
+diesel::joinable!(now_playing -> songs (song_id));
+diesel::joinable!(now_playing -> users (user_id));
+diesel::joinable!(playlist_follows -> playlists (playlist_id));
+diesel::joinable!(playlist_follows -> users (user_id));
+diesel::joinable!(playlist_members -> playlists (playlist_id));
+diesel::joinable!(playlist_members -> users (user_id));
 diesel::joinable!(playlist_songs -> playlists (playlist_id));
 diesel::joinable!(playlist_songs -> songs (song_id));
+diesel::joinable!(playlist_songs -> users (added_by));
 diesel::joinable!(playlists -> users (user_id));
+diesel::joinable!(plays -> songs (song_id));
+diesel::joinable!(plays -> users (user_id));
+diesel::joinable!(radio_stations -> users (user_id));
+diesel::joinable!(radio_tracks -> radio_stations (station_id));
+diesel::joinable!(radio_tracks -> songs (song_id));
 diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(skips -> radio_stations (station_id));
+diesel::joinable!(skips -> songs (song_id));
+diesel::joinable!(skips -> users (user_id));
+diesel::joinable!(song_renditions -> songs (song_id));
 diesel::joinable!(songs -> albums (album_id));
 diesel::joinable!(songs -> artists (artist_id));
 diesel::joinable!(songs -> genres (genre_id));
+diesel::joinable!(transcode_cache -> songs (song_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     albums,
     artists,
     favorites,
     genres,
+    ingest_jobs,
+    now_playing,
+    playlist_follows,
+    playlist_members,
     playlist_songs,
     playlists,
+    plays,
+    radio_stations,
+    radio_tracks,
     sessions,
+    skips,
+    song_renditions,
     songs,
+    transcode_cache,
     users,
 );
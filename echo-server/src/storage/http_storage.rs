@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use reqwest::{header, StatusCode};
+
+use crate::storage::{ByteStream, ObjectBody, ObjectMeta, ObjectStorage, StorageError};
+use crate::utils::range_utils::ByteRange;
+
+/// Object storage reached over plain HTTP: objects are uploaded with PUT under the
+/// write base URL and read/deleted under the read base URL (e.g. pre-authenticated
+/// bucket URLs).
+pub struct HttpStorage {
+    client: reqwest::Client,
+    write_base: String,
+    read_base: String,
+}
+
+impl HttpStorage {
+    pub fn new(write_base: String, read_base: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            write_base: write_base.trim_end_matches('/').to_string(),
+            read_base: read_base.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn read_url(&self, key: &str) -> String {
+        format!("{}/{}", self.read_base, key)
+    }
+
+    fn meta_from_headers(key: &str, headers: &header::HeaderMap, size: u64) -> ObjectMeta {
+        let get = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        ObjectMeta {
+            key: key.to_string(),
+            size,
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+            content_type: get(header::CONTENT_TYPE),
+        }
+    }
+}
+
+fn backend_error(e: reqwest::Error) -> StorageError {
+    StorageError::Backend(e.to_string())
+}
+
+fn status_error(status: StatusCode) -> StorageError {
+    if status == StatusCode::NOT_FOUND {
+        StorageError::NotFound
+    } else {
+        StorageError::Backend(format!("Object storage answered {}", status))
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for HttpStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        let res = self.client
+            .put(format!("{}/{}", self.write_base, key))
+            .header(header::CONTENT_TYPE, content_type)
+            .body(data)
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        if !res.status().is_success() {
+            return Err(status_error(res.status()));
+        }
+        Ok(())
+    }
+
+    async fn put_stream(&self, key: &str, data: ByteStream, content_type: &str) -> Result<(), StorageError> {
+        // Sent chunked; an error in `data` aborts the request before it completes
+        let res = self.client
+            .put(format!("{}/{}", self.write_base, key))
+            .header(header::CONTENT_TYPE, content_type)
+            .body(reqwest::Body::wrap_stream(data))
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        if !res.status().is_success() {
+            return Err(status_error(res.status()));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectBody, StorageError> {
+        // The total size is needed for Content-Range, so read it first
+        let meta = self.head(key).await?;
+
+        let mut req = self.client.get(self.read_url(key));
+        if let Some(r) = range {
+            req = req.header(header::RANGE, r.range_header());
+        }
+        let res = req.send().await.map_err(backend_error)?;
+
+        let expected = if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK };
+        if res.status() != expected {
+            return Err(status_error(res.status()));
+        }
+
+        Ok(ObjectBody {
+            meta,
+            range,
+            stream: Box::pin(res.bytes_stream().map_err(backend_error)),
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectMeta, StorageError> {
+        let res = self.client
+            .head(self.read_url(key))
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        if !res.status().is_success() {
+            return Err(status_error(res.status()));
+        }
+
+        let size = res.content_length()
+            .ok_or_else(|| StorageError::Backend("Object storage did not report the object size".to_string()))?;
+        Ok(Self::meta_from_headers(key, res.headers(), size))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        // Deletion goes through the read URL, as the bucket's read URL is the one allowing DELETE
+        let res = self.client
+            .delete(self.read_url(key))
+            .send()
+            .await
+            .map_err(backend_error)?;
+
+        if !res.status().is_success() {
+            return Err(status_error(res.status()));
+        }
+        Ok(())
+    }
+
+    async fn list(&self, _prefix: &str) -> Result<Vec<ObjectMeta>, StorageError> {
+        // Plain HTTP object URLs have no standard listing API
+        Err(StorageError::Unsupported("list"))
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        self.read_url(key)
+    }
+
+    fn key_for_url(&self, url: &str) -> Option<String> {
+        url.strip_prefix(&self.read_base)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+    }
+
+    fn public_url(&self, key: &str) -> Option<String> {
+        Some(self.read_url(key))
+    }
+}
@@ -0,0 +1,181 @@
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::storage::{content_type_for_key, ByteStream, ObjectBody, ObjectMeta, ObjectStorage, StorageError};
+use crate::utils::range_utils::ByteRange;
+
+const URL_SCHEME: &str = "local://";
+
+/// Object storage on the local filesystem, rooted at `LOCAL_STORAGE_ROOT`.
+/// Meant for development and CI, where no external bucket is available.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve a key to a path under the root, refusing anything that could escape it
+    fn path_for(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let valid = !key.is_empty()
+            && !key.contains('\\')
+            && key.split('/').all(|part| !part.is_empty() && part != "." && part != "..");
+        if !valid {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+        Ok(self.root.join(key))
+    }
+
+    async fn meta_for(&self, key: &str, path: &Path) -> Result<ObjectMeta, StorageError> {
+        let metadata = tokio::fs::metadata(path).await?;
+        if !metadata.is_file() {
+            return Err(StorageError::NotFound);
+        }
+
+        let modified: Option<DateTime<Utc>> = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: metadata.len(),
+            // Size and mtime change whenever the object is rewritten
+            etag: modified.map(|m| {
+                format!("\"{:x}-{:x}\"", metadata.len(), m.timestamp_nanos_opt().unwrap_or(0))
+            }),
+            last_modified: modified.map(|m| m.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+            content_type: Some(content_type_for_key(key).to_string()),
+        })
+    }
+}
+
+#[async_trait]
+impl ObjectStorage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        let chunk: Result<Bytes, StorageError> = Ok(Bytes::from(data));
+        self.put_stream(key, Box::pin(futures::stream::once(async { chunk })), content_type).await
+    }
+
+    async fn put_stream(&self, key: &str, mut data: ByteStream, _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Write to a temp file first so readers never see a partial object
+        let tmp_path = path.with_file_name(format!(
+            ".tmp-{}-{}",
+            Uuid::new_v4(),
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("object")
+        ));
+        let written: Result<(), StorageError> = async {
+            let mut file = tokio::fs::File::create(&tmp_path).await?;
+            while let Some(chunk) = data.try_next().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.sync_all().await?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e);
+        }
+        if let Err(e) = tokio::fs::rename(&tmp_path, &path).await {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectBody, StorageError> {
+        let path = self.path_for(key)?;
+        let meta = self.meta_for(key, &path).await?;
+        let mut file = tokio::fs::File::open(&path).await?;
+
+        let (start, len) = match range {
+            Some(r) => (r.start, r.content_length()),
+            None => (0, meta.size),
+        };
+        if start > 0 {
+            file.seek(std::io::SeekFrom::Start(start)).await?;
+        }
+
+        let stream = ReaderStream::new(file.take(len)).map_err(StorageError::from);
+
+        Ok(ObjectBody {
+            meta,
+            range,
+            stream: Box::pin(stream),
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectMeta, StorageError> {
+        let path = self.path_for(key)?;
+        self.meta_for(key, &path).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path_for(key)?;
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectMeta>, StorageError> {
+        let mut objects = Vec::new();
+        let mut pending_dirs = vec![self.root.clone()];
+
+        while let Some(dir) = pending_dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(e) => e,
+                // An empty store may not have created its root yet
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_type().await?.is_dir() {
+                    pending_dirs.push(path);
+                    continue;
+                }
+
+                let key = match path.strip_prefix(&self.root).ok().and_then(|p| p.to_str()) {
+                    Some(k) => k.replace(std::path::MAIN_SEPARATOR, "/"),
+                    None => continue,
+                };
+                let is_temp = key.rsplit('/').next().is_some_and(|name| name.starts_with(".tmp-"));
+                if is_temp || !key.starts_with(prefix) {
+                    continue;
+                }
+
+                objects.push(self.meta_for(&key, &path).await?);
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("{}{}", URL_SCHEME, key)
+    }
+
+    fn key_for_url(&self, url: &str) -> Option<String> {
+        url.strip_prefix(URL_SCHEME)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+    }
+
+    fn public_url(&self, _key: &str) -> Option<String> {
+        // Files on the API host are only reachable through the proxied stream
+        None
+    }
+}
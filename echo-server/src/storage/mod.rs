@@ -0,0 +1,136 @@
+pub mod http_storage;
+pub mod local_storage;
+
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::utils::range_utils::ByteRange;
+
+/// Stream of object bytes, as returned by `ObjectStorage::get`
+pub type ByteStream = BoxStream<'static, Result<Bytes, StorageError>>;
+
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound,
+    InvalidKey(String),
+    Unsupported(&'static str),
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "Object not found"),
+            StorageError::InvalidKey(key) => write!(f, "Invalid object key: {}", key),
+            StorageError::Unsupported(op) => write!(f, "Operation not supported by this storage backend: {}", op),
+            StorageError::Backend(msg) => write!(f, "Storage backend error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            StorageError::NotFound
+        } else {
+            StorageError::Backend(e.to_string())
+        }
+    }
+}
+
+/// Metadata of a stored object
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Body of a `get`, limited to `range` when one was requested
+pub struct ObjectBody {
+    pub meta: ObjectMeta,
+    pub range: Option<ByteRange>,
+    pub stream: ByteStream,
+}
+
+/// Object storage backend holding the audio files.
+/// Objects are addressed by key (e.g. `{song_id}.mp3`); `songs.object_url` stores the
+/// backend URL of the object, which `key_for_url` maps back to its key.
+#[async_trait]
+pub trait ObjectStorage: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError>;
+
+    /// Store an object as it is produced, without holding it in memory.
+    /// The upload fails, and nothing should be kept, when `data` yields an error.
+    async fn put_stream(&self, key: &str, data: ByteStream, content_type: &str) -> Result<(), StorageError>;
+
+    /// Read an object, optionally only an inclusive byte range of it
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectBody, StorageError>;
+
+    async fn head(&self, key: &str) -> Result<ObjectMeta, StorageError>;
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// List objects whose key starts with `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectMeta>, StorageError>;
+
+    /// URL recorded in `songs.object_url` for an object
+    fn object_url(&self, key: &str) -> String;
+
+    /// Inverse of `object_url`; `None` if the URL does not belong to this backend
+    fn key_for_url(&self, url: &str) -> Option<String>;
+
+    /// URL clients can fetch directly, used by the redirect stream mode.
+    /// `None` when the backend is not reachable by clients.
+    fn public_url(&self, key: &str) -> Option<String>;
+}
+
+/// Build the storage backend selected by `STORAGE_BACKEND` ("http" by default, or "local").
+pub fn from_env() -> Arc<dyn ObjectStorage> {
+    match std::env::var("STORAGE_BACKEND").as_deref() {
+        Ok("http") | Err(_) => {
+            let write_base: String = std::env::var("OBJECT_STORAGE_WRITE_BASE_URL")
+                .expect("OBJECT_STORAGE_WRITE_BASE_URL must be set in .env");
+            let read_base: String = std::env::var("OBJECT_STORAGE_READ_BASE_URL")
+                .expect("OBJECT_STORAGE_READ_BASE_URL must be set in .env");
+            Arc::new(http_storage::HttpStorage::new(write_base, read_base))
+        }
+        Ok("local") => {
+            let root = std::env::var("LOCAL_STORAGE_ROOT").unwrap_or_else(|_| "./storage".to_string());
+            Arc::new(local_storage::LocalStorage::new(root))
+        }
+        Ok(other) => panic!("Invalid STORAGE_BACKEND '{}': expected 'http' or 'local'", other),
+    }
+}
+
+/// Read a whole object into memory, for processing that needs the complete file
+pub async fn read_object(storage: &dyn ObjectStorage, key: &str) -> Result<Vec<u8>, StorageError> {
+    let body = storage.get(key, None).await?;
+    body.stream
+        .try_fold(Vec::with_capacity(body.meta.size as usize), |mut buf, chunk| async move {
+            buf.extend_from_slice(&chunk);
+            Ok(buf)
+        })
+        .await
+}
+
+/// Guess an audio content type from an object key's extension
+pub fn content_type_for_key(key: &str) -> &'static str {
+    match key.rsplit('.').next().map(|ext| ext.to_ascii_lowercase()).as_deref() {
+        Some("mp3") => "audio/mpeg",
+        Some("flac") => "audio/flac",
+        Some("ogg") | Some("opus") => "audio/ogg",
+        Some("m4a") => "audio/mp4",
+        Some("aac") => "audio/aac",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
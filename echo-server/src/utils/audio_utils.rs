@@ -1,82 +1,225 @@
+use serde::Deserialize;
 use tokio::process::{Command};
 use tokio::io::{AsyncWriteExt};
 use std::error::Error;
 
-/// Target normalization loudness in dB
-const TARGET_DB: f32 = -14.0;
+use crate::models::rendition_models::{RenditionFormat, RenditionProfile};
 
-pub async fn normalize_song_async(input: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-    // Run ffmpeg volumedetect using stdin
-    let mut vol_cmd = Command::new("ffmpeg")
-        .args(&["-i", "pipe:0", "-af", "volumedetect", "-f", "null", "/dev/null"])
+/// Default loudness target in LUFS, the ReplayGain 2.0 reference level
+const DEFAULT_TARGET_LUFS: f32 = -18.0;
+
+/// Maximum true peak in dBTP allowed when re-encoding
+const TRUE_PEAK_LIMIT: f32 = -1.0;
+
+/// Loudness range target of the loudnorm filter, only relevant if it has to fall back to dynamic mode
+const LOUDNESS_RANGE_TARGET: f32 = 11.0;
+
+/// What happens to uploads once their loudness is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationMode {
+    /// Keep the original audio untouched and store ReplayGain values for clients to apply
+    ReplayGain,
+    /// Re-encode to MP3 with the gain applied
+    Reencode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LoudnessSettings {
+    pub mode: NormalizationMode,
+    pub target_lufs: f32,
+}
+
+impl LoudnessSettings {
+    /// Read `NORMALIZATION_MODE` ("replaygain" by default, or "reencode") and `LOUDNESS_TARGET_LUFS`
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("NORMALIZATION_MODE").as_deref() {
+            Ok("replaygain") | Err(_) => NormalizationMode::ReplayGain,
+            Ok("reencode") => NormalizationMode::Reencode,
+            Ok(other) => panic!("Invalid NORMALIZATION_MODE '{}': expected 'replaygain' or 'reencode'", other),
+        };
+        let target_lufs = std::env::var("LOUDNESS_TARGET_LUFS")
+            .ok()
+            .and_then(|v| v.parse::<f32>().ok())
+            .unwrap_or(DEFAULT_TARGET_LUFS);
+
+        Self { mode, target_lufs }
+    }
+}
+
+/// EBU R128 measurement of a file, as reported by ffmpeg's loudnorm filter
+#[derive(Debug, Clone, Copy)]
+pub struct LoudnessAnalysis {
+    /// Integrated loudness in LUFS
+    pub integrated_lufs: f32,
+    /// True peak in dBTP
+    pub true_peak_db: f32,
+    pub loudness_range: f32,
+    pub threshold: f32,
+    pub target_offset: f32,
+}
+
+impl LoudnessAnalysis {
+    /// ReplayGain track gain in dB; `None` for silent audio
+    pub fn track_gain(&self, target_lufs: f32) -> Option<f32> {
+        Some(target_lufs - self.integrated_lufs).filter(|g| g.is_finite())
+    }
+
+    /// ReplayGain track peak as a linear amplitude (1.0 = full scale)
+    pub fn track_peak(&self) -> Option<f32> {
+        Some(10f32.powf(self.true_peak_db / 20.0)).filter(|p| p.is_finite())
+    }
+
+    pub fn integrated(&self) -> Option<f32> {
+        Some(self.integrated_lufs).filter(|l| l.is_finite())
+    }
+}
+
+// loudnorm prints its measurements as strings, "-inf" included
+#[derive(Deserialize)]
+struct LoudnormStats {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+/// First loudnorm pass: measure integrated loudness and true peak without producing audio
+pub async fn measure_loudness_async(input: &[u8], target_lufs: f32) -> Result<LoudnessAnalysis, Box<dyn Error>> {
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+        target_lufs, TRUE_PEAK_LIMIT, LOUDNESS_RANGE_TARGET
+    );
+    let mut measure_cmd = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i", "pipe:0", "-af", &filter, "-f", "null", "-"])
         .stdin(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .spawn()?;
 
-    // Write input to ffmpeg stdin
-    if let Some(mut stdin) = vol_cmd.stdin.take() {
-        stdin.write_all(input).await?;
+    let mut stdin = measure_cmd.stdin.take().ok_or("Failed to take stdin")?;
+    let input_data = input.to_vec();
+    let write_task = tokio::spawn(async move {
+        stdin.write_all(&input_data).await?;
         stdin.shutdown().await?;
-    } else {
-    }
+        Ok::<(), std::io::Error>(())
+    });
+
+    let output = measure_cmd.wait_with_output().await?;
+    write_task.await??;
 
-    let output = vol_cmd.wait_with_output().await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("ffmpeg volumedetect failed: {}", stderr).into());
+        return Err(format!("ffmpeg loudness measurement failed: {}", stderr).into());
     }
 
-    // Parse mean_volume from stderr
-    let stderr_str: std::borrow::Cow<'_, str> = String::from_utf8_lossy(&output.stderr);
-    let mut mean_db: f32 = 0.0_f32;
-    for line in stderr_str.lines() {
-        if line.contains("mean_volume:") {
-            if let Some(value_str) = line.split(':').nth(1) {
-                mean_db = value_str.trim().replace(" dB", "").parse::<f32>()?;
-                break;
-            }
-        }
-    }
+    // The JSON block is the last thing loudnorm prints
+    let json = match (stderr.rfind('{'), stderr.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &stderr[start..=end],
+        _ => return Err("ffmpeg loudnorm printed no measurements".into()),
+    };
+    let stats: LoudnormStats = serde_json::from_str(json)?;
+
+    Ok(LoudnessAnalysis {
+        integrated_lufs: stats.input_i.trim().parse()?,
+        true_peak_db: stats.input_tp.trim().parse()?,
+        loudness_range: stats.input_lra.trim().parse()?,
+        threshold: stats.input_thresh.trim().parse()?,
+        target_offset: stats.target_offset.trim().parse()?,
+    })
+}
 
-    let gain_db = TARGET_DB - mean_db;
-    let gain_factor = 10f32.powf(gain_db / 20.0);
+/// Second loudnorm pass: re-encode to MP3 at `target_lufs` using the first pass measurements.
+/// Linear mode applies a constant gain; loudnorm only compresses when that gain would push
+/// the true peak over the limit, instead of clipping.
+pub async fn normalize_song_async(
+    input: &[u8],
+    target_lufs: f32,
+    measured: &LoudnessAnalysis,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        target_lufs,
+        TRUE_PEAK_LIMIT,
+        LOUDNESS_RANGE_TARGET,
+        measured.integrated_lufs,
+        measured.true_peak_db,
+        measured.loudness_range,
+        measured.threshold,
+        measured.target_offset,
+    );
 
+    // loudnorm upsamples to 192 kHz internally, so the output rate is set explicitly
+    let args = [
+        "-i", "pipe:0",
+        "-af", &filter,
+        "-ar", "44100",
+        "-b:a", "320k",
+        "-f", "mp3",
+        "pipe:1",
+    ];
+    run_ffmpeg_pipe(&args, input)
+        .await
+        .map_err(|e| format!("ffmpeg normalization failed: {}", e).into())
+}
+
+/// Encode audio into a rendition profile
+pub async fn transcode_async(input: &[u8], profile: &RenditionProfile) -> Result<Vec<u8>, Box<dyn Error>> {
+    let args = transcode_args(profile);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-    // Apply normalization and capture output to memory
-    let mut normalize_cmd = Command::new("ffmpeg")
-        .args(&[
-            "-i", "pipe:0",
-            "-af", &format!("volume={}", gain_factor),
-            "-b:a", "320k",
-            "-f", "mp3",
-            "pipe:1",
-        ])
+    run_ffmpeg_pipe(&args, input)
+        .await
+        .map_err(|e| format!("ffmpeg transcoding to {} failed: {}", profile.format.name(), e).into())
+}
+
+/// ffmpeg arguments encoding stdin into a rendition profile on stdout
+pub fn transcode_args(profile: &RenditionProfile) -> Vec<String> {
+    let mut args: Vec<String> = ["-i", "pipe:0", "-vn", "-map_metadata", "-1", "-c:a", profile.format.codec()]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    if let Some(bitrate) = profile.bitrate_kbps {
+        args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+    }
+    if profile.format == RenditionFormat::Opus {
+        // Opus only runs at 48 kHz
+        args.extend(["-ar".to_string(), "48000".to_string()]);
+    }
+    args.extend(["-f".to_string(), profile.format.container().to_string(), "pipe:1".to_string()]);
+    args
+}
+
+/// Run ffmpeg with `input` on stdin and collect stdout; the error is ffmpeg's stderr
+async fn run_ffmpeg_pipe(args: &[&str], input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut cmd = Command::new("ffmpeg")
+        .args(args)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
-        .spawn()?;
+        .spawn()
+        .map_err(|e| e.to_string())?;
 
-    let mut stdin = normalize_cmd.stdin.take().ok_or("Failed to take stdin")?;
+    let mut stdin = cmd.stdin.take().ok_or("Failed to take stdin")?;
     let input_data = input.to_vec();
 
-    // New async write task
+    // Write stdin from another task while this one reads stdout/stderr, unblocking ffmpeg
     let write_task = tokio::spawn(async move {
         stdin.write_all(&input_data).await?;
         stdin.shutdown().await?;
         Ok::<(), std::io::Error>(())
     });
 
-    // Concurrently, the main task waits for the process to finish and collects the output. This will read from stdout/stderr, unblocking ffmpeg.
-    let norm_output = normalize_cmd.wait_with_output().await?;
-    
-    // Wait for the task to end to make sure no error were encountered during the process
-    write_task.await??;
+    let output = cmd.wait_with_output().await.map_err(|e| e.to_string())?;
 
-    if !norm_output.status.success() {
-        let stderr = String::from_utf8_lossy(&norm_output.stderr);
-        return Err(format!("ffmpeg normalization failed: {}", stderr).into());
+    // Wait for the task to end to make sure no error were encountered during the process
+    match write_task.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("Failed to write to ffmpeg: {}", e)),
+        Err(e) => return Err(e.to_string()),
     }
 
-    let normalized_bytes = norm_output.stdout;
-    Ok(normalized_bytes)
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(output.stdout)
 }
@@ -1,6 +1,8 @@
 use actix_web::{HttpResponse, web::ReqData};
+use diesel::prelude::*;
 
 use crate::models::token_models::Claims;
+use crate::schema::users;
 
 /// Check that the requested resource belongs to the logged-in user.
 /// Returns `Ok(&str)` with the user_id if authorized, otherwise returns a 404 response.
@@ -16,3 +18,12 @@ pub fn check_ownership<'a>(
         Ok(logged_in_user_id)
     }
 }
+
+/// Check whether the given user is an admin. Lookup failures count as "not admin".
+pub fn is_admin(conn: &mut MysqlConnection, user_id: &str) -> bool {
+    users::table
+        .filter(users::id.eq(user_id))
+        .select(users::is_admin)
+        .first::<bool>(conn)
+        .unwrap_or(false)
+}
@@ -0,0 +1,748 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+use crate::db::{get_conn, DbPool};
+use crate::models::backup_models::{
+    AlbumRecord, ArtistRecord, BackupError, FavoriteRecord, GenreRecord, Manifest, ManifestObject, ManifestTable,
+    PlaylistFollowRecord, PlaylistMemberRecord, PlaylistRecord, PlaylistSongRecord, RestoreReport, SongRecord,
+    UserRecord, BACKUP_FORMAT, BACKUP_TABLES, BACKUP_VERSION, MANIFEST_FILE,
+};
+use crate::models::playlist_models::{SmartRules, KIND_SMART};
+use crate::schema::{
+    albums, artists, favorites, genres, playlist_follows, playlist_members, playlist_songs, playlists, songs, users,
+};
+use crate::storage::{content_type_for_key, read_object, ObjectStorage, StorageError};
+use crate::utils::catalog_utils::find_or_create_genre;
+use crate::workers::ingest_worker::discard_objects;
+
+/// Rows inserted per statement on restore
+const INSERT_BATCH: usize = 500;
+/// Password hash of users restored from an archive without credentials. No password
+/// verifies against it, so they cannot log in until an admin sets a new one.
+const LOCKED_PASSWORD_HASH: &str = "!";
+
+/// Every row of an archive
+#[derive(Default)]
+pub struct BackupTables {
+    pub users: Vec<UserRecord>,
+    pub genres: Vec<GenreRecord>,
+    pub artists: Vec<ArtistRecord>,
+    pub albums: Vec<AlbumRecord>,
+    pub songs: Vec<SongRecord>,
+    pub playlists: Vec<PlaylistRecord>,
+    pub playlist_songs: Vec<PlaylistSongRecord>,
+    pub playlist_members: Vec<PlaylistMemberRecord>,
+    pub playlist_follows: Vec<PlaylistFollowRecord>,
+    pub favorites: Vec<FavoriteRecord>,
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Fresh directory path under the system temp dir
+fn staging_dir(prefix: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}-{}", prefix, Uuid::new_v4()))
+}
+
+/// Run blocking tar I/O off the async workers
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, BackupError> + Send + 'static,
+) -> Result<T, BackupError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| BackupError::Io(std::io::Error::other(e)))?
+}
+
+// --------------------- Backup ---------------------
+
+fn load_tables(conn: &mut MysqlConnection) -> QueryResult<BackupTables> {
+    Ok(BackupTables {
+        users: users::table.select(UserRecord::as_select()).load(conn)?,
+        genres: genres::table.select(GenreRecord::as_select()).load(conn)?,
+        artists: artists::table.select(ArtistRecord::as_select()).load(conn)?,
+        albums: albums::table.select(AlbumRecord::as_select()).load(conn)?,
+        songs: songs::table.select(SongRecord::as_select()).load(conn)?,
+        playlists: playlists::table.select(PlaylistRecord::as_select()).load(conn)?,
+        playlist_songs: playlist_songs::table.select(PlaylistSongRecord::as_select()).load(conn)?,
+        playlist_members: playlist_members::table.select(PlaylistMemberRecord::as_select()).load(conn)?,
+        playlist_follows: playlist_follows::table.select(PlaylistFollowRecord::as_select()).load(conn)?,
+        favorites: favorites::table.select(FavoriteRecord::as_select()).load(conn)?,
+    })
+}
+
+async fn stage_table<T: Serialize>(staging: &Path, name: &str, rows: &[T]) -> Result<ManifestTable, BackupError> {
+    let file = format!("data/{}.json", name);
+    let bytes = serde_json::to_vec(rows)?;
+    tokio::fs::write(staging.join(&file), &bytes).await?;
+    Ok(ManifestTable {
+        name: name.to_string(),
+        file,
+        rows: rows.len(),
+        sha256: sha256_hex(&bytes),
+    })
+}
+
+/// Write the tables, the audio objects and the manifest as files under `staging`
+async fn stage_backup(
+    pool: &DbPool,
+    storage: &dyn ObjectStorage,
+    credentials: bool,
+    staging: &Path,
+) -> Result<Manifest, BackupError> {
+    let mut tables = {
+        let mut conn = get_conn(pool)?;
+        // A single transaction reads every table from the same snapshot
+        conn.transaction::<_, diesel::result::Error, _>(|conn| load_tables(conn))?
+    };
+    if !credentials {
+        for user in &mut tables.users {
+            user.password_hash.clear();
+        }
+    }
+
+    tokio::fs::create_dir_all(staging.join("data")).await?;
+    tokio::fs::create_dir_all(staging.join("objects")).await?;
+
+    let manifest_tables = vec![
+        stage_table(staging, "users", &tables.users).await?,
+        stage_table(staging, "genres", &tables.genres).await?,
+        stage_table(staging, "artists", &tables.artists).await?,
+        stage_table(staging, "albums", &tables.albums).await?,
+        stage_table(staging, "songs", &tables.songs).await?,
+        stage_table(staging, "playlists", &tables.playlists).await?,
+        stage_table(staging, "playlist_songs", &tables.playlist_songs).await?,
+        stage_table(staging, "playlist_members", &tables.playlist_members).await?,
+        stage_table(staging, "playlist_follows", &tables.playlist_follows).await?,
+        stage_table(staging, "favorites", &tables.favorites).await?,
+    ];
+
+    let mut objects = Vec::with_capacity(tables.songs.len());
+    for song in &tables.songs {
+        let key = storage
+            .key_for_url(&song.object_url)
+            .ok_or_else(|| StorageError::InvalidKey(song.object_url.clone()))?;
+        let data = read_object(storage, &key).await.inspect_err(|e| {
+            eprintln!("Failed to read object {} of song {}: {}", key, song.id, e);
+        })?;
+        let file = format!("objects/{}", song.id);
+        tokio::fs::write(staging.join(&file), &data).await?;
+        objects.push(ManifestObject {
+            song_id: song.id.clone(),
+            key,
+            file,
+            size: data.len() as u64,
+            sha256: sha256_hex(&data),
+        });
+    }
+
+    let manifest = Manifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: Utc::now().naive_utc(),
+        credentials,
+        tables: manifest_tables,
+        objects,
+    };
+    tokio::fs::write(staging.join(MANIFEST_FILE), serde_json::to_vec_pretty(&manifest)?).await?;
+    Ok(manifest)
+}
+
+fn pack(staging: &Path, archive: &Path) -> Result<(), BackupError> {
+    let file = std::fs::File::create(archive)?;
+    let mut builder = tar::Builder::new(std::io::BufWriter::new(file));
+    // The manifest goes first, so readers learn what the archive holds before the rest of it
+    builder.append_path_with_name(staging.join(MANIFEST_FILE), MANIFEST_FILE)?;
+    builder.append_dir_all("data", staging.join("data"))?;
+    builder.append_dir_all("objects", staging.join("objects"))?;
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Archive the whole library into a tar file at `archive`: one JSON file per table, the
+/// audio object of every song and a manifest with their SHA-256. Password hashes are only
+/// kept with `credentials`; Subsonic passwords are always left out.
+pub async fn write_backup(
+    pool: &DbPool,
+    storage: &dyn ObjectStorage,
+    credentials: bool,
+    archive: &Path,
+) -> Result<Manifest, BackupError> {
+    let staging = staging_dir("echo-backup");
+    let result = match stage_backup(pool, storage, credentials, &staging).await {
+        Ok(manifest) => {
+            let (staged, archive) = (staging.clone(), archive.to_path_buf());
+            run_blocking(move || pack(&staged, &archive)).await.map(|_| manifest)
+        }
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    result
+}
+
+// --------------------- Restore ---------------------
+
+fn unpack(archive: &Path, staging: &Path) -> Result<(), BackupError> {
+    std::fs::create_dir_all(staging)?;
+    tar::Archive::new(std::fs::File::open(archive)?)
+        .unpack(staging)
+        .map_err(|e| BackupError::Invalid(format!("unreadable tar: {}", e)))
+}
+
+/// Read a file the manifest points at, checking it against its SHA-256
+async fn read_verified(staging: &Path, file: &str, sha256: &str) -> Result<Vec<u8>, BackupError> {
+    // Manifest paths stay inside the archive
+    let relative = Path::new(file);
+    if file.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(BackupError::Invalid(format!("bad file path {}", file)));
+    }
+    let bytes = tokio::fs::read(staging.join(relative))
+        .await
+        .map_err(|_| BackupError::Invalid(format!("{} is missing", file)))?;
+    if !sha256_hex(&bytes).eq_ignore_ascii_case(sha256) {
+        return Err(BackupError::Invalid(format!("checksum mismatch for {}", file)));
+    }
+    Ok(bytes)
+}
+
+async fn read_manifest(staging: &Path) -> Result<Manifest, BackupError> {
+    let bytes = tokio::fs::read(staging.join(MANIFEST_FILE))
+        .await
+        .map_err(|_| BackupError::Invalid(format!("no {}", MANIFEST_FILE)))?;
+    let manifest: Manifest = serde_json::from_slice(&bytes)?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(BackupError::Invalid(format!("unknown format {}", manifest.format)));
+    }
+    if manifest.version != BACKUP_VERSION {
+        return Err(BackupError::Invalid(format!(
+            "version {} is not supported (expected {})",
+            manifest.version, BACKUP_VERSION
+        )));
+    }
+    Ok(manifest)
+}
+
+async fn read_table<T: DeserializeOwned>(staging: &Path, manifest: &Manifest, name: &str) -> Result<Vec<T>, BackupError> {
+    let table = manifest
+        .tables
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| BackupError::Invalid(format!("table {} is missing", name)))?;
+    let rows: Vec<T> = serde_json::from_slice(&read_verified(staging, &table.file, &table.sha256).await?)?;
+    if rows.len() != table.rows {
+        return Err(BackupError::Invalid(format!("table {} has {} rows, not {}", name, rows.len(), table.rows)));
+    }
+    Ok(rows)
+}
+
+async fn read_tables(staging: &Path, manifest: &Manifest) -> Result<BackupTables, BackupError> {
+    if let Some(unknown) = manifest.tables.iter().find(|t| !BACKUP_TABLES.contains(&t.name.as_str())) {
+        return Err(BackupError::Invalid(format!("unknown table {}", unknown.name)));
+    }
+    Ok(BackupTables {
+        users: read_table(staging, manifest, "users").await?,
+        genres: read_table(staging, manifest, "genres").await?,
+        artists: read_table(staging, manifest, "artists").await?,
+        albums: read_table(staging, manifest, "albums").await?,
+        songs: read_table(staging, manifest, "songs").await?,
+        playlists: read_table(staging, manifest, "playlists").await?,
+        playlist_songs: read_table(staging, manifest, "playlist_songs").await?,
+        playlist_members: read_table(staging, manifest, "playlist_members").await?,
+        playlist_follows: read_table(staging, manifest, "playlist_follows").await?,
+        favorites: read_table(staging, manifest, "favorites").await?,
+    })
+}
+
+/// Check every audio object against its size and SHA-256, and that every song has one
+async fn verify_objects<'a>(
+    staging: &Path,
+    manifest: &'a Manifest,
+    tables: &BackupTables,
+) -> Result<HashMap<&'a str, &'a ManifestObject>, BackupError> {
+    let mut objects = HashMap::with_capacity(manifest.objects.len());
+    for object in &manifest.objects {
+        let bytes = read_verified(staging, &object.file, &object.sha256).await?;
+        if bytes.len() as u64 != object.size {
+            return Err(BackupError::Invalid(format!("size mismatch for {}", object.file)));
+        }
+        objects.insert(object.song_id.as_str(), object);
+    }
+    if let Some(song) = tables.songs.iter().find(|s| !objects.contains_key(s.id.as_str())) {
+        return Err(BackupError::Invalid(format!("no audio object for song {}", song.id)));
+    }
+    Ok(objects)
+}
+
+/// New ids of the rows of one table, and which of them are rows already in the database
+#[derive(Default)]
+struct Remap {
+    ids: HashMap<String, String>,
+    reused: HashSet<String>,
+}
+
+impl Remap {
+    fn get(&self, id: &str) -> String {
+        self.ids.get(id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// Map `id` to the existing row `existing` if there is one, else to a new id
+    fn assign(&mut self, id: &str, existing: Option<String>) {
+        let new_id = match existing {
+            Some(existing) => {
+                self.reused.insert(id.to_string());
+                existing
+            }
+            None => Uuid::new_v4().to_string(),
+        };
+        self.ids.insert(id.to_string(), new_id);
+    }
+
+    fn is_reused(&self, id: &str) -> bool {
+        self.reused.contains(id)
+    }
+}
+
+#[derive(Default)]
+struct IdMap {
+    users: Remap,
+    artists: Remap,
+    albums: Remap,
+    songs: Remap,
+    playlists: Remap,
+    /// Filled while restoring genres, which get their ids from the database
+    genres: HashMap<i32, i32>,
+}
+
+impl IdMap {
+    fn reused(&self) -> usize {
+        [&self.users, &self.artists, &self.albums, &self.songs]
+            .iter()
+            .map(|r| r.reused.len())
+            .sum()
+    }
+}
+
+/// Give every row a new id, or the id of the row it matches: users by username, artists by
+/// name, albums by artist and name, songs by artist and title
+fn plan_ids(conn: &mut MysqlConnection, tables: &BackupTables) -> QueryResult<IdMap> {
+    let mut ids = IdMap::default();
+    for user in &tables.users {
+        let existing = users::table
+            .filter(users::username.eq(&user.username))
+            .select(users::id)
+            .first::<String>(conn)
+            .optional()?;
+        ids.users.assign(&user.id, existing);
+    }
+    for artist in &tables.artists {
+        let existing = artists::table
+            .filter(artists::name.eq(&artist.name))
+            .select(artists::id)
+            .first::<String>(conn)
+            .optional()?;
+        ids.artists.assign(&artist.id, existing);
+    }
+    for album in &tables.albums {
+        let existing = albums::table
+            .filter(albums::artist_id.eq(ids.artists.get(&album.artist_id)))
+            .filter(albums::name.eq(&album.name))
+            .select(albums::id)
+            .first::<String>(conn)
+            .optional()?;
+        ids.albums.assign(&album.id, existing);
+    }
+    for song in &tables.songs {
+        let existing = songs::table
+            .filter(songs::artist_id.eq(ids.artists.get(&song.artist_id)))
+            .filter(songs::title.eq(&song.title))
+            .select(songs::id)
+            .first::<String>(conn)
+            .optional()?;
+        ids.songs.assign(&song.id, existing);
+    }
+    for playlist in &tables.playlists {
+        ids.playlists.assign(&playlist.id, None);
+    }
+    Ok(ids)
+}
+
+/// Rewrite the ids of every row and drop the rows matched to existing ones
+fn apply_ids(tables: &mut BackupTables, ids: &IdMap) {
+    tables.users.retain(|u| !ids.users.is_reused(&u.id));
+    for user in &mut tables.users {
+        user.id = ids.users.get(&user.id);
+    }
+    tables.artists.retain(|a| !ids.artists.is_reused(&a.id));
+    for artist in &mut tables.artists {
+        artist.id = ids.artists.get(&artist.id);
+    }
+    tables.albums.retain(|a| !ids.albums.is_reused(&a.id));
+    for album in &mut tables.albums {
+        album.id = ids.albums.get(&album.id);
+        album.artist_id = ids.artists.get(&album.artist_id);
+    }
+    tables.songs.retain(|s| !ids.songs.is_reused(&s.id));
+    for song in &mut tables.songs {
+        song.id = ids.songs.get(&song.id);
+        song.artist_id = ids.artists.get(&song.artist_id);
+        song.album_id = song.album_id.as_deref().map(|id| ids.albums.get(id));
+    }
+
+    // Accounts matched by username may belong to someone else: nothing archived in the
+    // name of such a user is restored, neither their playlists and favorites nor the
+    // playlists shared with or followed by them
+    let skipped: HashSet<String> = tables
+        .playlists
+        .iter()
+        .filter(|p| ids.users.is_reused(&p.user_id))
+        .map(|p| p.id.clone())
+        .collect();
+    tables.playlists.retain(|p| !skipped.contains(&p.id));
+    for playlist in &mut tables.playlists {
+        playlist.id = ids.playlists.get(&playlist.id);
+        playlist.user_id = ids.users.get(&playlist.user_id);
+    }
+    tables.playlist_songs.retain(|e| !skipped.contains(&e.playlist_id));
+    for entry in &mut tables.playlist_songs {
+        entry.playlist_id = ids.playlists.get(&entry.playlist_id);
+        entry.song_id = ids.songs.get(&entry.song_id);
+        entry.added_by = entry
+            .added_by
+            .as_deref()
+            .filter(|id| !ids.users.is_reused(id))
+            .map(|id| ids.users.get(id));
+    }
+    tables
+        .playlist_members
+        .retain(|m| !skipped.contains(&m.playlist_id) && !ids.users.is_reused(&m.user_id));
+    for member in &mut tables.playlist_members {
+        member.playlist_id = ids.playlists.get(&member.playlist_id);
+        member.user_id = ids.users.get(&member.user_id);
+    }
+    tables
+        .playlist_follows
+        .retain(|f| !skipped.contains(&f.playlist_id) && !ids.users.is_reused(&f.user_id));
+    for follow in &mut tables.playlist_follows {
+        follow.playlist_id = ids.playlists.get(&follow.playlist_id);
+        follow.user_id = ids.users.get(&follow.user_id);
+    }
+    tables.favorites.retain(|f| !ids.users.is_reused(&f.user_id));
+    for favorite in &mut tables.favorites {
+        favorite.user_id = ids.users.get(&favorite.user_id);
+        favorite.song_id = ids.songs.get(&favorite.song_id);
+    }
+}
+
+/// Smart playlist rules with their genre and artists mapped to the restored ones
+fn remap_rules(rules: &str, ids: &IdMap) -> Option<String> {
+    // Rules that do not parse are kept as they are; reading them falls back to the defaults
+    let mut rules: SmartRules = serde_json::from_str(rules).ok()?;
+    rules.genre_id = rules.genre_id.map(|id| ids.genres.get(&id).copied().unwrap_or(id));
+    rules.artist_ids = rules.artist_ids.iter().map(|id| ids.artists.get(id)).collect();
+    serde_json::to_string(&rules).ok()
+}
+
+/// Object key for a song given a new id: `{song_id}.{extension}`, like ingested songs
+fn remapped_key(song_id: &str, key: &str) -> String {
+    match key.rsplit_once('.') {
+        Some((_, ext)) if !ext.is_empty() && !ext.contains('/') => format!("{}.{}", song_id, ext),
+        _ => song_id.to_string(),
+    }
+}
+
+/// Insert the rows, in `BACKUP_TABLES` order. With `remap`, genres are matched by name
+/// and smart playlist rules follow the new ids.
+fn insert_tables(
+    conn: &mut MysqlConnection,
+    tables: &mut BackupTables,
+    ids: &mut IdMap,
+    remap: bool,
+    report: &mut RestoreReport,
+) -> Result<(), BackupError> {
+    macro_rules! insert_batches {
+        ($table:expr, $rows:expr) => {{
+            let mut inserted = 0;
+            for batch in $rows.chunks(INSERT_BATCH) {
+                inserted += diesel::insert_into($table).values(batch).execute(conn)?;
+            }
+            inserted
+        }};
+    }
+
+    report.users = insert_batches!(users::table, tables.users);
+
+    if remap {
+        for genre in &tables.genres {
+            let existing = genres::table
+                .filter(genres::name.eq(&genre.name))
+                .select(genres::id)
+                .first::<i32>(conn)
+                .optional()?;
+            let id = match existing {
+                Some(id) => {
+                    report.reused += 1;
+                    id
+                }
+                None => {
+                    report.genres += 1;
+                    find_or_create_genre(conn, &genre.name)?
+                }
+            };
+            ids.genres.insert(genre.id, id);
+        }
+        for song in &mut tables.songs {
+            song.genre_id = song.genre_id.map(|id| ids.genres.get(&id).copied().unwrap_or(id));
+        }
+        for playlist in &mut tables.playlists {
+            if playlist.kind == KIND_SMART
+                && let Some(rules) = playlist.rules.as_deref().and_then(|r| remap_rules(r, ids))
+            {
+                playlist.rules = Some(rules);
+            }
+        }
+    } else {
+        report.genres = insert_batches!(genres::table, tables.genres);
+    }
+
+    report.artists = insert_batches!(artists::table, tables.artists);
+    report.albums = insert_batches!(albums::table, tables.albums);
+    report.songs = insert_batches!(songs::table, tables.songs);
+    report.playlists = insert_batches!(playlists::table, tables.playlists);
+    report.playlist_songs = insert_batches!(playlist_songs::table, tables.playlist_songs);
+    report.playlist_members = insert_batches!(playlist_members::table, tables.playlist_members);
+    report.playlist_follows = insert_batches!(playlist_follows::table, tables.playlist_follows);
+    report.favorites = insert_batches!(favorites::table, tables.favorites);
+    Ok(())
+}
+
+/// Upload the audio objects of the songs to insert, pointing their `object_url` at them.
+/// Returns the keys written, for the restore to remove them if it fails.
+async fn upload_objects(
+    storage: &dyn ObjectStorage,
+    staging: &Path,
+    songs: &mut [SongRecord],
+    objects: &HashMap<&str, &ManifestObject>,
+    ids: &IdMap,
+    remap: bool,
+) -> Result<Vec<String>, (BackupError, Vec<String>)> {
+    let mut uploaded = Vec::new();
+    for song in songs.iter_mut() {
+        // Songs still carry their archived ids here
+        let object = objects[song.id.as_str()];
+        let key = if remap {
+            remapped_key(&ids.songs.get(&song.id), &object.key)
+        } else {
+            object.key.clone()
+        };
+        song.object_url = storage.object_url(&key);
+
+        // Restoring onto the storage the archive came from: the object is already there
+        // and must not be removed if the restore fails
+        if storage.head(&key).await.is_ok() {
+            continue;
+        }
+        let data = match tokio::fs::read(staging.join(&object.file)).await {
+            Ok(data) => data,
+            Err(e) => return Err((e.into(), uploaded)),
+        };
+        if let Err(e) = storage.put(&key, data, content_type_for_key(&key)).await {
+            return Err((e.into(), uploaded));
+        }
+        uploaded.push(key);
+    }
+    Ok(uploaded)
+}
+
+async fn restore_staged(
+    pool: &DbPool,
+    storage: &dyn ObjectStorage,
+    archive: &Path,
+    staging: &Path,
+    remap: bool,
+) -> Result<RestoreReport, BackupError> {
+    let (archive_path, unpack_dir) = (archive.to_path_buf(), staging.to_path_buf());
+    run_blocking(move || unpack(&archive_path, &unpack_dir)).await?;
+
+    // Everything is checked before anything is written
+    let manifest = read_manifest(staging).await?;
+    let mut tables = read_tables(staging, &manifest).await?;
+    let objects = verify_objects(staging, &manifest, &tables).await?;
+
+    let mut ids = if remap {
+        let mut conn = get_conn(pool)?;
+        plan_ids(&mut conn, &tables)?
+    } else {
+        IdMap::default()
+    };
+    let mut report = RestoreReport {
+        reused: ids.reused(),
+        ..Default::default()
+    };
+
+    // Songs matched to existing ones keep the object they already have
+    tables.songs.retain(|s| !ids.songs.is_reused(&s.id));
+    let uploaded = match upload_objects(storage, staging, &mut tables.songs, &objects, &ids, remap).await {
+        Ok(uploaded) => uploaded,
+        Err((e, uploaded)) => {
+            discard_objects(storage, &uploaded).await;
+            return Err(e);
+        }
+    };
+    report.objects = uploaded.len();
+
+    if remap {
+        apply_ids(&mut tables, &ids);
+    }
+    for user in &mut tables.users {
+        if user.password_hash.is_empty() {
+            user.password_hash = LOCKED_PASSWORD_HASH.to_string();
+        }
+    }
+
+    let inserted = get_conn(pool).map_err(BackupError::from).and_then(|mut conn| {
+        conn.transaction::<_, BackupError, _>(|conn| insert_tables(conn, &mut tables, &mut ids, remap, &mut report))
+    });
+    if let Err(e) = inserted {
+        discard_objects(storage, &uploaded).await;
+        return Err(e);
+    }
+    Ok(report)
+}
+
+/// Restore an archive written by `write_backup`, after checking every file of it against
+/// the manifest. Without `remap`, rows keep their ids and the restore fails on any row
+/// already there; with it, rows get new ids and library rows matching existing ones are
+/// reused. Either way the database is left untouched on failure.
+pub async fn restore_archive(
+    pool: &DbPool,
+    storage: &dyn ObjectStorage,
+    archive: &Path,
+    remap: bool,
+) -> Result<RestoreReport, BackupError> {
+    let staging = staging_dir("echo-restore");
+    let result = restore_staged(pool, storage, archive, &staging, remap).await;
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::playlist_models::KIND_REGULAR;
+
+    fn at() -> chrono::NaiveDateTime {
+        chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn members_and_follows_follow_new_ids_and_skip_reused_users() {
+        let mut ids = IdMap::default();
+        ids.users.assign("new-user", None);
+        ids.users.assign("known-user", Some("existing".to_string()));
+        ids.playlists.assign("playlist", None);
+
+        let mut tables = BackupTables {
+            playlist_members: ["new-user", "known-user"]
+                .iter()
+                .map(|user| PlaylistMemberRecord {
+                    playlist_id: "playlist".to_string(),
+                    user_id: user.to_string(),
+                    role: "viewer".to_string(),
+                    added_at: at(),
+                })
+                .collect(),
+            playlist_follows: ["known-user", "new-user"]
+                .iter()
+                .map(|user| PlaylistFollowRecord {
+                    playlist_id: "playlist".to_string(),
+                    user_id: user.to_string(),
+                    followed_at: at(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        apply_ids(&mut tables, &ids);
+
+        let (playlist, user) = (ids.playlists.get("playlist"), ids.users.get("new-user"));
+        assert_ne!(playlist, "playlist");
+        assert_ne!(user, "new-user");
+        let members: Vec<(&str, &str)> =
+            tables.playlist_members.iter().map(|m| (m.playlist_id.as_str(), m.user_id.as_str())).collect();
+        assert_eq!(members, vec![(playlist.as_str(), user.as_str())]);
+        let follows: Vec<(&str, &str)> =
+            tables.playlist_follows.iter().map(|f| (f.playlist_id.as_str(), f.user_id.as_str())).collect();
+        assert_eq!(follows, vec![(playlist.as_str(), user.as_str())]);
+    }
+
+    fn playlist(id: &str, user_id: &str) -> PlaylistRecord {
+        PlaylistRecord {
+            id: id.to_string(),
+            user_id: user_id.to_string(),
+            name: id.to_string(),
+            description: None,
+            is_public: Some(false),
+            created_at: None,
+            updated_at: None,
+            kind: KIND_REGULAR.to_string(),
+            rules: None,
+        }
+    }
+
+    #[test]
+    fn playlists_and_favorites_of_reused_users_are_left_out() {
+        let mut ids = IdMap::default();
+        ids.users.assign("new-user", None);
+        ids.users.assign("known-user", Some("existing".to_string()));
+        ids.songs.assign("song", None);
+        ids.playlists.assign("theirs", None);
+        ids.playlists.assign("mine", None);
+
+        let entry = |playlist_id: &str, added_by: &str| PlaylistSongRecord {
+            playlist_id: playlist_id.to_string(),
+            song_id: "song".to_string(),
+            position: 0,
+            added_at: None,
+            added_by: Some(added_by.to_string()),
+        };
+        let mut tables = BackupTables {
+            playlists: vec![playlist("theirs", "known-user"), playlist("mine", "new-user")],
+            playlist_songs: vec![entry("theirs", "known-user"), entry("mine", "known-user")],
+            playlist_follows: vec![PlaylistFollowRecord {
+                playlist_id: "theirs".to_string(),
+                user_id: "new-user".to_string(),
+                followed_at: at(),
+            }],
+            favorites: ["known-user", "new-user"]
+                .iter()
+                .map(|user| FavoriteRecord {
+                    user_id: user.to_string(),
+                    song_id: "song".to_string(),
+                    added_at: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        apply_ids(&mut tables, &ids);
+
+        let (mine, user, song) = (ids.playlists.get("mine"), ids.users.get("new-user"), ids.songs.get("song"));
+        let playlists: Vec<(&str, &str)> =
+            tables.playlists.iter().map(|p| (p.id.as_str(), p.user_id.as_str())).collect();
+        assert_eq!(playlists, vec![(mine.as_str(), user.as_str())]);
+        // Songs of the skipped playlist go with it; a reused user is not credited for the rest
+        assert_eq!(tables.playlist_songs.len(), 1);
+        assert_eq!(tables.playlist_songs[0].playlist_id, mine);
+        assert_eq!(tables.playlist_songs[0].added_by, None);
+        assert!(tables.playlist_follows.is_empty());
+        let favorites: Vec<(&str, &str)> =
+            tables.favorites.iter().map(|f| (f.user_id.as_str(), f.song_id.as_str())).collect();
+        assert_eq!(favorites, vec![(user.as_str(), song.as_str())]);
+        assert!(tables.favorites.iter().all(|f| f.user_id != "existing"));
+    }
+}
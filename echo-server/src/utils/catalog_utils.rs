@@ -0,0 +1,103 @@
+use diesel::prelude::*;
+use diesel::sql_types::{Float, Integer, Text};
+use uuid::Uuid;
+
+use crate::models::album_models::NewAlbum;
+use crate::models::artist_models::NewArtist;
+use crate::models::genre_models::NewGenre;
+use crate::schema::{albums, artists, genres};
+
+// Name lookups for uploads that reference the catalog by name (e.g. from file tags)
+// instead of by id. Unknown entries are created; names compare with the column collation,
+// which is case-insensitive by default in MySQL.
+
+pub fn find_or_create_artist(conn: &mut MysqlConnection, name: &str) -> QueryResult<String> {
+    let existing = artists::table
+        .filter(artists::name.eq(name))
+        .select(artists::id)
+        .first::<String>(conn)
+        .optional()?;
+    if let Some(artist_id) = existing {
+        return Ok(artist_id);
+    }
+
+    let new_artist = NewArtist {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        bio: None,
+        image_url: None,
+    };
+    diesel::insert_into(artists::table).values(&new_artist).execute(conn)?;
+    Ok(new_artist.id)
+}
+
+pub fn find_or_create_album(
+    conn: &mut MysqlConnection,
+    name: &str,
+    artist_id: &str,
+    release_year: Option<i32>,
+) -> QueryResult<String> {
+    let existing = albums::table
+        .filter(albums::name.eq(name))
+        .filter(albums::artist_id.eq(artist_id))
+        .select(albums::id)
+        .first::<String>(conn)
+        .optional()?;
+    if let Some(album_id) = existing {
+        return Ok(album_id);
+    }
+
+    let new_album = NewAlbum {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        artist_id: artist_id.to_string(),
+        release_year,
+        cover_url: None,
+    };
+    diesel::insert_into(albums::table).values(&new_album).execute(conn)?;
+    Ok(new_album.id)
+}
+
+pub fn find_or_create_genre(conn: &mut MysqlConnection, name: &str) -> QueryResult<i32> {
+    let existing = genres::table
+        .filter(genres::name.eq(name))
+        .select(genres::id)
+        .first::<i32>(conn)
+        .optional()?;
+    if let Some(genre_id) = existing {
+        return Ok(genre_id);
+    }
+
+    diesel::insert_into(genres::table)
+        .values(&NewGenre { name: name.to_string() })
+        .execute(conn)?;
+    // genres.id is AUTO_INCREMENT and LAST_INSERT_ID() is scoped to this connection
+    diesel::select(diesel::dsl::sql::<Integer>("LAST_INSERT_ID()")).get_result::<i32>(conn)
+}
+
+/// Recompute an album's ReplayGain from its songs: album loudness is the duration-weighted
+/// energy average of the track loudnesses, album peak the highest track peak.
+/// Albums without measured songs get no album gain.
+pub fn refresh_album_gain(conn: &mut MysqlConnection, album_id: &str, target_lufs: f32) -> QueryResult<usize> {
+    diesel::sql_query(
+        r#"
+        UPDATE albums al
+        LEFT JOIN (
+            SELECT
+                album_id,
+                10 * LOG10(SUM(duration_seconds * POW(10, loudness_lufs / 10)) / SUM(duration_seconds)) AS loudness,
+                MAX(track_peak) AS peak
+            FROM songs
+            WHERE album_id = ? AND loudness_lufs IS NOT NULL AND duration_seconds > 0
+            GROUP BY album_id
+        ) measured ON measured.album_id = al.id
+        SET al.album_gain = ? - measured.loudness,
+            al.album_peak = measured.peak
+        WHERE al.id = ?
+        "#,
+    )
+    .bind::<Text, _>(album_id)
+    .bind::<Float, _>(target_lufs)
+    .bind::<Text, _>(album_id)
+    .execute(conn)
+}
@@ -1,4 +1,23 @@
 pub mod token_utils;
 pub mod audio_utils;
 pub mod auth_utils;
-pub mod pagination_utils;
\ No newline at end of file
+pub mod pagination_utils;
+pub mod range_utils;
+pub mod stream_utils;
+pub mod signing_utils;
+pub mod probe_utils;
+pub mod catalog_utils;
+pub mod rendition_utils;
+pub mod transcode_utils;
+pub mod subsonic_utils;
+pub mod play_utils;
+pub mod stats_utils;
+pub mod recommendation_utils;
+pub mod radio_utils;
+pub mod search_utils;
+pub mod song_query_utils;
+pub mod sql_utils;
+pub mod playlist_utils;
+pub mod playlist_format_utils;
+pub mod song_match_utils;
+pub mod backup_utils;
\ No newline at end of file
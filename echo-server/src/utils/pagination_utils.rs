@@ -1,7 +1,47 @@
-use crate::models::pagination_models::{Pagination, PaginationError};
+use diesel::deserialize;
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::row::NamedRow;
+use diesel::sql_types::{BigInt, Text};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::pagination_models::{Page, PageError, Pagination, PaginationError};
+use crate::utils::sql_utils::{bind_values, BindValue};
+
+/// Where a page starts: right after the sort key values of a row, or right before them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cursor {
+    #[serde(rename = "k")]
+    keys: Vec<Value>,
+    #[serde(rename = "b", default, skip_serializing_if = "std::ops::Not::not")]
+    backward: bool,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let bytes = hex::decode(token).ok()?;
+        let cursor: Cursor = serde_json::from_slice(&bytes).ok()?;
+        let scalar = cursor.keys.iter().all(|k| !k.is_array() && !k.is_object());
+        scalar.then_some(cursor)
+    }
+}
+
+/// A validated page request: either a cursor, or an offset from the start
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub offset: i64,
+    pub cursor: Option<Cursor>,
+}
 
 /// Validate pagination parameters
-pub fn validate_pagination(p: &Pagination) -> Result<(i64, i64), PaginationError> {
+pub fn validate_pagination(p: &Pagination) -> Result<PageRequest, PaginationError> {
     let limit = p.limit.unwrap_or(Pagination::DEFAULT_LIMIT);
     if limit > Pagination::MAX_LIMIT {
         return Err(PaginationError(format!(
@@ -9,6 +49,404 @@ pub fn validate_pagination(p: &Pagination) -> Result<(i64, i64), PaginationError
             Pagination::MAX_LIMIT
         )));
     }
+    if limit < 1 {
+        return Err(PaginationError("Limit must be at least 1".to_string()));
+    }
+
     let offset = p.offset.unwrap_or(0);
-    Ok((limit, offset))
+    if offset < 0 {
+        return Err(PaginationError("Offset cannot be negative".to_string()));
+    }
+
+    let cursor = match p.cursor.as_deref() {
+        Some(_) if offset > 0 => {
+            return Err(PaginationError("Give either a cursor or an offset, not both".to_string()))
+        }
+        Some(token) => Some(Cursor::decode(token).ok_or_else(|| PaginationError("Invalid cursor".to_string()))?),
+        None => None,
+    };
+
+    Ok(PageRequest { limit, offset, cursor })
+}
+
+/// A key a listing is sorted on
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    expr: String,
+    desc: bool,
+    nullable: bool,
+}
+
+impl SortKey {
+    pub fn asc(expr: impl Into<String>) -> Self {
+        Self { expr: expr.into(), desc: false, nullable: false }
+    }
+
+    pub fn desc(expr: impl Into<String>) -> Self {
+        Self { expr: expr.into(), desc: true, nullable: false }
+    }
+
+    /// The key can be NULL; NULLs come last, whatever the direction
+    pub fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+}
+
+/// A listing paged on its sort keys rather than on an offset, so pages stay put while
+/// rows are added or removed before them. The last key must tell rows apart, like an id.
+#[derive(Debug, Clone)]
+pub struct KeysetQuery {
+    /// Columns of the items
+    pub select: String,
+    /// `FROM` and joins
+    pub from: String,
+    pub conditions: Vec<String>,
+    /// Values of the `?` of `conditions`, in order
+    pub binds: Vec<BindValue>,
+    pub keys: Vec<SortKey>,
+    /// Random order instead of `keys`; shuffled pages have no cursors
+    pub shuffle: bool,
+}
+
+impl KeysetQuery {
+    /// Sorted expressions and whether they are descending, with NULL ordering spelled out
+    fn columns(&self) -> Vec<(String, bool)> {
+        let mut columns = Vec::new();
+        for key in &self.keys {
+            if key.nullable {
+                columns.push((format!("({} IS NULL)", key.expr), false));
+            }
+            columns.push((key.expr.clone(), key.desc));
+        }
+        columns
+    }
+
+    /// Only the first `count` rows in the order of the keys; `id` must tell rows apart.
+    /// Pages of a capped query still stop at those rows, whatever cursor or offset is given.
+    pub fn capped(mut self, id: &str, count: i64) -> Self {
+        // MySQL refuses LIMIT directly in an IN subquery, but not in a derived table within it
+        let condition = format!(
+            "{id} IN (SELECT capped_id FROM (SELECT {id} AS capped_id {} {} ORDER BY {} LIMIT ?) capped)",
+            self.from,
+            where_clause(&self.conditions),
+            order_clause(&self.columns(), false)
+        );
+        let mut binds = self.binds.clone();
+        binds.push(BindValue::BigInt(count));
+        self.conditions.push(condition);
+        self.binds.extend(binds);
+        self
+    }
+}
+
+/// Rows after (or before) the key values of a cursor:
+/// `k1 > ? OR (k1 <=> ? AND k2 > ?) OR ...`, with `<` for descending keys
+fn keyset_condition(columns: &[(String, bool)], values: &[Value], backward: bool) -> (String, Vec<BindValue>) {
+    let values: Vec<Option<String>> = values
+        .iter()
+        .map(|v| match v {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        })
+        .collect();
+
+    let mut parts = Vec::new();
+    let mut binds = Vec::new();
+    for (i, (expr, desc)) in columns.iter().enumerate() {
+        let mut terms = Vec::new();
+        for (j, (previous, _)) in columns[..i].iter().enumerate() {
+            terms.push(format!("{} <=> ?", previous));
+            binds.push(BindValue::NullableText(values[j].clone()));
+        }
+        let op = if *desc != backward { "<" } else { ">" };
+        terms.push(format!("{} {} ?", expr, op));
+        binds.push(BindValue::NullableText(values[i].clone()));
+        parts.push(format!("({})", terms.join(" AND ")));
+    }
+    (format!("({})", parts.join(" OR ")), binds)
+}
+
+/// `ORDER BY` list of the columns, reversed when going backward
+fn order_clause(columns: &[(String, bool)], backward: bool) -> String {
+    columns
+        .iter()
+        .map(|(expr, desc)| format!("{} {}", expr, if *desc != backward { "DESC" } else { "ASC" }))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
+
+#[derive(QueryableByName)]
+struct Total {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+}
+
+/// A row, with the values of its sort keys as a JSON array
+struct Keyed<T> {
+    cursor_keys: String,
+    item: T,
+}
+
+impl<T: QueryableByName<Mysql>> QueryableByName<Mysql> for Keyed<T> {
+    fn build<'a>(row: &impl NamedRow<'a, Mysql>) -> deserialize::Result<Self> {
+        Ok(Keyed {
+            cursor_keys: NamedRow::get::<Text, String>(row, "cursor_keys")?,
+            item: T::build(row)?,
+        })
+    }
+}
+
+fn cursor_at<T>(row: Option<&Keyed<T>>, backward: bool) -> Option<String> {
+    let keys = serde_json::from_str::<Vec<Value>>(&row?.cursor_keys).ok()?;
+    Some(Cursor { keys, backward }.encode())
+}
+
+/// Every row of `query`, in order, for when the whole listing is needed at once
+pub fn load_all<T>(conn: &mut MysqlConnection, query: &KeysetQuery) -> QueryResult<Vec<T>>
+where
+    T: QueryableByName<Mysql> + 'static,
+{
+    let order = if query.shuffle {
+        "RAND()".to_string()
+    } else {
+        order_clause(&query.columns(), false)
+    };
+    let sql = format!(
+        "SELECT {} {} {} ORDER BY {}",
+        query.select,
+        query.from,
+        where_clause(&query.conditions),
+        order
+    );
+    bind_values(diesel::sql_query(sql).into_boxed::<Mysql>(), query.binds.clone()).load::<T>(conn)
+}
+
+/// SQL and binds of the page of `query` asked for, with one row more than the limit to
+/// know whether another page follows
+fn page_sql(query: &KeysetQuery, page: &PageRequest) -> Result<(String, Vec<BindValue>), PageError> {
+    let columns = query.columns();
+    let cursor = page.cursor.as_ref();
+    if let Some(cursor) = cursor
+        && (query.shuffle || cursor.keys.len() != columns.len())
+    {
+        return Err(PageError::InvalidCursor);
+    }
+    let backward = cursor.is_some_and(|c| c.backward);
+
+    let mut conditions = query.conditions.clone();
+    let mut binds = query.binds.clone();
+    if let Some(cursor) = cursor {
+        let (condition, values) = keyset_condition(&columns, &cursor.keys, backward);
+        conditions.push(condition);
+        binds.extend(values);
+    }
+
+    let order = if query.shuffle {
+        "RAND()".to_string()
+    } else {
+        order_clause(&columns, backward)
+    };
+    let key_values = columns.iter().map(|(expr, _)| expr.as_str()).collect::<Vec<_>>().join(", ");
+
+    binds.push(BindValue::BigInt(page.limit + 1));
+    binds.push(BindValue::BigInt(page.offset));
+
+    let sql = format!(
+        "SELECT {}, CAST(JSON_ARRAY({}) AS CHAR) AS cursor_keys {} {} ORDER BY {} LIMIT ? OFFSET ?",
+        query.select,
+        key_values,
+        query.from,
+        where_clause(&conditions),
+        order
+    );
+    Ok((sql, binds))
+}
+
+/// Load the page of `query` asked for, with the total count of the listing
+pub fn load_page<T>(conn: &mut MysqlConnection, query: &KeysetQuery, page: &PageRequest) -> Result<Page<T>, PageError>
+where
+    T: QueryableByName<Mysql> + 'static,
+{
+    let (sql, binds) = page_sql(query, page)?;
+    let cursor = page.cursor.as_ref();
+    let backward = cursor.is_some_and(|c| c.backward);
+
+    let count_sql = format!("SELECT COUNT(*) AS total {} {}", query.from, where_clause(&query.conditions));
+    let total = bind_values(diesel::sql_query(count_sql).into_boxed::<Mysql>(), query.binds.clone())
+        .get_result::<Total>(conn)?
+        .total;
+
+    let mut rows = bind_values(diesel::sql_query(sql).into_boxed::<Mysql>(), binds).load::<Keyed<T>>(conn)?;
+
+    let more = rows.len() as i64 > page.limit;
+    rows.truncate(page.limit as usize);
+    if backward {
+        rows.reverse();
+    }
+
+    let (next_cursor, prev_cursor) = if query.shuffle {
+        (None, None)
+    } else {
+        // Going backward, the page we came from follows; going forward, anything
+        // skipped by the cursor or the offset comes before
+        let has_next = backward || more;
+        let has_prev = if backward { more } else { cursor.is_some() || page.offset > 0 };
+        (
+            has_next.then(|| cursor_at(rows.last(), false)).flatten(),
+            has_prev.then(|| cursor_at(rows.first(), true)).flatten(),
+        )
+    };
+
+    Ok(Page {
+        items: rows.into_iter().map(|row| row.item).collect(),
+        total,
+        next_cursor,
+        prev_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token(json: &str) -> String {
+        hex::encode(json.as_bytes())
+    }
+
+    fn query(keys: Vec<SortKey>) -> KeysetQuery {
+        KeysetQuery {
+            select: "s.id".to_string(),
+            from: "FROM songs s".to_string(),
+            conditions: vec!["s.genre_id = ?".to_string()],
+            binds: vec![BindValue::Int(7)],
+            keys,
+            shuffle: false,
+        }
+    }
+
+    fn page(cursor: Option<Cursor>) -> PageRequest {
+        PageRequest { limit: 10, offset: 0, cursor }
+    }
+
+    fn text(value: &str) -> BindValue {
+        BindValue::NullableText(Some(value.to_string()))
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for backward in [false, true] {
+            let cursor = Cursor { keys: vec![json!("abc"), json!(3), json!(1.5), Value::Null], backward };
+            assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        }
+    }
+
+    #[test]
+    fn forward_cursor_leaves_out_direction() {
+        let cursor = Cursor { keys: vec![json!(1)], backward: false };
+        assert_eq!(cursor.encode(), token(r#"{"k":[1]}"#));
+    }
+
+    #[test]
+    fn tampered_cursors_are_rejected() {
+        let valid = Cursor { keys: vec![json!("abc"), json!(3)], backward: false }.encode();
+        assert!(Cursor::decode(&valid[..valid.len() - 2]).is_none());
+        assert!(Cursor::decode(&format!("{}zz", valid)).is_none());
+        assert!(Cursor::decode("").is_none());
+        assert!(Cursor::decode(&token("not json")).is_none());
+        assert!(Cursor::decode(&token(r#"{"k":[[1]]}"#)).is_none());
+        assert!(Cursor::decode(&token(r#"{"k":[{"a":1}]}"#)).is_none());
+        assert!(Cursor::decode(&token(r#"{"k":[1],"sql":"1=1"}"#)).is_none());
+        assert!(Cursor::decode(&token(r#"{"b":true}"#)).is_none());
+    }
+
+    #[test]
+    fn validate_pagination_checks_the_cursor() {
+        let with = |cursor: &str, offset| Pagination { limit: None, offset, cursor: Some(cursor.to_string()) };
+        let valid = Cursor { keys: vec![json!(1)], backward: true }.encode();
+        assert!(validate_pagination(&with(&valid, None)).unwrap().cursor.is_some());
+        assert!(validate_pagination(&with("abc", None)).is_err());
+        assert!(validate_pagination(&with(&valid, Some(5))).is_err());
+    }
+
+    #[test]
+    fn cursor_must_match_the_keys() {
+        let keyset = query(vec![SortKey::asc("s.title"), SortKey::asc("s.id")]);
+        let short = Cursor { keys: vec![json!("a")], backward: false };
+        assert!(matches!(page_sql(&keyset, &page(Some(short))), Err(PageError::InvalidCursor)));
+
+        let mut shuffled = keyset.clone();
+        shuffled.shuffle = true;
+        let cursor = Cursor { keys: vec![json!("a"), json!("id")], backward: false };
+        assert!(matches!(page_sql(&shuffled, &page(Some(cursor))), Err(PageError::InvalidCursor)));
+    }
+
+    #[test]
+    fn nullable_key_without_cursor_sorts_nulls_last() {
+        let keyset = query(vec![SortKey::desc("al.release_year").nullable(), SortKey::asc("s.id")]);
+        let (sql, binds) = page_sql(&keyset, &page(None)).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT s.id, CAST(JSON_ARRAY((al.release_year IS NULL), al.release_year, s.id) AS CHAR) AS cursor_keys \
+             FROM songs s WHERE s.genre_id = ? \
+             ORDER BY (al.release_year IS NULL) ASC, al.release_year DESC, s.id ASC LIMIT ? OFFSET ?"
+        );
+        assert_eq!(binds, vec![BindValue::Int(7), BindValue::BigInt(11), BindValue::BigInt(0)]);
+    }
+
+    #[test]
+    fn nullable_key_with_cursor_continues_after_its_values() {
+        let keyset = query(vec![SortKey::desc("al.release_year").nullable(), SortKey::asc("s.id")]);
+        let cursor = Cursor { keys: vec![json!(0), json!(1999), json!("id9")], backward: false };
+        let (sql, binds) = page_sql(&keyset, &page(Some(cursor))).unwrap();
+        assert!(sql.contains(
+            "WHERE s.genre_id = ? AND (((al.release_year IS NULL) > ?) \
+             OR ((al.release_year IS NULL) <=> ? AND al.release_year < ?) \
+             OR ((al.release_year IS NULL) <=> ? AND al.release_year <=> ? AND s.id > ?))"
+        ));
+        assert_eq!(
+            binds,
+            vec![
+                BindValue::Int(7),
+                text("0"),
+                text("0"),
+                text("1999"),
+                text("0"),
+                text("1999"),
+                text("id9"),
+                BindValue::BigInt(11),
+                BindValue::BigInt(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn nullable_key_with_null_in_cursor_binds_null() {
+        let keyset = query(vec![SortKey::desc("al.release_year").nullable(), SortKey::asc("s.id")]);
+        let cursor = Cursor { keys: vec![json!(1), Value::Null, json!("id9")], backward: false };
+        let (_, binds) = page_sql(&keyset, &page(Some(cursor))).unwrap();
+        // Among NULL years only the id moves the page on: `<=> NULL` matches them all
+        assert_eq!(binds[3], BindValue::NullableText(None));
+        assert_eq!(binds[5], BindValue::NullableText(None));
+        assert_eq!(binds[6], text("id9"));
+    }
+
+    #[test]
+    fn backward_cursor_flips_comparisons_and_order() {
+        let keyset = query(vec![SortKey::asc("s.title"), SortKey::asc("s.id")]);
+        let cursor = Cursor { keys: vec![json!("m"), json!("id5")], backward: true };
+        let (sql, _) = page_sql(&keyset, &page(Some(cursor))).unwrap();
+        assert!(sql.contains("((s.title < ?) OR (s.title <=> ? AND s.id < ?))"));
+        assert!(sql.contains("ORDER BY s.title DESC, s.id DESC"));
+    }
 }
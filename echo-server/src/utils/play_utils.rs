@@ -0,0 +1,314 @@
+use actix_web::web::Bytes;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, Instant};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::models::play_models::{NewPlay, PLAY_SOURCE_REPORT, PLAY_SOURCE_STREAM};
+use crate::schema::{now_playing, plays, songs};
+use crate::utils::range_utils::ByteRange;
+
+/// A stream completion within this many seconds of a previous play of the same song by the
+/// same user is the same play (e.g. a client re-fetching the tail of the file)
+const STREAM_PLAY_DEDUP_SECONDS: i64 = 30;
+
+/// Parts of a song streamed to a user are forgotten after this long without a new part
+const STREAM_PARTS_IDLE_SECONDS: u64 = 600;
+
+/// Songs streamed in parts tracked at once, to keep memory in check
+const MAX_STREAM_PARTS_ENTRIES: usize = 1000;
+
+/// Byte ranges streamed lately, merged, per user, song and object size
+type StreamParts = HashMap<(String, String, u64), (Instant, Vec<(u64, u64)>)>;
+static STREAM_PARTS: Lazy<Mutex<StreamParts>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Now-playing entries last for the song's duration plus this many seconds
+pub const NOW_PLAYING_GRACE_SECONDS: i64 = 60;
+
+/// Longest client name kept, matching `plays.client`
+const MAX_CLIENT_LENGTH: usize = 100;
+
+/// Trim a client name to something storable; blank names are dropped
+pub fn client_name(client: Option<&str>) -> Option<String> {
+    client
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| c.chars().take(MAX_CLIENT_LENGTH).collect())
+}
+
+/// Record a play, avoiding double counts between the two sources: a stream completion is
+/// dropped if the song was just played, and a report takes over the stream play the same
+/// playback produced (streams usually finish downloading before playback ends).
+/// Returns the id of the recorded play, or `None` if it was a duplicate.
+pub fn record_play(conn: &mut MysqlConnection, play: NewPlay) -> QueryResult<Option<String>> {
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        if play.source == PLAY_SOURCE_STREAM {
+            let recent = plays::table
+                .filter(plays::user_id.eq(&play.user_id))
+                .filter(plays::song_id.eq(&play.song_id))
+                .filter(plays::played_at.gt(play.played_at - Duration::seconds(STREAM_PLAY_DEDUP_SECONDS)))
+                .count()
+                .get_result::<i64>(conn)?;
+            if recent > 0 {
+                return Ok(None);
+            }
+        } else {
+            // Retried report
+            let same = plays::table
+                .filter(plays::user_id.eq(&play.user_id))
+                .filter(plays::song_id.eq(&play.song_id))
+                .filter(plays::played_at.eq(play.played_at))
+                .filter(plays::source.eq(PLAY_SOURCE_REPORT))
+                .count()
+                .get_result::<i64>(conn)?;
+            if same > 0 {
+                return Ok(None);
+            }
+
+            let duration = songs::table
+                .filter(songs::id.eq(&play.song_id))
+                .select(songs::duration_seconds)
+                .first::<i32>(conn)?;
+            let window_start = play.played_at - Duration::seconds(i64::from(duration) + STREAM_PLAY_DEDUP_SECONDS);
+            let stream_play = plays::table
+                .filter(plays::user_id.eq(&play.user_id))
+                .filter(plays::song_id.eq(&play.song_id))
+                .filter(plays::source.eq(PLAY_SOURCE_STREAM))
+                .filter(plays::played_at.between(window_start, play.played_at + Duration::seconds(STREAM_PLAY_DEDUP_SECONDS)))
+                .order(plays::played_at.desc())
+                .select(plays::id)
+                .first::<String>(conn)
+                .optional()?;
+
+            if let Some(play_id) = stream_play {
+                diesel::update(plays::table.filter(plays::id.eq(&play_id)))
+                    .set((
+                        plays::played_at.eq(play.played_at),
+                        plays::seconds_listened.eq(play.seconds_listened),
+                        plays::client.eq(&play.client),
+                        plays::source.eq(PLAY_SOURCE_REPORT),
+                    ))
+                    .execute(conn)?;
+                return Ok(Some(play_id));
+            }
+        }
+
+        diesel::insert_into(plays::table).values(&play).execute(conn)?;
+        Ok(Some(play.id))
+    })
+}
+
+/// Replace the user's now-playing entry
+pub fn set_now_playing(conn: &mut MysqlConnection, user_id: &str, song_id: &str, client: Option<String>) -> QueryResult<usize> {
+    diesel::replace_into(now_playing::table)
+        .values((
+            now_playing::user_id.eq(user_id),
+            now_playing::song_id.eq(song_id),
+            now_playing::client.eq(client),
+            now_playing::started_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+}
+
+/// Clear the user's now-playing entry if it is still about `song_id`
+pub fn clear_now_playing(conn: &mut MysqlConnection, user_id: &str, song_id: &str) -> QueryResult<usize> {
+    diesel::delete(
+        now_playing::table
+            .filter(now_playing::user_id.eq(user_id))
+            .filter(now_playing::song_id.eq(song_id)),
+    )
+    .execute(conn)
+}
+
+/// Add the inclusive range `start..=end` to sorted, disjoint `ranges`, merging it with the
+/// ranges it overlaps or touches
+fn merge_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    let (mut start, mut end) = (start, end);
+    ranges.retain(|&(s, e)| {
+        let apart = e.saturating_add(1) < start || end.saturating_add(1) < s;
+        if !apart {
+            start = start.min(s);
+            end = end.max(e);
+        }
+        apart
+    });
+    let at = ranges.partition_point(|&(s, _)| s < start);
+    ranges.insert(at, (start, end));
+}
+
+/// Whether `ranges` make up a whole object of `total_size` bytes
+fn covers_whole(ranges: &[(u64, u64)], total_size: u64) -> bool {
+    matches!(ranges, [(0, end)] if end.saturating_add(1) >= total_size)
+}
+
+/// Note that `range` of an object of `total_size` bytes was streamed; true once the parts
+/// streamed lately make up the whole object, which then starts over
+fn stream_part_completes(user_id: &str, song_id: &str, range: ByteRange, total_size: u64) -> bool {
+    let Ok(mut parts) = STREAM_PARTS.lock() else {
+        return false;
+    };
+    let idle = StdDuration::from_secs(STREAM_PARTS_IDLE_SECONDS);
+    if parts.len() >= MAX_STREAM_PARTS_ENTRIES {
+        parts.retain(|_, (updated_at, _)| updated_at.elapsed() < idle);
+    }
+
+    let key = (user_id.to_string(), song_id.to_string(), total_size);
+    let entry = parts.entry(key.clone()).or_insert_with(|| (Instant::now(), Vec::new()));
+    if entry.0.elapsed() >= idle {
+        entry.1.clear();
+    }
+    entry.0 = Instant::now();
+    merge_range(&mut entry.1, range.start, range.end);
+    if covers_whole(&entry.1, total_size) {
+        parts.remove(&key);
+        return true;
+    }
+    false
+}
+
+/// Records a play once the audio it is attached to has been sent completely
+pub struct PlayRecorder {
+    pub pool: DbPool,
+    pub user_id: String,
+    pub song_id: String,
+    pub client: Option<String>,
+}
+
+impl PlayRecorder {
+    /// Pass `source` through and record the play when it ends without error.
+    /// Nothing is recorded if the client disconnects first, since the stream is then dropped.
+    pub fn on_completion<S, E>(self, source: S) -> impl Stream<Item = Result<Bytes, E>> + Send + 'static
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+        E: Send + 'static,
+    {
+        async_stream::stream! {
+            let mut source = source;
+            while let Some(chunk) = source.next().await {
+                let failed = chunk.is_err();
+                yield chunk;
+                if failed {
+                    return;
+                }
+            }
+            self.record(Utc::now().naive_utc());
+        }
+    }
+
+    /// Like `on_completion` for `range` of an object of `total_size` bytes: the play is
+    /// recorded once the ranges streamed to the user lately make up the whole object, so
+    /// clients fetching in parts count while probes of the tail (ID3v1 tags) never do
+    pub fn on_part_completion<S, E>(
+        self,
+        source: S,
+        range: ByteRange,
+        total_size: u64,
+    ) -> impl Stream<Item = Result<Bytes, E>> + Send + 'static
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + Unpin + 'static,
+        E: Send + 'static,
+    {
+        async_stream::stream! {
+            let mut source = source;
+            while let Some(chunk) = source.next().await {
+                let failed = chunk.is_err();
+                yield chunk;
+                if failed {
+                    return;
+                }
+            }
+            if stream_part_completes(&self.user_id, &self.song_id, range, total_size) {
+                self.record(Utc::now().naive_utc());
+            }
+        }
+    }
+
+    fn record(self, played_at: NaiveDateTime) {
+        let play = NewPlay {
+            id: Uuid::new_v4().to_string(),
+            user_id: self.user_id,
+            song_id: self.song_id,
+            played_at,
+            seconds_listened: None,
+            client: self.client,
+            source: PLAY_SOURCE_STREAM.to_string(),
+        };
+        let pool = self.pool;
+        tokio::task::spawn_blocking(move || {
+            let result = pool
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|mut conn| record_play(&mut conn, play).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                eprintln!("Failed to record stream play: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn merge_range_keeps_ranges_sorted_and_disjoint() {
+        let mut ranges = Vec::new();
+        merge_range(&mut ranges, 500, 599);
+        merge_range(&mut ranges, 0, 99);
+        merge_range(&mut ranges, 300, 349);
+        assert_eq!(ranges, vec![(0, 99), (300, 349), (500, 599)]);
+
+        // Touching ranges merge, as do ranges bridged by a new one
+        merge_range(&mut ranges, 100, 149);
+        assert_eq!(ranges, vec![(0, 149), (300, 349), (500, 599)]);
+        merge_range(&mut ranges, 140, 520);
+        assert_eq!(ranges, vec![(0, 599)]);
+        merge_range(&mut ranges, 10, 20);
+        assert_eq!(ranges, vec![(0, 599)]);
+    }
+
+    #[test]
+    fn only_ranges_from_the_first_to_the_last_byte_cover_the_object() {
+        assert!(covers_whole(&[(0, 999)], 1000));
+        assert!(!covers_whole(&[(0, 998)], 1000));
+        assert!(!covers_whole(&[(1, 999)], 1000));
+        assert!(!covers_whole(&[(0, 499), (501, 999)], 1000));
+        assert!(!covers_whole(&[], 1000));
+    }
+
+    #[test]
+    fn tail_probes_are_not_plays() {
+        // ID3v1 tags live in the last 128 bytes
+        assert!(!stream_part_completes("tail-user", "song", part(872, 999), 1000));
+        assert!(!stream_part_completes("tail-user", "song", part(872, 999), 1000));
+        assert!(!stream_part_completes("tail-user", "song", part(0, 9), 1000));
+    }
+
+    #[test]
+    fn parts_making_up_the_object_are_one_play() {
+        assert!(!stream_part_completes("parts-user", "song", part(0, 399), 1000));
+        // Another user, song or object size is tracked apart
+        assert!(!stream_part_completes("other-user", "song", part(400, 999), 1000));
+        assert!(!stream_part_completes("parts-user", "other-song", part(400, 999), 1000));
+        assert!(!stream_part_completes("parts-user", "song", part(400, 999), 2000));
+        assert!(stream_part_completes("parts-user", "song", part(400, 999), 1000));
+
+        // Counted once: the next play starts over
+        assert!(!stream_part_completes("parts-user", "song", part(400, 999), 1000));
+        assert!(stream_part_completes("parts-user", "song", part(0, 399), 1000));
+    }
+
+    #[test]
+    fn a_range_of_the_whole_object_is_a_play() {
+        assert!(stream_part_completes("whole-user", "song", part(0, 999), 1000));
+    }
+}
@@ -0,0 +1,424 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::models::playlist_models::{ImportEntry, PlaylistFormat};
+use crate::models::song_models::SongResponse;
+
+const XSPF_NAMESPACE: &str = "http://xspf.org/ns/0/";
+
+static XSPF_TRACK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<track\b[^>]*>(.*?)</track>").unwrap());
+static XSPF_FIELD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)<(title|creator|album|duration|location)\b[^>]*>(.*?)</(?:title|creator|album|duration|location)>")
+        .unwrap()
+});
+static XSPF_TITLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<title\b[^>]*>(.*?)</title>").unwrap());
+static XML_ENTITY: Lazy<Regex> = Lazy::new(|| Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|[a-z]+);").unwrap());
+
+/// Write a playlist file; each song comes with the URL it streams from
+pub fn render_playlist(format: PlaylistFormat, title: &str, tracks: &[(SongResponse, String)]) -> String {
+    match format {
+        PlaylistFormat::M3u8 => render_m3u8(title, tracks),
+        PlaylistFormat::Xspf => render_xspf(title, tracks),
+        PlaylistFormat::Jspf => render_jspf(title, tracks),
+    }
+}
+
+/// Read the title, if any, and the entries of a playlist file
+pub fn parse_playlist(format: PlaylistFormat, body: &str) -> Result<(Option<String>, Vec<ImportEntry>), String> {
+    let body = body.trim_start_matches('\u{feff}');
+    match format {
+        PlaylistFormat::M3u8 => Ok(parse_m3u8(body)),
+        PlaylistFormat::Xspf => Ok(parse_xspf(body)),
+        PlaylistFormat::Jspf => parse_jspf(body),
+    }
+}
+
+// --------------------- M3U8 ---------------------
+
+/// Keep a value on its line
+fn one_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+fn render_m3u8(title: &str, tracks: &[(SongResponse, String)]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    out.push_str(&format!("#PLAYLIST:{}\n", one_line(title)));
+    for (song, url) in tracks {
+        let name = match song.artist_name.as_deref() {
+            Some(artist) => format!("{} - {}", artist, song.title),
+            None => song.title.clone(),
+        };
+        out.push_str(&format!("#EXTINF:{},{}\n{}\n", song.duration_seconds, one_line(&name), url));
+    }
+    out
+}
+
+/// `#EXTINF:<seconds>,<artist> - <title>` lines describe the location that follows them;
+/// entries without one are described by their file name
+fn parse_m3u8(body: &str) -> (Option<String>, Vec<ImportEntry>) {
+    let mut title = None;
+    let mut entries = Vec::new();
+    let mut pending: Option<ImportEntry> = None;
+
+    for line in body.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            title = Some(name.trim().to_string()).filter(|t| !t.is_empty());
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, name) = info.split_once(',').unwrap_or((info, ""));
+            // Durations may carry attributes (`123 tvg-id="..."`); -1 means unknown
+            let duration_seconds = duration
+                .split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d > 0.0)
+                .map(|d| d.round() as i32);
+            let mut entry = entry_from_name(name);
+            entry.duration_seconds = duration_seconds;
+            pending = Some(entry);
+        } else if line.starts_with('#') {
+            // Other directives and comments
+        } else {
+            let mut entry = pending.take().unwrap_or_else(|| entry_from_name(file_stem(line)));
+            entry.location = Some(line.to_string());
+            entries.push(entry);
+        }
+    }
+    (title, entries)
+}
+
+/// Last path segment of a location, without its extension
+fn file_stem(location: &str) -> &str {
+    let name = location.rsplit(['/', '\\']).next().unwrap_or(location);
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+/// Entry from an "Artist - Title" name, or just a title
+fn entry_from_name(name: &str) -> ImportEntry {
+    let name = name.trim();
+    let (artist, title) = match name.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim()), title.trim()),
+        None => (None, name),
+    };
+    ImportEntry {
+        title: Some(title.to_string()).filter(|t| !t.is_empty()),
+        artist: artist.filter(|a| !a.is_empty()).map(str::to_string),
+        ..Default::default()
+    }
+}
+
+// --------------------- XSPF ---------------------
+
+fn xml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn xml_unescape(value: &str) -> String {
+    let value = value.trim();
+    if let Some(data) = value.strip_prefix("<![CDATA[").and_then(|v| v.strip_suffix("]]>")) {
+        return data.to_string();
+    }
+    XML_ENTITY
+        .replace_all(value, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            // Unknown entities are left as they are
+            c.map(String::from).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+fn push_element(out: &mut String, indent: &str, name: &str, value: &str) {
+    out.push_str(&format!("{indent}<{name}>{}</{name}>\n", xml_escape(value)));
+}
+
+fn render_xspf(title: &str, tracks: &[(SongResponse, String)]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!("<playlist version=\"1\" xmlns=\"{}\">\n", XSPF_NAMESPACE));
+    push_element(&mut out, "  ", "title", title);
+    out.push_str("  <trackList>\n");
+    for (song, url) in tracks {
+        out.push_str("    <track>\n");
+        push_element(&mut out, "      ", "location", url);
+        push_element(&mut out, "      ", "title", &song.title);
+        if let Some(artist) = song.artist_name.as_deref() {
+            push_element(&mut out, "      ", "creator", artist);
+        }
+        if let Some(album) = song.album_name.as_deref() {
+            push_element(&mut out, "      ", "album", album);
+        }
+        if let Some(track) = song.track_number {
+            push_element(&mut out, "      ", "trackNum", &track.to_string());
+        }
+        // XSPF durations are in milliseconds
+        push_element(&mut out, "      ", "duration", &(i64::from(song.duration_seconds) * 1000).to_string());
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+fn parse_xspf(body: &str) -> (Option<String>, Vec<ImportEntry>) {
+    // The playlist's own title comes before its tracks
+    let head = body.split("<trackList").next().unwrap_or("");
+    let title = XSPF_TITLE
+        .captures(head)
+        .map(|c| xml_unescape(&c[1]))
+        .filter(|t| !t.is_empty());
+
+    let entries = XSPF_TRACK
+        .captures_iter(body)
+        .map(|track| {
+            let mut entry = ImportEntry::default();
+            let mut duration = None;
+            for field in XSPF_FIELD.captures_iter(&track[1]) {
+                let value = Some(xml_unescape(&field[2])).filter(|v| !v.is_empty());
+                // A track may list several locations; the first one is kept
+                let slot = match &field[1] {
+                    "title" => &mut entry.title,
+                    "creator" => &mut entry.artist,
+                    "album" => &mut entry.album,
+                    "location" => &mut entry.location,
+                    _ => &mut duration,
+                };
+                if slot.is_none() {
+                    *slot = value;
+                }
+            }
+            entry.duration_seconds = duration.and_then(|d| milliseconds_to_seconds(&Value::String(d)));
+            entry
+        })
+        .collect();
+    (title, entries)
+}
+
+// --------------------- JSPF ---------------------
+
+fn render_jspf(title: &str, tracks: &[(SongResponse, String)]) -> String {
+    let tracks: Vec<Value> = tracks
+        .iter()
+        .map(|(song, url)| {
+            let mut track = json!({
+                "location": [url],
+                "title": song.title,
+                "duration": i64::from(song.duration_seconds) * 1000,
+            });
+            if let Some(artist) = &song.artist_name {
+                track["creator"] = json!(artist);
+            }
+            if let Some(album) = &song.album_name {
+                track["album"] = json!(album);
+            }
+            if let Some(number) = song.track_number {
+                track["trackNum"] = json!(number);
+            }
+            track
+        })
+        .collect();
+    json!({ "playlist": { "title": title, "track": tracks } }).to_string()
+}
+
+/// Milliseconds, as a number or a string, in whole seconds
+fn milliseconds_to_seconds(value: &Value) -> Option<i32> {
+    let ms = match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }?;
+    (ms > 0.0).then(|| (ms / 1000.0).round() as i32)
+}
+
+fn parse_jspf(body: &str) -> Result<(Option<String>, Vec<ImportEntry>), String> {
+    let root: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSPF: {}", e))?;
+    let playlist = root.get("playlist").ok_or("Invalid JSPF: no playlist object")?;
+
+    let text = |value: Option<&Value>| {
+        value
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+
+    let entries = playlist
+        .get("track")
+        .and_then(Value::as_array)
+        .map(|tracks| {
+            tracks
+                .iter()
+                .map(|track| {
+                    // The spec has a list of locations, some writers a single one
+                    let location = match track.get("location") {
+                        Some(Value::Array(locations)) => text(locations.first()),
+                        other => text(other),
+                    };
+                    ImportEntry {
+                        title: text(track.get("title")),
+                        artist: text(track.get("creator")),
+                        album: text(track.get("album")),
+                        duration_seconds: track.get("duration").and_then(milliseconds_to_seconds),
+                        location,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok((text(playlist.get("title")), entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(title: &str, artist: Option<&str>, album: Option<&str>, duration_seconds: i32) -> SongResponse {
+        SongResponse {
+            id: "3f2504e0-4f89-11d3-9a0c-0305e82c3301".to_string(),
+            title: title.to_string(),
+            artist_id: "artist".to_string(),
+            artist_name: artist.map(str::to_string),
+            album_id: None,
+            album_name: album.map(str::to_string),
+            genre_id: None,
+            genre_name: None,
+            track_number: Some(4),
+            duration_seconds,
+            object_url: String::new(),
+            track_gain: None,
+            track_peak: None,
+            album_gain: None,
+            album_peak: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn tracks() -> Vec<(SongResponse, String)> {
+        vec![
+            (
+                song("Rock & Roll <Live>", Some("Led \"Zep\""), Some("Tom's Album"), 245),
+                "https://music.example/api/stream/3f2504e0?sig=a&exp=1".to_string(),
+            ),
+            (song("Untitled", None, None, 61), "https://music.example/api/stream/other".to_string()),
+        ]
+    }
+
+    fn entry(title: &str, artist: Option<&str>, album: Option<&str>, duration_seconds: i32, location: &str) -> ImportEntry {
+        ImportEntry {
+            title: Some(title.to_string()),
+            artist: artist.map(str::to_string),
+            album: album.map(str::to_string),
+            duration_seconds: Some(duration_seconds),
+            location: Some(location.to_string()),
+        }
+    }
+
+    #[test]
+    fn m3u8_round_trip() {
+        let body = render_playlist(PlaylistFormat::M3u8, "Road\ntrip", &tracks());
+        let (title, entries) = parse_playlist(PlaylistFormat::M3u8, &body).unwrap();
+
+        // M3U8 has no album, and line breaks cannot survive
+        assert_eq!(title.as_deref(), Some("Road trip"));
+        assert_eq!(entries, vec![
+            entry("Rock & Roll <Live>", Some("Led \"Zep\""), None, 245, "https://music.example/api/stream/3f2504e0?sig=a&exp=1"),
+            entry("Untitled", None, None, 61, "https://music.example/api/stream/other"),
+        ]);
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let body = render_playlist(PlaylistFormat::Xspf, "Mine & yours", &tracks());
+        assert!(body.contains("<title>Rock &amp; Roll &lt;Live&gt;</title>"));
+        assert!(body.contains("<creator>Led &quot;Zep&quot;</creator>"));
+
+        let (title, entries) = parse_playlist(PlaylistFormat::Xspf, &body).unwrap();
+        assert_eq!(title.as_deref(), Some("Mine & yours"));
+        assert_eq!(entries, vec![
+            entry("Rock & Roll <Live>", Some("Led \"Zep\""), Some("Tom's Album"), 245, "https://music.example/api/stream/3f2504e0?sig=a&exp=1"),
+            entry("Untitled", None, None, 61, "https://music.example/api/stream/other"),
+        ]);
+    }
+
+    #[test]
+    fn jspf_round_trip() {
+        let body = render_playlist(PlaylistFormat::Jspf, "Mine & yours", &tracks());
+        let (title, entries) = parse_playlist(PlaylistFormat::Jspf, &body).unwrap();
+        assert_eq!(title.as_deref(), Some("Mine & yours"));
+        assert_eq!(entries, vec![
+            entry("Rock & Roll <Live>", Some("Led \"Zep\""), Some("Tom's Album"), 245, "https://music.example/api/stream/3f2504e0?sig=a&exp=1"),
+            entry("Untitled", None, None, 61, "https://music.example/api/stream/other"),
+        ]);
+    }
+
+    #[test]
+    fn jspf_rejects_other_json() {
+        assert!(parse_playlist(PlaylistFormat::Jspf, "{\"title\": \"x\"}").is_err());
+        assert!(parse_playlist(PlaylistFormat::Jspf, "not json").is_err());
+    }
+
+    #[test]
+    fn xml_escape_covers_markup_characters() {
+        assert_eq!(xml_escape(r#"<a href="x">Tom & Jerry's</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+        assert_eq!(xml_unescape(&xml_escape(r#"<&>"'"#)), r#"<&>"'"#);
+    }
+
+    #[test]
+    fn xml_unescape_reads_numeric_entities_and_cdata() {
+        assert_eq!(xml_unescape("Caf&#233; &#x263A;"), "Café ☺");
+        assert_eq!(xml_unescape(" <![CDATA[a < b & c]]> "), "a < b & c");
+        // Unknown entities are kept as written, not double-decoded
+        assert_eq!(xml_unescape("&nbsp;&amp;lt;"), "&nbsp;&lt;");
+    }
+
+    #[test]
+    fn extinf_lines_describe_the_next_location() {
+        let body = "\u{feff}#EXTM3U\n\
+                    #EXTINF:123 tvg-id=\"x\",Artist - Song - Live\n\
+                    # a comment\n\
+                    song.mp3\n\
+                    #EXTINF:-1,Just A Title\n\
+                    http://radio.example/stream\n\
+                    C:\\Music\\Band - Other.flac\n\
+                    #EXTINF:59.6,\n\
+                    empty.ogg\n";
+        let (title, entries) = parse_playlist(PlaylistFormat::M3u8, body).unwrap();
+
+        assert_eq!(title, None);
+        assert_eq!(entries, vec![
+            // Only the first " - " separates the artist from the title
+            entry("Song - Live", Some("Artist"), None, 123, "song.mp3"),
+            // -1 is an unknown duration
+            ImportEntry { duration_seconds: None, ..entry("Just A Title", None, None, 0, "http://radio.example/stream") },
+            // No #EXTINF: described by its file name
+            ImportEntry { duration_seconds: None, ..entry("Other", Some("Band"), None, 0, "C:\\Music\\Band - Other.flac") },
+            ImportEntry { title: None, ..entry("", None, None, 60, "empty.ogg") },
+        ]);
+    }
+}
@@ -0,0 +1,384 @@
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Text;
+
+use crate::models::playlist_models::{NewPlaylistSong, Playlist, SmartRules, KIND_SMART, ROLE_EDITOR, ROLE_VIEWER};
+use crate::schema::{playlist_members, playlist_songs, playlists};
+use crate::utils::pagination_utils::{KeysetQuery, SortKey};
+use crate::utils::song_query_utils::{SongFilter, SongListing, SongSource};
+use crate::utils::sql_utils::{bind_values, BindValue};
+
+/// Songs a smart playlist keeps when its rules give no limit
+pub const SMART_DEFAULT_LIMIT: i64 = 100;
+pub const SMART_MAX_LIMIT: i64 = 500;
+const SMART_MAX_ARTISTS: usize = 100;
+
+/// Followers of `pl`, usable in `WHERE` and `ORDER BY` alike
+const FOLLOWER_COUNT: &str = "(SELECT COUNT(*) FROM playlist_follows pf WHERE pf.playlist_id = pl.id)";
+
+/// Sort keys of a public playlist listing: "popular", "recent" or "name"
+pub fn public_sort_keys(sort: &str) -> Option<Vec<SortKey>> {
+    let first = match sort {
+        "popular" => SortKey::desc(FOLLOWER_COUNT),
+        "recent" => SortKey::desc("pl.created_at").nullable(),
+        "name" => SortKey::asc("pl.name"),
+        _ => return None,
+    };
+    Some(vec![first, SortKey::asc("pl.id")])
+}
+
+/// Playlists `pl` matching `conditions`, as `PlaylistSummary`
+pub fn playlist_summaries(conditions: Vec<String>, binds: Vec<BindValue>, keys: Vec<SortKey>) -> KeysetQuery {
+    KeysetQuery {
+        select: format!(
+            "pl.id, pl.user_id, u.username AS owner, pl.name, pl.description, pl.kind, pl.created_at, \
+             (SELECT COUNT(*) FROM playlist_songs ps WHERE ps.playlist_id = pl.id) AS song_count, \
+             {FOLLOWER_COUNT} AS follower_count"
+        ),
+        from: "FROM playlists pl JOIN users u ON pl.user_id = u.id".to_string(),
+        conditions,
+        binds,
+        keys,
+        shuffle: false,
+    }
+}
+
+/// The songs of a smart playlist of `owner_id`, listed from its rules
+pub fn smart_listing(rules: &SmartRules, owner_id: &str) -> Result<SongListing, String> {
+    let limit = rules.limit.unwrap_or(SMART_DEFAULT_LIMIT);
+    if !(1..=SMART_MAX_LIMIT).contains(&limit) {
+        return Err(format!("limit must be between 1 and {}", SMART_MAX_LIMIT));
+    }
+    if rules.artist_ids.len() > SMART_MAX_ARTISTS {
+        return Err(format!("artist_ids can hold at most {} artists", SMART_MAX_ARTISTS));
+    }
+    if rules.added_within_days.is_some_and(|d| d < 1) {
+        return Err("added_within_days must be at least 1".to_string());
+    }
+    if rules.duration_max.is_some_and(|d| d < 1) {
+        return Err("duration_max must be at least 1".to_string());
+    }
+    if rules.play_count_above.is_some_and(|c| c < 0) {
+        return Err("play_count_above cannot be negative".to_string());
+    }
+
+    let filters = [
+        rules.genre_id.map(SongFilter::GenreId),
+        (!rules.artist_ids.is_empty()).then(|| SongFilter::ArtistIds(rules.artist_ids.clone())),
+        rules.added_within_days.map(SongFilter::AddedWithinDays),
+        rules.duration_max.map(SongFilter::DurationMax),
+        rules.in_favorites.then(|| SongFilter::FavoriteOf(owner_id.to_string())),
+        rules.play_count_above.map(|c| SongFilter::PlayedMoreThan(owner_id.to_string(), c)),
+    ];
+
+    let listing = SongListing::new(SongSource::All)
+        .filters(filters.into_iter().flatten())
+        .cap(limit);
+    match rules.sort.as_deref() {
+        Some(sort) => listing.sort_by(sort),
+        None => Ok(listing),
+    }
+}
+
+/// Rules of a smart playlist as stored; unreadable ones count as no rules
+pub fn stored_rules(rules: Option<&str>) -> SmartRules {
+    rules.and_then(|r| serde_json::from_str::<SmartRules>(r).ok()).unwrap_or_default()
+}
+
+/// Every song of a playlist in its own order: by position, or as its rules list them
+pub fn ordered_listing(playlist: &Playlist) -> Result<SongListing, String> {
+    if playlist.kind == KIND_SMART {
+        smart_listing(&stored_rules(playlist.rules.as_deref()), &playlist.user_id)
+    } else {
+        Ok(SongListing::new(SongSource::Playlist(playlist.id.clone())))
+    }
+}
+
+/// What a user may do with a playlist, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlaylistAccess {
+    /// See it and list its songs
+    Viewer,
+    /// Also add, move and remove songs
+    Editor,
+    /// Also rename, delete and share it
+    Owner,
+}
+
+/// Access of `user_id` to the playlist `playlist_id` of `owner_id`: `None` when there is no
+/// such playlist or it is private and not shared with the user. Public playlists give viewers.
+pub fn playlist_access(
+    conn: &mut MysqlConnection,
+    playlist_id: &str,
+    owner_id: &str,
+    user_id: &str,
+) -> QueryResult<Option<PlaylistAccess>> {
+    let is_public = playlists::table
+        .filter(playlists::id.eq(playlist_id))
+        .filter(playlists::user_id.eq(owner_id))
+        .select(playlists::is_public)
+        .first::<Option<bool>>(conn)
+        .optional()?;
+    let Some(is_public) = is_public else {
+        return Ok(None);
+    };
+    if owner_id == user_id {
+        return Ok(Some(PlaylistAccess::Owner));
+    }
+
+    let role = playlist_members::table
+        .filter(playlist_members::playlist_id.eq(playlist_id))
+        .filter(playlist_members::user_id.eq(user_id))
+        .select(playlist_members::role)
+        .first::<String>(conn)
+        .optional()?;
+    Ok(match role.as_deref() {
+        Some(ROLE_EDITOR) => Some(PlaylistAccess::Editor),
+        Some(ROLE_VIEWER) => Some(PlaylistAccess::Viewer),
+        _ if is_public.unwrap_or(false) => Some(PlaylistAccess::Viewer),
+        _ => None,
+    })
+}
+
+// Playlist order is kept by the server: positions always run from 0 to the number of
+// songs minus one. Every change locks the playlist, edits the order in memory and writes
+// it back whole, inside the caller's transaction.
+
+/// Why a change to the order of a playlist was refused
+#[derive(Debug)]
+pub enum OrderError {
+    /// Position outside the playlist; holds the highest one allowed
+    Position(usize),
+    AlreadyInPlaylist,
+    NotInPlaylist,
+    /// Reorder list that is not every song of the playlist exactly once
+    Mismatch,
+    /// Smart playlists only change through their rules
+    SmartPlaylist,
+    Database(DieselError),
+}
+
+impl From<DieselError> for OrderError {
+    fn from(e: DieselError) -> Self {
+        OrderError::Database(e)
+    }
+}
+
+/// Song ids of the playlist in order. Locks the playlist row, so edits of the same
+/// playlist wait for each other until the transaction ends.
+pub fn lock_order(conn: &mut MysqlConnection, playlist_id: &str) -> Result<Vec<String>, OrderError> {
+    let kind = playlists::table
+        .find(playlist_id)
+        .select(playlists::kind)
+        .for_update()
+        .first::<String>(conn)?;
+    if kind == KIND_SMART {
+        return Err(OrderError::SmartPlaylist);
+    }
+    Ok(song_order(conn, playlist_id)?)
+}
+
+/// Song ids of the playlist in order
+pub fn song_order(conn: &mut MysqlConnection, playlist_id: &str) -> QueryResult<Vec<String>> {
+    playlist_songs::table
+        .filter(playlist_songs::playlist_id.eq(playlist_id))
+        .order((playlist_songs::position.asc(), playlist_songs::added_at.asc(), playlist_songs::song_id.asc()))
+        .select(playlist_songs::song_id)
+        .load::<String>(conn)
+}
+
+/// Position of a song inserted but not placed yet, below every position `write_order`
+/// parks songs at
+const STAGED_POSITION: i32 = i32::MIN;
+
+/// The two passes of `write_order`, as (song id, position) pairs. (playlist_id, position) is
+/// unique and checked row by row, so every song is first parked at a distinct negative
+/// position, then given its place: neither pass can hit a position still taken.
+fn order_passes(order: &[String]) -> [Vec<(&str, i32)>; 2] {
+    let parked = order.iter().enumerate().map(|(i, id)| (id.as_str(), -1 - i as i32)).collect();
+    let placed = order.iter().enumerate().map(|(i, id)| (id.as_str(), i as i32)).collect();
+    [parked, placed]
+}
+
+/// Give the songs of `order` the positions 0, 1, 2...
+pub fn write_order(conn: &mut MysqlConnection, playlist_id: &str, order: &[String]) -> QueryResult<()> {
+    if order.is_empty() {
+        return Ok(());
+    }
+
+    for pass in order_passes(order) {
+        let cases = vec!["WHEN ? THEN ?"; pass.len()].join(" ");
+        let sql = format!("UPDATE playlist_songs SET position = CASE song_id {cases} ELSE position END WHERE playlist_id = ?");
+        let mut binds: Vec<BindValue> = pass
+            .into_iter()
+            .flat_map(|(id, position)| [BindValue::Text(id.to_string()), BindValue::Int(position)])
+            .collect();
+        binds.push(BindValue::Text(playlist_id.to_string()));
+        bind_values(diesel::sql_query(sql).into_boxed::<Mysql>(), binds).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Add a song at `position`, or at the end, moving the songs from there down by one.
+/// Returns the position given to the song.
+pub fn insert_song(
+    conn: &mut MysqlConnection,
+    playlist_id: &str,
+    song_id: &str,
+    position: Option<i32>,
+    added_by: &str,
+) -> Result<i32, OrderError> {
+    let mut order = lock_order(conn, playlist_id)?;
+    if order.iter().any(|id| id == song_id) {
+        return Err(OrderError::AlreadyInPlaylist);
+    }
+    let index = match position {
+        None => order.len(),
+        Some(p) if p >= 0 && p as usize <= order.len() => p as usize,
+        Some(_) => return Err(OrderError::Position(order.len())),
+    };
+
+    // Positions in use are never negative, and parking the others cannot land on this one
+    diesel::insert_into(playlist_songs::table)
+        .values(&NewPlaylistSong {
+            playlist_id: playlist_id.to_string(),
+            song_id: song_id.to_string(),
+            position: STAGED_POSITION,
+            added_by: Some(added_by.to_string()),
+        })
+        .execute(conn)?;
+
+    order.insert(index, song_id.to_string());
+    write_order(conn, playlist_id, &order)?;
+    Ok(index as i32)
+}
+
+/// Move a song to `position`, shifting the songs in between
+pub fn move_song(conn: &mut MysqlConnection, playlist_id: &str, song_id: &str, position: i32) -> Result<(), OrderError> {
+    let mut order = lock_order(conn, playlist_id)?;
+    let from = order.iter().position(|id| id == song_id).ok_or(OrderError::NotInPlaylist)?;
+    if position < 0 || position as usize >= order.len() {
+        return Err(OrderError::Position(order.len() - 1));
+    }
+
+    let song = order.remove(from);
+    order.insert(position as usize, song);
+    write_order(conn, playlist_id, &order)?;
+    Ok(())
+}
+
+/// Put the songs of the playlist in the order of `song_ids`, which must list each of them once
+pub fn reorder_songs(conn: &mut MysqlConnection, playlist_id: &str, song_ids: &[String]) -> Result<(), OrderError> {
+    let order = lock_order(conn, playlist_id)?;
+
+    let mut current = order.clone();
+    let mut requested = song_ids.to_vec();
+    current.sort();
+    requested.sort();
+    if current != requested {
+        return Err(OrderError::Mismatch);
+    }
+
+    write_order(conn, playlist_id, song_ids)?;
+    Ok(())
+}
+
+/// Remove a song and close the gap it leaves
+pub fn remove_song(conn: &mut MysqlConnection, playlist_id: &str, song_id: &str) -> Result<(), OrderError> {
+    let mut order = lock_order(conn, playlist_id)?;
+    let index = order.iter().position(|id| id == song_id).ok_or(OrderError::NotInPlaylist)?;
+
+    diesel::delete(
+        playlist_songs::table
+            .filter(playlist_songs::playlist_id.eq(playlist_id))
+            .filter(playlist_songs::song_id.eq(song_id)),
+    )
+    .execute(conn)?;
+
+    order.remove(index);
+    write_order(conn, playlist_id, &order)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// Apply a pass of `write_order` to `rows` one row at a time, in the given order, as MySQL
+    /// does while checking the unique (playlist_id, position) key after each row.
+    /// Returns the position that collided.
+    fn apply_pass(
+        rows: &mut [(String, i32)],
+        pass: &[(&str, i32)],
+        row_order: impl Iterator<Item = usize>,
+    ) -> Result<(), i32> {
+        for row in row_order {
+            let Some(&(_, target)) = pass.iter().find(|(id, _)| *id == rows[row].0) else {
+                continue;
+            };
+            if rows.iter().enumerate().any(|(other, (_, p))| other != row && *p == target) {
+                return Err(target);
+            }
+            rows[row].1 = target;
+        }
+        Ok(())
+    }
+
+    /// Run `write_order` on `rows` in both row orders, returning the song ids by position
+    fn write(rows: &[(String, i32)], order: &[String]) -> Vec<String> {
+        let mut results = Vec::new();
+        for reversed in [false, true] {
+            let mut rows = rows.to_vec();
+            for pass in order_passes(order) {
+                let row_order: Vec<usize> =
+                    if reversed { (0..rows.len()).rev().collect() } else { (0..rows.len()).collect() };
+                apply_pass(&mut rows, &pass, row_order.into_iter()).unwrap();
+            }
+            rows.sort_by_key(|(_, p)| *p);
+            assert!(rows.iter().enumerate().all(|(i, (_, p))| *p == i as i32));
+            results.push(rows.into_iter().map(|(id, _)| id).collect::<Vec<_>>());
+        }
+        assert_eq!(results[0], results[1]);
+        results.remove(0)
+    }
+
+    fn numbered(order: &[&str]) -> Vec<(String, i32)> {
+        order.iter().enumerate().map(|(i, id)| (id.to_string(), i as i32)).collect()
+    }
+
+    #[test]
+    fn passes_park_then_place_every_song() {
+        let order = ids(&["b", "a"]);
+        let [parked, placed] = order_passes(&order);
+        assert_eq!(parked, vec![("b", -1), ("a", -2)]);
+        assert_eq!(placed, vec![("b", 0), ("a", 1)]);
+    }
+
+    #[test]
+    fn reordering_renumbers_without_collisions() {
+        let order = ids(&["c", "a", "b"]);
+        assert_eq!(write(&numbered(&["a", "b", "c"]), &order), order);
+    }
+
+    #[test]
+    fn inserting_a_staged_song_renumbers_without_collisions() {
+        for index in 0..=3 {
+            let mut current = numbered(&["a", "b", "c"]);
+            current.push(("new".to_string(), STAGED_POSITION));
+            let mut order = ids(&["a", "b", "c"]);
+            order.insert(index, "new".to_string());
+            assert_eq!(write(&current, &order), order);
+        }
+    }
+
+    #[test]
+    fn inserting_into_an_empty_playlist_places_the_staged_song() {
+        let order = ids(&["new"]);
+        assert_eq!(write(&[("new".to_string(), STAGED_POSITION)], &order), order);
+    }
+}
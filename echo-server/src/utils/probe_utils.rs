@@ -0,0 +1,110 @@
+use ffmpeg_next as ffmpeg;
+use std::error::Error;
+use std::path::Path;
+use uuid::Uuid;
+
+/// A stated duration may differ from the probed one by this many seconds...
+const DURATION_TOLERANCE_SECONDS: i32 = 5;
+/// ...or by this fraction of the probed duration, whichever is larger
+const DURATION_TOLERANCE_RATIO: f64 = 0.1;
+
+/// Duration and tags read from an uploaded file
+#[derive(Debug, Default)]
+pub struct AudioProbe {
+    /// Extension to store the original file under; `None` for formats clients may not play
+    pub extension: Option<&'static str>,
+    pub duration_seconds: Option<i32>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+    pub track_number: Option<i32>,
+}
+
+pub async fn probe_audio_async(input: &[u8]) -> Result<AudioProbe, Box<dyn Error + Send + Sync>> {
+    // libavformat probes files by path, so the upload is spilled to a temp file
+    let path = std::env::temp_dir().join(format!("echo-probe-{}", Uuid::new_v4()));
+    tokio::fs::write(&path, input).await?;
+
+    let probe_path = path.clone();
+    let result = tokio::task::spawn_blocking(move || probe_file(&probe_path)).await;
+
+    let _ = tokio::fs::remove_file(&path).await;
+    result?
+}
+
+fn probe_file(path: &Path) -> Result<AudioProbe, Box<dyn Error + Send + Sync>> {
+    ffmpeg::init()?;
+
+    let context = ffmpeg::format::input(&path)?;
+    let stream = context
+        .streams()
+        .best(ffmpeg::media::Type::Audio)
+        .ok_or("No audio stream found")?;
+
+    let duration = if context.duration() > 0 {
+        Some(context.duration() as f64 / ffmpeg::ffi::AV_TIME_BASE as f64)
+    } else if stream.duration() > 0 {
+        Some(stream.duration() as f64 * f64::from(stream.time_base()))
+    } else {
+        None
+    };
+
+    // ID3 tags live on the container, Vorbis comments (Ogg) on the audio stream.
+    // Lookups are case-insensitive.
+    let container_tags = context.metadata();
+    let stream_tags = stream.metadata();
+    let tag = |key: &str| -> Option<String> {
+        container_tags
+            .get(key)
+            .or_else(|| stream_tags.get(key))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+
+    Ok(AudioProbe {
+        extension: extension_for_format(context.format().name()),
+        duration_seconds: duration.map(|d| d.round() as i32),
+        title: tag("title"),
+        artist: tag("artist").or_else(|| tag("album_artist")),
+        album: tag("album"),
+        genre: tag("genre"),
+        year: tag("date").or_else(|| tag("year")).as_deref().and_then(parse_year),
+        track_number: tag("track").as_deref().and_then(parse_track_number),
+    })
+}
+
+/// Map a libavformat demuxer name (e.g. "mov,mp4,m4a,3gp,3g2,mj2") to a file extension
+fn extension_for_format(format_name: &str) -> Option<&'static str> {
+    match format_name.split(',').next()? {
+        "mp3" => Some("mp3"),
+        "flac" => Some("flac"),
+        "ogg" => Some("ogg"),
+        "mov" => Some("m4a"),
+        "aac" => Some("aac"),
+        "wav" => Some("wav"),
+        _ => None,
+    }
+}
+
+/// "2019", "2019-04-12" -> 2019
+fn parse_year(value: &str) -> Option<i32> {
+    value.get(..4).and_then(|y| y.parse().ok())
+}
+
+/// "3", "3/12" -> 3
+fn parse_track_number(value: &str) -> Option<i32> {
+    value
+        .split('/')
+        .next()
+        .and_then(|n| n.trim().parse().ok())
+        .filter(|n| *n > 0)
+}
+
+/// Whether a client-stated duration is too far off the probed one to be trusted
+pub fn duration_mismatch(stated_seconds: i32, probed_seconds: i32) -> bool {
+    let tolerance = (probed_seconds as f64 * DURATION_TOLERANCE_RATIO).max(DURATION_TOLERANCE_SECONDS as f64);
+    ((stated_seconds - probed_seconds) as f64).abs() > tolerance
+}
@@ -0,0 +1,377 @@
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Nullable, Text, Timestamp};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::models::radio_models::{
+    NewRadioTrack, RadioCandidate, RadioStation, RadioTrack, SEED_ARTIST, SEED_GENRE, SEED_SONG,
+};
+use crate::schema::{artists, genres, radio_tracks, songs};
+use crate::utils::song_query_utils::{SONG_COLUMNS, SONG_JOINS};
+
+// Base score of a candidate, on top of 1 for every song so a station never runs dry
+const RADIO_ARTIST_WEIGHT: i64 = 4;
+const RADIO_GENRE_WEIGHT: i64 = 3;
+/// Artists sharing a playlist with the seed artist, or making songs in the seed genre
+const RADIO_ADJACENT_ARTIST_WEIGHT: i64 = 2;
+/// Genres sharing an artist with the seed artist or genre
+const RADIO_ADJACENT_GENRE_WEIGHT: i64 = 1;
+
+/// Best scored songs a page is drawn from
+const RADIO_CANDIDATES: i64 = 300;
+/// A song is not repeated within this many tracks of a station...
+const RADIO_RECENT_TRACKS: i32 = 50;
+/// ...nor if the user played it this recently, unless nothing else is left
+const RADIO_REPEAT_HOURS: i64 = 6;
+
+/// Skips older than this are forgotten
+const SKIP_MEMORY_DAYS: i64 = 30;
+/// Each skip divides the weight of the song by `1 + penalty * skips`, with its artist's
+/// skips counting less
+const SONG_SKIP_PENALTY: f64 = 2.0;
+const ARTIST_SKIP_PENALTY: f64 = 0.5;
+/// Weight factor of a song by the artist of the previous track
+const SAME_ARTIST_FACTOR: f64 = 0.25;
+
+/// Stations older than this are deleted, and their continuation tokens stop working
+pub const RADIO_STATION_DAYS: i64 = 7;
+
+pub fn encode_continuation(station_id: &str, position: i32) -> String {
+    format!("{}.{}", station_id, position)
+}
+
+/// `(station_id, position)` of a continuation token
+pub fn parse_continuation(token: &str) -> Option<(String, i32)> {
+    let (station_id, position) = token.rsplit_once('.')?;
+    let position = position.parse::<i32>().ok().filter(|p| *p >= 0)?;
+    if station_id.is_empty() {
+        return None;
+    }
+    Some((station_id.to_string(), position))
+}
+
+/// Artist and genre a station gravitates around
+#[derive(Debug, Clone, Default)]
+pub struct RadioSeed {
+    pub artist_id: Option<String>,
+    pub genre_id: Option<i32>,
+}
+
+/// Resolve a seed to its artist and genre. `NotFound` if it does not exist.
+pub fn resolve_seed(conn: &mut MysqlConnection, seed_type: &str, seed_id: &str) -> QueryResult<RadioSeed> {
+    match seed_type {
+        SEED_SONG => {
+            let (artist_id, genre_id) = songs::table
+                .filter(songs::id.eq(seed_id))
+                .select((songs::artist_id, songs::genre_id))
+                .first::<(String, Option<i32>)>(conn)?;
+            Ok(RadioSeed { artist_id: Some(artist_id), genre_id })
+        }
+        SEED_ARTIST => {
+            artists::table.find(seed_id).select(artists::id).first::<String>(conn)?;
+            // The genre the artist plays most
+            let genre_id = songs::table
+                .filter(songs::artist_id.eq(seed_id))
+                .filter(songs::genre_id.is_not_null())
+                .group_by(songs::genre_id)
+                .select(songs::genre_id)
+                .order((diesel::dsl::count_star().desc(), songs::genre_id))
+                .first::<Option<i32>>(conn)
+                .optional()?
+                .flatten();
+            Ok(RadioSeed { artist_id: Some(seed_id.to_string()), genre_id })
+        }
+        SEED_GENRE => {
+            let genre_id = seed_id.parse::<i32>().map_err(|_| diesel::result::Error::NotFound)?;
+            genres::table.find(genre_id).select(genres::id).first::<i32>(conn)?;
+            Ok(RadioSeed { artist_id: None, genre_id: Some(genre_id) })
+        }
+        _ => Err(diesel::result::Error::NotFound),
+    }
+}
+
+/// Position the next generated track of the station gets
+pub fn next_position(conn: &mut MysqlConnection, station_id: &str) -> QueryResult<i32> {
+    let max = radio_tracks::table
+        .filter(radio_tracks::station_id.eq(station_id))
+        .select(diesel::dsl::max(radio_tracks::position))
+        .first::<Option<i32>>(conn)?;
+    Ok(max.map_or(0, |m| m + 1))
+}
+
+fn radio_candidates(conn: &mut MysqlConnection, station: &RadioStation, seed: &RadioSeed, next: i32) -> QueryResult<Vec<RadioCandidate>> {
+    let sql = format!(
+        r#"
+        SELECT
+            s.id,
+            s.artist_id,
+            CAST(
+                1
+                + COALESCE(s.artist_id = ?, 0) * {RADIO_ARTIST_WEIGHT}
+                + COALESCE(s.genre_id = ?, 0) * {RADIO_GENRE_WEIGHT}
+                + (aa.artist_id IS NOT NULL) * {RADIO_ADJACENT_ARTIST_WEIGHT}
+                + (ag.genre_id IS NOT NULL) * {RADIO_ADJACENT_GENRE_WEIGHT}
+            AS SIGNED) AS score,
+            CAST(
+                EXISTS (
+                    SELECT 1 FROM radio_tracks rt
+                    WHERE rt.station_id = ? AND rt.song_id = s.id AND rt.position >= ?
+                )
+                OR EXISTS (SELECT 1 FROM skips sk WHERE sk.station_id = ? AND sk.song_id = s.id)
+                OR EXISTS (
+                    SELECT 1 FROM plays rp
+                    WHERE rp.user_id = ? AND rp.song_id = s.id AND rp.played_at >= ?
+                )
+            AS SIGNED) AS recent,
+            (
+                SELECT COUNT(*) FROM skips k
+                WHERE k.user_id = ? AND k.song_id = s.id AND k.skipped_at >= ?
+            ) AS song_skips,
+            (
+                SELECT COUNT(*) FROM skips k
+                JOIN songs ks ON ks.id = k.song_id
+                WHERE k.user_id = ? AND ks.artist_id = s.artist_id AND k.skipped_at >= ?
+            ) AS artist_skips
+        FROM songs s
+        LEFT JOIN (
+            SELECT b_s.artist_id
+            FROM playlist_songs a
+            JOIN songs a_s ON a_s.id = a.song_id
+            JOIN playlist_songs b ON b.playlist_id = a.playlist_id
+            JOIN songs b_s ON b_s.id = b.song_id
+            WHERE a_s.artist_id = ?
+            UNION
+            SELECT artist_id FROM songs WHERE genre_id = ?
+        ) aa ON aa.artist_id = s.artist_id
+        LEFT JOIN (
+            SELECT DISTINCT o.genre_id
+            FROM songs x
+            JOIN songs o ON o.artist_id = x.artist_id
+            WHERE (x.artist_id = ? OR x.genre_id = ?) AND o.genre_id IS NOT NULL
+        ) ag ON ag.genre_id = s.genre_id
+        ORDER BY score DESC, s.id
+        LIMIT {RADIO_CANDIDATES}
+        "#
+    );
+
+    let now = Utc::now().naive_utc();
+    let repeat_since = now - Duration::hours(RADIO_REPEAT_HOURS);
+    let skips_since = now - Duration::days(SKIP_MEMORY_DAYS);
+
+    diesel::sql_query(sql)
+        .bind::<Nullable<Text>, _>(&seed.artist_id)
+        .bind::<Nullable<Integer>, _>(seed.genre_id)
+        .bind::<Text, _>(&station.id)
+        .bind::<Integer, _>(next - RADIO_RECENT_TRACKS)
+        .bind::<Text, _>(&station.id)
+        .bind::<Text, _>(&station.user_id)
+        .bind::<Timestamp, _>(repeat_since)
+        .bind::<Text, _>(&station.user_id)
+        .bind::<Timestamp, _>(skips_since)
+        .bind::<Text, _>(&station.user_id)
+        .bind::<Timestamp, _>(skips_since)
+        .bind::<Nullable<Text>, _>(&seed.artist_id)
+        .bind::<Nullable<Integer>, _>(seed.genre_id)
+        .bind::<Nullable<Text>, _>(&seed.artist_id)
+        .bind::<Nullable<Integer>, _>(seed.genre_id)
+        .load::<RadioCandidate>(conn)
+}
+
+fn candidate_weight(candidate: &RadioCandidate, previous_artist: Option<&str>) -> f64 {
+    let skips = 1.0 + SONG_SKIP_PENALTY * candidate.song_skips as f64 + ARTIST_SKIP_PENALTY * candidate.artist_skips as f64;
+    let mut weight = candidate.score as f64 / skips;
+    if previous_artist == Some(candidate.artist_id.as_str()) {
+        weight *= SAME_ARTIST_FACTOR;
+    }
+    weight.max(f64::MIN_POSITIVE)
+}
+
+/// Draw up to `count` distinct songs, each with a probability proportional to its weight.
+/// Recent songs are only drawn once the others are used up.
+pub fn pick_tracks<R: Rng + ?Sized>(
+    candidates: Vec<RadioCandidate>,
+    count: usize,
+    previous_artist: Option<String>,
+    rng: &mut R,
+) -> Vec<String> {
+    let (mut fresh, mut recent): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|c| c.recent == 0);
+    let mut previous_artist = previous_artist;
+    let mut picked = Vec::with_capacity(count);
+
+    while picked.len() < count {
+        let pool = if !fresh.is_empty() {
+            &mut fresh
+        } else if !recent.is_empty() {
+            &mut recent
+        } else {
+            break;
+        };
+
+        let weights: Vec<f64> = pool.iter().map(|c| candidate_weight(c, previous_artist.as_deref())).collect();
+        let index = WeightedIndex::new(&weights).map(|w| w.sample(rng)).unwrap_or(0);
+        let candidate = pool.swap_remove(index);
+        previous_artist = Some(candidate.artist_id);
+        picked.push(candidate.id);
+    }
+
+    picked
+}
+
+/// Generate the station's tracks up to `end` (exclusive). Song stations start with their seed.
+pub fn extend_station(conn: &mut MysqlConnection, station: &RadioStation, end: i32) -> QueryResult<()> {
+    let next = next_position(conn, &station.id)?;
+    if end <= next {
+        return Ok(());
+    }
+
+    let mut song_ids: Vec<String> = Vec::new();
+    if next == 0 && station.seed_type == SEED_SONG {
+        song_ids.push(station.seed_id.clone());
+    }
+
+    let wanted = (end - next) as usize;
+    if song_ids.len() < wanted {
+        let seed = resolve_seed(conn, &station.seed_type, &station.seed_id)?;
+        let candidates = radio_candidates(conn, station, &seed, next)?
+            .into_iter()
+            .filter(|c| !song_ids.contains(&c.id))
+            .collect();
+
+        let previous_artist = if song_ids.is_empty() {
+            radio_tracks::table
+                .inner_join(songs::table)
+                .filter(radio_tracks::station_id.eq(&station.id))
+                .filter(radio_tracks::position.eq(next - 1))
+                .select(songs::artist_id)
+                .first::<String>(conn)
+                .optional()?
+        } else {
+            // Right after the seed song
+            seed.artist_id.clone()
+        };
+
+        let count = wanted - song_ids.len();
+        song_ids.extend(pick_tracks(candidates, count, previous_artist, &mut rand::thread_rng()));
+    }
+
+    let tracks: Vec<NewRadioTrack> = song_ids
+        .into_iter()
+        .zip(next..)
+        .map(|(song_id, position)| NewRadioTrack {
+            station_id: station.id.clone(),
+            position,
+            song_id,
+        })
+        .collect();
+
+    diesel::insert_into(radio_tracks::table).values(&tracks).execute(conn)?;
+    Ok(())
+}
+
+/// Tracks of the station from `start` to `end` (exclusive)
+pub fn load_tracks(conn: &mut MysqlConnection, station_id: &str, start: i32, end: i32) -> QueryResult<Vec<RadioTrack>> {
+    let sql = format!(
+        r#"
+        SELECT rt.position, {SONG_COLUMNS}
+        FROM radio_tracks rt
+        JOIN songs s ON s.id = rt.song_id
+        {SONG_JOINS}
+        WHERE rt.station_id = ? AND rt.position >= ? AND rt.position < ?
+        ORDER BY rt.position
+        "#
+    );
+
+    diesel::sql_query(sql)
+        .bind::<Text, _>(station_id)
+        .bind::<Integer, _>(start)
+        .bind::<Integer, _>(end)
+        .load::<RadioTrack>(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn candidate(id: &str, artist_id: &str, score: i64, recent: bool) -> RadioCandidate {
+        RadioCandidate {
+            id: id.to_string(),
+            artist_id: artist_id.to_string(),
+            score,
+            recent: recent as i64,
+            song_skips: 0,
+            artist_skips: 0,
+        }
+    }
+
+    #[test]
+    fn continuation_round_trips() {
+        let token = encode_continuation("3f2a-station", 40);
+        assert_eq!(parse_continuation(&token), Some(("3f2a-station".to_string(), 40)));
+    }
+
+    #[test]
+    fn malformed_continuations_are_rejected() {
+        assert_eq!(parse_continuation("station"), None);
+        assert_eq!(parse_continuation(".40"), None);
+        assert_eq!(parse_continuation("station.-1"), None);
+        assert_eq!(parse_continuation("station.next"), None);
+    }
+
+    #[test]
+    fn skips_lower_the_weight() {
+        let plain = candidate("a", "x", 8, false);
+        let mut skipped = candidate("a", "x", 8, false);
+        skipped.song_skips = 1;
+        let mut artist_skipped = candidate("a", "x", 8, false);
+        artist_skipped.artist_skips = 1;
+
+        assert_eq!(candidate_weight(&plain, None), 8.0);
+        assert_eq!(candidate_weight(&skipped, None), 8.0 / 3.0);
+        assert_eq!(candidate_weight(&artist_skipped, None), 8.0 / 1.5);
+    }
+
+    #[test]
+    fn same_artist_as_the_previous_track_weighs_less() {
+        let c = candidate("a", "x", 8, false);
+        assert_eq!(candidate_weight(&c, Some("x")), 2.0);
+        assert_eq!(candidate_weight(&c, Some("y")), 8.0);
+    }
+
+    #[test]
+    fn weight_is_never_zero() {
+        assert!(candidate_weight(&candidate("a", "x", 0, false), None) > 0.0);
+    }
+
+    #[test]
+    fn picks_distinct_songs_up_to_count() {
+        let candidates = (0..10).map(|i| candidate(&i.to_string(), "x", 1 + i, false)).collect();
+        let picked = pick_tracks(candidates, 5, None, &mut StdRng::seed_from_u64(7));
+        assert_eq!(picked.len(), 5);
+        let mut distinct = picked.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 5);
+    }
+
+    #[test]
+    fn recent_songs_come_after_the_others() {
+        let candidates = vec![
+            candidate("old", "x", 100, true),
+            candidate("new1", "y", 1, false),
+            candidate("new2", "z", 1, false),
+        ];
+        let picked = pick_tracks(candidates, 3, None, &mut StdRng::seed_from_u64(7));
+        assert_eq!(picked.len(), 3);
+        assert_eq!(picked[2], "old");
+    }
+
+    #[test]
+    fn stops_when_candidates_run_out() {
+        let candidates = vec![candidate("a", "x", 1, false), candidate("b", "y", 1, true)];
+        assert_eq!(pick_tracks(candidates, 5, None, &mut StdRng::seed_from_u64(7)).len(), 2);
+        assert!(pick_tracks(Vec::new(), 5, None, &mut StdRng::seed_from_u64(7)).is_empty());
+    }
+}
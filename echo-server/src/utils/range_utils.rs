@@ -0,0 +1,183 @@
+/// An inclusive byte range, as used by `Range` and `Content-Range` headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn content_length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Value for a `Content-Range` header, e.g. `bytes 0-1023/4096`
+    pub fn content_range(&self, total_size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total_size)
+    }
+
+    /// Value for a `Range` request header sent upstream, e.g. `bytes=0-1023`
+    pub fn range_header(&self) -> String {
+        format!("bytes={}-{}", self.start, self.end)
+    }
+}
+
+/// Outcome of evaluating a `Range` header against a resource of known size
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No (usable) range: serve the whole resource with 200
+    Full,
+    /// Serve this range with 206
+    Partial(ByteRange),
+    /// Range cannot be satisfied: answer 416
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header value for a resource of `total_size` bytes.
+/// Only single ranges are honored; multipart ranges fall back to a full response,
+/// which RFC 9110 allows since servers may ignore `Range`.
+pub fn parse_range_header(header: &str, total_size: u64) -> RangeRequest {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(s) => s.trim(),
+        None => return RangeRequest::Full,
+    };
+
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+
+    let (start_str, end_str) = match spec.split_once('-') {
+        Some(parts) => (parts.0.trim(), parts.1.trim()),
+        None => return RangeRequest::Full,
+    };
+
+    if total_size == 0 {
+        return RangeRequest::Unsatisfiable;
+    }
+    let last = total_size - 1;
+
+    // Suffix range: "bytes=-500" means the last 500 bytes
+    if start_str.is_empty() {
+        return match end_str.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(suffix) => RangeRequest::Partial(ByteRange {
+                start: total_size.saturating_sub(suffix),
+                end: last,
+            }),
+            Err(_) => RangeRequest::Full,
+        };
+    }
+
+    let start = match start_str.parse::<u64>() {
+        Ok(v) => v,
+        Err(_) => return RangeRequest::Full,
+    };
+    let end = if end_str.is_empty() {
+        last
+    } else {
+        match end_str.parse::<u64>() {
+            Ok(v) => v.min(last),
+            Err(_) => return RangeRequest::Full,
+        }
+    };
+
+    if start > last {
+        return RangeRequest::Unsatisfiable;
+    }
+    if end < start {
+        // Syntactically invalid, so the header is ignored
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+/// Evaluate an `If-Range` precondition. Ranges are only honored when the validator
+/// still matches the current representation; otherwise the full resource is sent.
+/// Entity tags are compared strongly, weak tags never match.
+pub fn if_range_matches(if_range: &str, etag: &str, last_modified: Option<&str>) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') {
+        return !etag.starts_with("W/") && if_range == etag;
+    }
+    if if_range.starts_with("W/") {
+        return false;
+    }
+
+    // Otherwise it is an HTTP-date, which must match Last-Modified exactly
+    matches!(last_modified, Some(lm) if lm.trim() == if_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(start: u64, end: u64) -> RangeRequest {
+        RangeRequest::Partial(ByteRange { start, end })
+    }
+
+    #[test]
+    fn closed_range() {
+        assert_eq!(parse_range_header("bytes=0-1023", 4096), partial(0, 1023));
+        assert_eq!(parse_range_header("bytes=100-100", 4096), partial(100, 100));
+        // An end past the resource is clamped to its last byte
+        assert_eq!(parse_range_header("bytes=4000-9999", 4096), partial(4000, 4095));
+    }
+
+    #[test]
+    fn open_ended_range() {
+        assert_eq!(parse_range_header("bytes=1000-", 4096), partial(1000, 4095));
+        assert_eq!(parse_range_header("bytes=0-", 4096), partial(0, 4095));
+    }
+
+    #[test]
+    fn suffix_range() {
+        assert_eq!(parse_range_header("bytes=-500", 4096), partial(3596, 4095));
+        // A suffix longer than the resource covers all of it
+        assert_eq!(parse_range_header("bytes=-9999", 4096), partial(0, 4095));
+        assert_eq!(parse_range_header("bytes=-0", 4096), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn start_past_the_end_is_unsatisfiable() {
+        assert_eq!(parse_range_header("bytes=4096-", 4096), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=5000-6000", 4096), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn start_after_end_is_ignored() {
+        assert_eq!(parse_range_header("bytes=500-100", 4096), RangeRequest::Full);
+    }
+
+    #[test]
+    fn multiple_ranges_are_ignored() {
+        assert_eq!(parse_range_header("bytes=0-99,200-299", 4096), RangeRequest::Full);
+    }
+
+    #[test]
+    fn malformed_headers_are_ignored() {
+        assert_eq!(parse_range_header("items=0-99", 4096), RangeRequest::Full);
+        assert_eq!(parse_range_header("bytes=abc-99", 4096), RangeRequest::Full);
+        assert_eq!(parse_range_header("bytes=0-xyz", 4096), RangeRequest::Full);
+        assert_eq!(parse_range_header("bytes=100", 4096), RangeRequest::Full);
+    }
+
+    #[test]
+    fn content_range_of_a_partial_response() {
+        let range = ByteRange { start: 0, end: 1023 };
+        assert_eq!(range.content_length(), 1024);
+        assert_eq!(range.content_range(4096), "bytes 0-1023/4096");
+        assert_eq!(range.range_header(), "bytes=0-1023");
+    }
+
+    #[test]
+    fn if_range_validators() {
+        assert!(if_range_matches("\"abc\"", "\"abc\"", None));
+        assert!(!if_range_matches("\"abc\"", "\"def\"", None));
+        assert!(!if_range_matches("W/\"abc\"", "W/\"abc\"", None));
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert!(if_range_matches(date, "\"abc\"", Some(date)));
+        assert!(!if_range_matches(date, "\"abc\"", None));
+    }
+}
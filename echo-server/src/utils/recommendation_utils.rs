@@ -0,0 +1,517 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Text, Timestamp};
+use std::collections::HashMap;
+
+use crate::models::recommendation_models::Recommendation;
+use crate::models::song_models::SongResponse;
+use crate::utils::song_query_utils::{SONG_COLUMNS, SONG_JOINS};
+use crate::utils::sql_utils::{bind_values, BindValue};
+
+// The SQL only counts signals; scores are integer sums of weighted signals computed here,
+// ties broken by song id, so the same data always gives the same recommendations.
+
+// Similar songs: every signal is counted against the seed song
+/// Per playlist containing both songs
+const SIMILAR_PLAYLIST_WEIGHT: i64 = 3;
+/// Per user who favorited both songs
+const SIMILAR_FAVORITE_WEIGHT: i64 = 2;
+/// Per user who played both songs within `SESSION_SECONDS` of each other
+const SIMILAR_LISTENER_WEIGHT: i64 = 2;
+const SIMILAR_ARTIST_WEIGHT: i64 = 2;
+const SIMILAR_GENRE_WEIGHT: i64 = 1;
+
+/// Plays this close together count as one listening session
+const SESSION_SECONDS: i64 = 3600;
+
+// Mixes: the user's affinity for an artist or genre sums these over their songs
+const AFFINITY_FAVORITE_WEIGHT: i64 = 3;
+const AFFINITY_PLAYLIST_WEIGHT: i64 = 2;
+const AFFINITY_PLAY_WEIGHT: i64 = 1;
+/// Only plays this recent shape the affinity
+const AFFINITY_PLAY_DAYS: i64 = 90;
+
+// A song's score in a mix
+const MIX_ARTIST_WEIGHT: i64 = 2;
+const MIX_GENRE_WEIGHT: i64 = 1;
+/// Per playlist shared with one of the user's favorites
+const MIX_COOCCURRENCE_WEIGHT: i64 = 2;
+/// Songs played this recently are left out, so mixes change as the user listens
+const MIX_COOLDOWN_HOURS: i64 = 24;
+
+/// Candidates loaded per recommendation asked for
+const PRESELECT_FACTOR: i64 = 3;
+
+// --------------------- Scoring ---------------------
+
+/// What relates a song to the seed of `similar_songs`
+#[derive(QueryableByName, Debug, Clone, Default)]
+pub struct SimilarSignals {
+    #[diesel(sql_type = Text)]
+    pub song_id: String,
+    /// Playlists containing both songs
+    #[diesel(sql_type = BigInt)]
+    pub playlists: i64,
+    /// Users who favorited both songs
+    #[diesel(sql_type = BigInt)]
+    pub favorites: i64,
+    /// Users who played both songs in one listening session
+    #[diesel(sql_type = BigInt)]
+    pub listeners: i64,
+    /// 1 for a song by the seed's artist, else 0
+    #[diesel(sql_type = BigInt)]
+    pub same_artist: i64,
+    /// 1 for a song of the seed's genre, else 0
+    #[diesel(sql_type = BigInt)]
+    pub same_genre: i64,
+}
+
+pub fn similar_score(signals: &SimilarSignals) -> i64 {
+    signals.playlists * SIMILAR_PLAYLIST_WEIGHT
+        + signals.favorites * SIMILAR_FAVORITE_WEIGHT
+        + signals.listeners * SIMILAR_LISTENER_WEIGHT
+        + signals.same_artist * SIMILAR_ARTIST_WEIGHT
+        + signals.same_genre * SIMILAR_GENRE_WEIGHT
+}
+
+/// How much a user likes an artist or genre, from its songs among their favorites, their
+/// playlists' entries and their recent plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Affinity {
+    pub favorites: i64,
+    pub playlist_entries: i64,
+    pub plays: i64,
+}
+
+impl Affinity {
+    pub fn score(&self) -> i64 {
+        self.favorites * AFFINITY_FAVORITE_WEIGHT
+            + self.playlist_entries * AFFINITY_PLAYLIST_WEIGHT
+            + self.plays * AFFINITY_PLAY_WEIGHT
+    }
+}
+
+/// What makes a song fit a user's mix
+#[derive(QueryableByName, Debug, Clone, Default)]
+pub struct MixSignals {
+    #[diesel(sql_type = Text)]
+    pub song_id: String,
+    #[diesel(sql_type = BigInt)]
+    pub artist_favorites: i64,
+    #[diesel(sql_type = BigInt)]
+    pub artist_playlist_entries: i64,
+    #[diesel(sql_type = BigInt)]
+    pub artist_plays: i64,
+    #[diesel(sql_type = BigInt)]
+    pub genre_favorites: i64,
+    #[diesel(sql_type = BigInt)]
+    pub genre_playlist_entries: i64,
+    #[diesel(sql_type = BigInt)]
+    pub genre_plays: i64,
+    /// Playlists the song shares with one of the user's favorites
+    #[diesel(sql_type = BigInt)]
+    pub cooccurrences: i64,
+}
+
+impl MixSignals {
+    pub fn artist_affinity(&self) -> Affinity {
+        Affinity {
+            favorites: self.artist_favorites,
+            playlist_entries: self.artist_playlist_entries,
+            plays: self.artist_plays,
+        }
+    }
+
+    pub fn genre_affinity(&self) -> Affinity {
+        Affinity {
+            favorites: self.genre_favorites,
+            playlist_entries: self.genre_playlist_entries,
+            plays: self.genre_plays,
+        }
+    }
+}
+
+pub fn mix_score(signals: &MixSignals) -> i64 {
+    signals.artist_affinity().score() * MIX_ARTIST_WEIGHT
+        + signals.genre_affinity().score() * MIX_GENRE_WEIGHT
+        + signals.cooccurrences * MIX_COOCCURRENCE_WEIGHT
+}
+
+/// The `limit` best `(id, score)`: highest score first, ties broken by id.
+/// Candidates scoring nothing are left out.
+pub fn rank(mut scored: Vec<(String, i64)>, limit: i64) -> Vec<(String, i64)> {
+    scored.retain(|(_, score)| *score > 0);
+    scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(usize::try_from(limit).unwrap_or(0));
+    scored
+}
+
+/// Rows the SQL hands over for `limit` recommendations. It orders them by the same weights
+/// as the scoring here, so the best ones are always among them; the margin absorbs ties
+/// the database breaks in another order.
+fn preselection(limit: i64) -> i64 {
+    limit.saturating_mul(PRESELECT_FACTOR)
+}
+
+/// The songs of a ranking, in its order
+fn load_ranked(conn: &mut MysqlConnection, ranked: Vec<(String, i64)>) -> QueryResult<Vec<Recommendation>> {
+    if ranked.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let placeholders = vec!["?"; ranked.len()].join(", ");
+    let sql = format!("SELECT {SONG_COLUMNS} FROM songs s {SONG_JOINS} WHERE s.id IN ({placeholders})");
+    let binds = ranked.iter().map(|(id, _)| BindValue::Text(id.clone())).collect();
+    let mut found: HashMap<String, SongResponse> = bind_values(diesel::sql_query(sql).into_boxed(), binds)
+        .load::<SongResponse>(conn)?
+        .into_iter()
+        .map(|song| (song.id.clone(), song))
+        .collect();
+
+    Ok(ranked
+        .into_iter()
+        .filter_map(|(id, score)| found.remove(&id).map(|song| Recommendation { score, song }))
+        .collect())
+}
+
+// --------------------- Queries ---------------------
+
+/// Songs most related to `song_id`: shared playlists, favorites and listening sessions,
+/// then same artist and genre
+pub fn similar_songs(conn: &mut MysqlConnection, song_id: &str, limit: i64) -> QueryResult<Vec<Recommendation>> {
+    let sql = format!(
+        r#"
+        SELECT
+            c.song_id,
+            CAST(SUM(c.playlists) AS SIGNED) AS playlists,
+            CAST(SUM(c.favorites) AS SIGNED) AS favorites,
+            CAST(SUM(c.listeners) AS SIGNED) AS listeners,
+            CAST(o.artist_id = seed.artist_id AS SIGNED) AS same_artist,
+            CAST(COALESCE(o.genre_id = seed.genre_id, 0) AS SIGNED) AS same_genre
+        FROM (
+            SELECT b.song_id, COUNT(DISTINCT b.playlist_id) AS playlists, 0 AS favorites, 0 AS listeners
+            FROM playlist_songs a
+            JOIN playlist_songs b ON b.playlist_id = a.playlist_id AND b.song_id <> a.song_id
+            WHERE a.song_id = ?
+            GROUP BY b.song_id
+
+            UNION ALL
+
+            SELECT b.song_id, 0, COUNT(DISTINCT b.user_id), 0
+            FROM favorites a
+            JOIN favorites b ON b.user_id = a.user_id AND b.song_id <> a.song_id
+            WHERE a.song_id = ?
+            GROUP BY b.song_id
+
+            UNION ALL
+
+            SELECT b.song_id, 0, 0, COUNT(DISTINCT b.user_id)
+            FROM plays a
+            JOIN plays b ON b.user_id = a.user_id AND b.song_id <> a.song_id
+                AND b.played_at BETWEEN a.played_at - INTERVAL {SESSION_SECONDS} SECOND
+                                    AND a.played_at + INTERVAL {SESSION_SECONDS} SECOND
+            WHERE a.song_id = ?
+            GROUP BY b.song_id
+
+            UNION ALL
+
+            -- Songs of the same artist or genre, best first: only as many as could be
+            -- recommended, the others scoring less than all of them
+            SELECT * FROM (
+                SELECT o.id AS song_id, 0 AS playlists, 0 AS favorites, 0 AS listeners
+                FROM songs seed
+                JOIN songs o ON o.id <> seed.id AND (o.artist_id = seed.artist_id OR o.genre_id = seed.genre_id)
+                WHERE seed.id = ?
+                ORDER BY (o.artist_id = seed.artist_id) * {SIMILAR_ARTIST_WEIGHT}
+                    + COALESCE(o.genre_id = seed.genre_id, 0) * {SIMILAR_GENRE_WEIGHT} DESC, o.id
+                LIMIT ?
+            ) related
+        ) c
+        JOIN songs o ON o.id = c.song_id
+        JOIN songs seed ON seed.id = ?
+        GROUP BY c.song_id, o.artist_id, o.genre_id, seed.artist_id, seed.genre_id
+        ORDER BY SUM(c.playlists) * {SIMILAR_PLAYLIST_WEIGHT}
+            + SUM(c.favorites) * {SIMILAR_FAVORITE_WEIGHT}
+            + SUM(c.listeners) * {SIMILAR_LISTENER_WEIGHT}
+            + (o.artist_id = seed.artist_id) * {SIMILAR_ARTIST_WEIGHT}
+            + COALESCE(o.genre_id = seed.genre_id, 0) * {SIMILAR_GENRE_WEIGHT} DESC, c.song_id
+        LIMIT ?
+        "#
+    );
+
+    let preselected = preselection(limit);
+    let candidates = diesel::sql_query(sql)
+        .bind::<Text, _>(song_id)
+        .bind::<Text, _>(song_id)
+        .bind::<Text, _>(song_id)
+        .bind::<Text, _>(song_id)
+        .bind::<BigInt, _>(preselected)
+        .bind::<Text, _>(song_id)
+        .bind::<BigInt, _>(preselected)
+        .load::<SimilarSignals>(conn)?;
+
+    let scored = candidates.iter().map(|c| (c.song_id.clone(), similar_score(c))).collect();
+    load_ranked(conn, rank(scored, limit))
+}
+
+/// `(target, favorites, playlist_entries, plays)` of the user for every `songs.{column}`
+/// (`artist_id` or `genre_id`). Binds: user id three times, then the oldest play counted.
+fn affinity_sql(column: &str) -> String {
+    format!(
+        r#"
+        SELECT
+            x_s.{column} AS target,
+            CAST(SUM(x.source = 'favorite') AS SIGNED) AS favorites,
+            CAST(SUM(x.source = 'playlist') AS SIGNED) AS playlist_entries,
+            CAST(SUM(x.source = 'play') AS SIGNED) AS plays
+        FROM (
+            SELECT f.song_id, 'favorite' AS source
+            FROM favorites f
+            WHERE f.user_id = ?
+
+            UNION ALL
+
+            SELECT ps.song_id, 'playlist'
+            FROM playlist_songs ps
+            JOIN playlists pl ON ps.playlist_id = pl.id
+            WHERE pl.user_id = ?
+
+            UNION ALL
+
+            SELECT p.song_id, 'play'
+            FROM plays p
+            WHERE p.user_id = ? AND p.played_at >= ?
+        ) x
+        JOIN songs x_s ON x_s.id = x.song_id
+        WHERE x_s.{column} IS NOT NULL
+        GROUP BY x_s.{column}
+        "#
+    )
+}
+
+/// `Affinity::score` of the columns of an `affinity_sql` row aliased `alias`, 0 when missing
+fn affinity_score_sql(alias: &str) -> String {
+    format!(
+        "(COALESCE({alias}.favorites, 0) * {AFFINITY_FAVORITE_WEIGHT} \
+         + COALESCE({alias}.playlist_entries, 0) * {AFFINITY_PLAYLIST_WEIGHT} \
+         + COALESCE({alias}.plays, 0) * {AFFINITY_PLAY_WEIGHT})"
+    )
+}
+
+fn affinity_since() -> NaiveDateTime {
+    Utc::now().naive_utc() - Duration::days(AFFINITY_PLAY_DAYS)
+}
+
+#[derive(QueryableByName)]
+pub struct MixGenre {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+}
+
+#[derive(QueryableByName)]
+struct GenreAffinity {
+    #[diesel(embed)]
+    genre: MixGenre,
+    #[diesel(sql_type = BigInt)]
+    favorites: i64,
+    #[diesel(sql_type = BigInt)]
+    playlist_entries: i64,
+    #[diesel(sql_type = BigInt)]
+    plays: i64,
+}
+
+/// The genres the user likes most, ties broken by genre id
+pub fn top_genres(conn: &mut MysqlConnection, user_id: &str, limit: i64) -> QueryResult<Vec<MixGenre>> {
+    let sql = format!(
+        r#"
+        SELECT g.id, g.name, ga.favorites, ga.playlist_entries, ga.plays
+        FROM ({affinity}) ga
+        JOIN genres g ON g.id = ga.target
+        "#,
+        affinity = affinity_sql("genre_id"),
+    );
+
+    let mut genres: Vec<(i64, MixGenre)> = diesel::sql_query(sql)
+        .bind::<Text, _>(user_id)
+        .bind::<Text, _>(user_id)
+        .bind::<Text, _>(user_id)
+        .bind::<Timestamp, _>(affinity_since())
+        .load::<GenreAffinity>(conn)?
+        .into_iter()
+        .map(|g| {
+            let affinity = Affinity {
+                favorites: g.favorites,
+                playlist_entries: g.playlist_entries,
+                plays: g.plays,
+            };
+            (affinity.score(), g.genre)
+        })
+        .collect();
+
+    genres.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.id.cmp(&b.1.id)));
+    genres.truncate(usize::try_from(limit).unwrap_or(0));
+    Ok(genres.into_iter().map(|(_, genre)| genre).collect())
+}
+
+/// Which songs a mix draws from
+#[derive(Debug, Clone, Copy)]
+pub enum MixKind {
+    /// Songs of one genre
+    Genre(i32),
+    /// Songs the user never played nor favorited
+    Discover,
+}
+
+/// Songs for the user ranked by their affinity for the artist and genre, plus how often
+/// they share a playlist with the user's favorites
+pub fn mix_songs(conn: &mut MysqlConnection, user_id: &str, kind: MixKind, limit: i64) -> QueryResult<Vec<Recommendation>> {
+    let filter = match kind {
+        MixKind::Genre(_) => "AND s.genre_id = ?",
+        MixKind::Discover => {
+            r#"
+            AND NOT EXISTS (SELECT 1 FROM plays hp WHERE hp.user_id = ? AND hp.song_id = s.id)
+            AND NOT EXISTS (SELECT 1 FROM favorites hf WHERE hf.user_id = ? AND hf.song_id = s.id)
+            "#
+        }
+    };
+
+    let sql = format!(
+        r#"
+        SELECT
+            s.id AS song_id,
+            COALESCE(aa.favorites, 0) AS artist_favorites,
+            COALESCE(aa.playlist_entries, 0) AS artist_playlist_entries,
+            COALESCE(aa.plays, 0) AS artist_plays,
+            COALESCE(ga.favorites, 0) AS genre_favorites,
+            COALESCE(ga.playlist_entries, 0) AS genre_playlist_entries,
+            COALESCE(ga.plays, 0) AS genre_plays,
+            COALESCE(co.playlists, 0) AS cooccurrences
+        FROM songs s
+        LEFT JOIN ({artist_affinity}) aa ON aa.target = s.artist_id
+        LEFT JOIN ({genre_affinity}) ga ON ga.target = s.genre_id
+        LEFT JOIN (
+            SELECT b.song_id, COUNT(DISTINCT b.playlist_id) AS playlists
+            FROM favorites f
+            JOIN playlist_songs a ON a.song_id = f.song_id
+            JOIN playlist_songs b ON b.playlist_id = a.playlist_id AND b.song_id <> a.song_id
+            WHERE f.user_id = ?
+            GROUP BY b.song_id
+        ) co ON co.song_id = s.id
+        WHERE (aa.target IS NOT NULL OR ga.target IS NOT NULL OR co.song_id IS NOT NULL)
+            AND NOT EXISTS (
+                SELECT 1 FROM plays rp
+                WHERE rp.user_id = ? AND rp.song_id = s.id AND rp.played_at >= ?
+            )
+            {filter}
+        ORDER BY {artist_score} * {MIX_ARTIST_WEIGHT}
+            + {genre_score} * {MIX_GENRE_WEIGHT}
+            + COALESCE(co.playlists, 0) * {MIX_COOCCURRENCE_WEIGHT} DESC, s.id
+        LIMIT ?
+        "#,
+        artist_affinity = affinity_sql("artist_id"),
+        genre_affinity = affinity_sql("genre_id"),
+        artist_score = affinity_score_sql("aa"),
+        genre_score = affinity_score_sql("ga"),
+    );
+
+    let since = affinity_since();
+    let cooldown = Utc::now().naive_utc() - Duration::hours(MIX_COOLDOWN_HOURS);
+
+    let mut query = diesel::sql_query(sql).into_boxed();
+    // Artist then genre affinity
+    for _ in 0..2 {
+        query = query
+            .bind::<Text, _>(user_id)
+            .bind::<Text, _>(user_id)
+            .bind::<Text, _>(user_id)
+            .bind::<Timestamp, _>(since);
+    }
+    query = query
+        .bind::<Text, _>(user_id)
+        .bind::<Text, _>(user_id)
+        .bind::<Timestamp, _>(cooldown);
+    query = match kind {
+        MixKind::Genre(genre_id) => query.bind::<Integer, _>(genre_id),
+        MixKind::Discover => query.bind::<Text, _>(user_id).bind::<Text, _>(user_id),
+    };
+    query = query.bind::<BigInt, _>(preselection(limit));
+
+    let candidates = query.load::<MixSignals>(conn)?;
+    let scored = candidates.iter().map(|c| (c.song_id.clone(), mix_score(c))).collect();
+    load_ranked(conn, rank(scored, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preselection_leaves_a_margin() {
+        assert_eq!(preselection(20), 60);
+        assert_eq!(preselection(i64::MAX), i64::MAX);
+    }
+
+    fn scored(items: &[(&str, i64)]) -> Vec<(String, i64)> {
+        items.iter().map(|(id, score)| (id.to_string(), *score)).collect()
+    }
+
+    #[test]
+    fn similar_score_weights_each_signal() {
+        let one = |f: fn(&mut SimilarSignals)| {
+            let mut signals = SimilarSignals::default();
+            f(&mut signals);
+            similar_score(&signals)
+        };
+        assert_eq!(one(|s| s.playlists = 1), 3);
+        assert_eq!(one(|s| s.favorites = 1), 2);
+        assert_eq!(one(|s| s.listeners = 1), 2);
+        assert_eq!(one(|s| s.same_artist = 1), 2);
+        assert_eq!(one(|s| s.same_genre = 1), 1);
+
+        let all = SimilarSignals {
+            song_id: "s".to_string(),
+            playlists: 2,
+            favorites: 1,
+            listeners: 3,
+            same_artist: 1,
+            same_genre: 1,
+        };
+        assert_eq!(similar_score(&all), 2 * 3 + 2 + 3 * 2 + 2 + 1);
+    }
+
+    #[test]
+    fn affinity_weights_favorites_over_playlists_over_plays() {
+        assert_eq!(Affinity { favorites: 1, ..Default::default() }.score(), 3);
+        assert_eq!(Affinity { playlist_entries: 1, ..Default::default() }.score(), 2);
+        assert_eq!(Affinity { plays: 1, ..Default::default() }.score(), 1);
+    }
+
+    #[test]
+    fn mix_score_weights_artist_genre_and_cooccurrence() {
+        let signals = MixSignals {
+            song_id: "s".to_string(),
+            artist_favorites: 1,
+            artist_plays: 2,
+            genre_playlist_entries: 1,
+            cooccurrences: 2,
+            ..Default::default()
+        };
+        // Artist affinity 5 doubled, genre affinity 2, two shared playlists doubled
+        assert_eq!(mix_score(&signals), 5 * 2 + 2 + 2 * 2);
+    }
+
+    #[test]
+    fn rank_orders_by_score_then_id() {
+        let ranked = rank(scored(&[("b", 2), ("c", 5), ("a", 2), ("d", 1)]), 10);
+        assert_eq!(ranked, scored(&[("c", 5), ("a", 2), ("b", 2), ("d", 1)]));
+    }
+
+    #[test]
+    fn rank_drops_zero_scores_and_applies_limit() {
+        let ranked = rank(scored(&[("a", 0), ("b", 3), ("c", 1), ("d", 2)]), 2);
+        assert_eq!(ranked, scored(&[("b", 3), ("d", 2)]));
+        assert!(rank(scored(&[("a", 1)]), 0).is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::models::rendition_models::{Rendition, RenditionFormat};
+
+/// Media ranges of an `Accept` header with a non-zero quality, most preferred first
+fn accepted_media_ranges(accept: &str) -> Vec<String> {
+    let mut ranges: Vec<(f32, String)> = Vec::new();
+
+    for part in accept.split(',') {
+        let mut params = part.split(';');
+        let range = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        if range.is_empty() {
+            continue;
+        }
+        let quality = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .next()
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if quality > 0.0 {
+            ranges.push((quality, range));
+        }
+    }
+
+    // Stable sort keeps the header order between equal qualities
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranges.into_iter().map(|(_, range)| range).collect()
+}
+
+/// Whether an `Accept` header allows an audio content type
+pub fn accepts_audio(accept: &str, content_type: &str) -> bool {
+    accepted_media_ranges(accept)
+        .iter()
+        .any(|range| range == "*/*" || range == "audio/*" || range == content_type)
+}
+
+/// Rendition formats named by an `Accept` header, most preferred first
+pub fn accepted_formats(accept: &str) -> Vec<RenditionFormat> {
+    let mut formats: Vec<RenditionFormat> = Vec::new();
+    for range in accepted_media_ranges(accept) {
+        if let Some(format) = RenditionFormat::from_content_type(&range)
+            && !formats.contains(&format)
+        {
+            formats.push(format);
+        }
+    }
+    formats
+}
+
+/// Pick the best stored rendition in `format` (any format when `None`) whose bitrate does
+/// not exceed `max_bitrate`. Lossless renditions rank above any bitrate and never fit a cap.
+pub fn choose_rendition(
+    renditions: &[Rendition],
+    format: Option<RenditionFormat>,
+    max_bitrate: Option<i32>,
+) -> Option<&Rendition> {
+    fn quality(r: &Rendition) -> i32 {
+        r.bitrate_kbps.unwrap_or(i32::MAX)
+    }
+
+    renditions
+        .iter()
+        .filter(|r| format.is_none_or(|f| r.format == f.name()))
+        .filter(|r| max_bitrate.is_none_or(|max| quality(r) <= max))
+        .max_by_key(|r| quality(r))
+}
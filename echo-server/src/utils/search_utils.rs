@@ -0,0 +1,271 @@
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{BigInt, Text};
+
+use crate::models::search_models::{AlbumHit, ArtistHit, PlaylistHit, SearchResults};
+use crate::models::song_models::SongResponse;
+use crate::utils::song_query_utils::{SONG_COLUMNS, SONG_JOINS};
+
+/// Shortest word MySQL indexes (`innodb_ft_min_token_size`), shorter ones never match
+const FT_MIN_TOKEN_SIZE: usize = 3;
+
+/// A searched column: the column list of its FULLTEXT index, and the plain column
+/// used when the index cannot help
+struct SearchColumn {
+    fulltext: &'static str,
+    plain: &'static str,
+}
+
+const fn column(name: &'static str) -> SearchColumn {
+    SearchColumn { fulltext: name, plain: name }
+}
+
+/// How a query is matched against the searched columns
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// Every word must appear, as a prefix (`+word*`); ranked by natural language relevance
+    FullText { boolean: String, natural: String },
+    /// Names starting with the query, for queries without any indexed word
+    Prefix(String),
+    /// Names sounding like the query, to get past typos when nothing else matched
+    SoundsLike(String),
+}
+
+impl Matcher {
+    /// Full-text matcher for the words of `q` long enough to be indexed, or a prefix matcher
+    pub fn for_query(q: &str) -> Self {
+        let words: Vec<&str> = q
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() >= FT_MIN_TOKEN_SIZE)
+            .collect();
+
+        if words.is_empty() {
+            let escaped = q.trim().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            return Matcher::Prefix(format!("{}%", escaped));
+        }
+
+        Matcher::FullText {
+            boolean: words.iter().map(|w| format!("+{}*", w)).collect::<Vec<_>>().join(" "),
+            natural: words.join(" "),
+        }
+    }
+
+    fn condition(&self, columns: &[SearchColumn]) -> String {
+        let parts: Vec<String> = columns
+            .iter()
+            .map(|c| match self {
+                Matcher::FullText { .. } => format!("MATCH({}) AGAINST(? IN BOOLEAN MODE)", c.fulltext),
+                Matcher::Prefix(_) => format!("{} LIKE ?", c.plain),
+                Matcher::SoundsLike(_) => format!("SOUNDEX({}) = SOUNDEX(?)", c.plain),
+            })
+            .collect();
+        format!("({})", parts.join(" OR "))
+    }
+
+    fn rank(&self, columns: &[SearchColumn]) -> String {
+        match self {
+            Matcher::FullText { .. } => {
+                let parts: Vec<String> = columns
+                    .iter()
+                    .map(|c| format!("MATCH({}) AGAINST(? IN NATURAL LANGUAGE MODE)", c.fulltext))
+                    .collect();
+                parts.join(" + ")
+            }
+            // Ties, ordered by name afterwards
+            Matcher::Prefix(_) | Matcher::SoundsLike(_) => "0".to_string(),
+        }
+    }
+
+    /// Bind the values of `condition` then `rank`
+    fn bind<'a>(&self, mut query: BoxedSqlQuery<'a, Mysql, SqlQuery>, columns: usize) -> BoxedSqlQuery<'a, Mysql, SqlQuery> {
+        let (condition, rank) = match self {
+            Matcher::FullText { boolean, natural } => (boolean, Some(natural)),
+            Matcher::Prefix(pattern) => (pattern, None),
+            Matcher::SoundsLike(q) => (q, None),
+        };
+        for _ in 0..columns {
+            query = query.bind::<Text, _>(condition.clone());
+        }
+        if let Some(rank) = rank {
+            for _ in 0..columns {
+                query = query.bind::<Text, _>(rank.clone());
+            }
+        }
+        query
+    }
+}
+
+/// Run a search; `sql` has `{condition}` and `{rank}` placeholders, and its `?` are the
+/// `leading` values, then the matcher's, then the limit
+fn run_search<T>(
+    conn: &mut MysqlConnection,
+    sql: &str,
+    leading: &[&str],
+    matcher: &Matcher,
+    columns: &[SearchColumn],
+    limit: i64,
+) -> QueryResult<Vec<T>>
+where
+    T: QueryableByName<Mysql> + 'static,
+{
+    let sql = sql
+        .replace("{condition}", &matcher.condition(columns))
+        .replace("{rank}", &matcher.rank(columns));
+
+    let mut query = diesel::sql_query(sql).into_boxed::<Mysql>();
+    for value in leading {
+        query = query.bind::<Text, _>(value.to_string());
+    }
+    matcher.bind(query, columns.len()).bind::<BigInt, _>(limit).load::<T>(conn)
+}
+
+/// Run a search, and again by sound if nothing matched
+fn search_group<T>(
+    conn: &mut MysqlConnection,
+    sql: &str,
+    leading: &[&str],
+    q: &str,
+    matcher: &Matcher,
+    columns: &[SearchColumn],
+    limit: i64,
+) -> QueryResult<Vec<T>>
+where
+    T: QueryableByName<Mysql> + 'static,
+{
+    let hits = run_search::<T>(conn, sql, leading, matcher, columns, limit)?;
+    if !hits.is_empty() {
+        return Ok(hits);
+    }
+    run_search::<T>(conn, sql, leading, &Matcher::SoundsLike(q.trim().to_string()), columns, limit)
+}
+
+const ALBUM_SEARCH: &str = r#"
+    SELECT al.id, al.name, al.artist_id, a.name AS artist_name, al.release_year, al.cover_url
+    FROM albums al
+    JOIN artists a ON al.artist_id = a.id
+    WHERE {condition}
+    ORDER BY {rank} DESC, al.name, al.id
+    LIMIT ?
+"#;
+
+const ARTIST_SEARCH: &str = r#"
+    SELECT a.id, a.name, a.image_url
+    FROM artists a
+    WHERE {condition}
+    ORDER BY {rank} DESC, a.name, a.id
+    LIMIT ?
+"#;
+
+/// Public playlists, the user's own and those shared with them
+const PLAYLIST_SEARCH: &str = r#"
+    SELECT pl.id, pl.user_id, u.username AS owner, pl.name, pl.description
+    FROM playlists pl
+    JOIN users u ON pl.user_id = u.id
+    WHERE (
+        pl.is_public = TRUE OR pl.user_id = ?
+        OR EXISTS (SELECT 1 FROM playlist_members m WHERE m.playlist_id = pl.id AND m.user_id = ?)
+    ) AND {condition}
+    ORDER BY {rank} DESC, pl.name, pl.id
+    LIMIT ?
+"#;
+
+/// Songs by title or artist, albums by name or artist, artists by name and playlists
+/// visible to `user_id` (public, own or shared) by name or description
+pub fn search_all(conn: &mut MysqlConnection, q: &str, user_id: &str, limit: i64) -> QueryResult<SearchResults> {
+    let matcher = Matcher::for_query(q);
+
+    let song_search = format!(
+        r#"
+        SELECT {SONG_COLUMNS}
+        FROM songs s
+        {SONG_JOINS}
+        WHERE {{condition}}
+        ORDER BY {{rank}} DESC, s.title, s.id
+        LIMIT ?
+        "#
+    );
+
+    Ok(SearchResults {
+        songs: search_group::<SongResponse>(conn, &song_search, &[], q, &matcher, &[column("s.title"), column("a.name")], limit)?,
+        albums: search_group::<AlbumHit>(conn, ALBUM_SEARCH, &[], q, &matcher, &[column("al.name"), column("a.name")], limit)?,
+        artists: search_group::<ArtistHit>(conn, ARTIST_SEARCH, &[], q, &matcher, &[column("a.name")], limit)?,
+        playlists: search_group::<PlaylistHit>(
+            conn,
+            PLAYLIST_SEARCH,
+            &[user_id, user_id],
+            q,
+            &matcher,
+            &[SearchColumn { fulltext: "pl.name, pl.description", plain: "pl.name" }],
+            limit,
+        )?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_words_are_required_prefixes() {
+        match Matcher::for_query("Daft Punk - Harder, Better") {
+            Matcher::FullText { boolean, natural } => {
+                assert_eq!(boolean, "+Daft* +Punk* +Harder* +Better*");
+                assert_eq!(natural, "Daft Punk Harder Better");
+            }
+            other => panic!("expected a full-text matcher, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn words_too_short_to_be_indexed_are_dropped() {
+        match Matcher::for_query("U2 of the sun") {
+            Matcher::FullText { boolean, natural } => {
+                assert_eq!(boolean, "+the* +sun*");
+                assert_eq!(natural, "the sun");
+            }
+            other => panic!("expected a full-text matcher, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn queries_without_indexed_words_match_by_escaped_prefix() {
+        assert!(matches!(Matcher::for_query(" U2 "), Matcher::Prefix(p) if p == "U2%"));
+        assert!(matches!(Matcher::for_query("a_%"), Matcher::Prefix(p) if p == "a\\_\\%%"));
+    }
+
+    #[test]
+    fn condition_matches_any_column() {
+        let columns = [column("s.title"), column("a.name")];
+        assert_eq!(
+            Matcher::for_query("hello").condition(&columns),
+            "(MATCH(s.title) AGAINST(? IN BOOLEAN MODE) OR MATCH(a.name) AGAINST(? IN BOOLEAN MODE))"
+        );
+        assert_eq!(Matcher::Prefix("ab%".to_string()).condition(&columns), "(s.title LIKE ? OR a.name LIKE ?)");
+        assert_eq!(
+            Matcher::SoundsLike("helo".to_string()).condition(&columns),
+            "(SOUNDEX(s.title) = SOUNDEX(?) OR SOUNDEX(a.name) = SOUNDEX(?))"
+        );
+    }
+
+    #[test]
+    fn fulltext_condition_uses_the_whole_index() {
+        let columns = [SearchColumn { fulltext: "pl.name, pl.description", plain: "pl.name" }];
+        assert_eq!(
+            Matcher::for_query("road trip").condition(&columns),
+            "(MATCH(pl.name, pl.description) AGAINST(? IN BOOLEAN MODE))"
+        );
+        assert_eq!(Matcher::Prefix("ro%".to_string()).condition(&columns), "(pl.name LIKE ?)");
+    }
+
+    #[test]
+    fn only_fulltext_matches_are_ranked() {
+        let columns = [column("s.title"), column("a.name")];
+        assert_eq!(
+            Matcher::for_query("hello").rank(&columns),
+            "MATCH(s.title) AGAINST(? IN NATURAL LANGUAGE MODE) + MATCH(a.name) AGAINST(? IN NATURAL LANGUAGE MODE)"
+        );
+        assert_eq!(Matcher::Prefix("ab%".to_string()).rank(&columns), "0");
+        assert_eq!(Matcher::SoundsLike("helo".to_string()).rank(&columns), "0");
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Default lifetime of a signed stream URL, overridable with `STREAM_URL_TTL_SECONDS`
+const DEFAULT_STREAM_URL_TTL_SECONDS: i64 = 900;
+
+/// Default lifetime of the stream URLs of exported playlists, which other players keep for
+/// a while. Overridable with `EXPORT_STREAM_URL_TTL_SECONDS`
+const DEFAULT_EXPORT_STREAM_URL_TTL_SECONDS: i64 = 7 * 24 * 3600;
+
+/// Default base of signed URLs, overridable with `PUBLIC_BASE_URL`
+const DEFAULT_PUBLIC_BASE_URL: &str = "http://localhost:8080";
+
+pub fn stream_url_ttl_seconds() -> i64 {
+    ttl_from_env("STREAM_URL_TTL_SECONDS", DEFAULT_STREAM_URL_TTL_SECONDS)
+}
+
+pub fn export_stream_url_ttl_seconds() -> i64 {
+    ttl_from_env("EXPORT_STREAM_URL_TTL_SECONDS", DEFAULT_EXPORT_STREAM_URL_TTL_SECONDS)
+}
+
+fn ttl_from_env(name: &str, default: i64) -> i64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(default)
+}
+
+/// Scheme and host clients reach the API on. It is configured rather than taken from the
+/// request, whose Host header is chosen by the client.
+pub fn public_base_url() -> String {
+    std::env::var("PUBLIC_BASE_URL")
+        .ok()
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_PUBLIC_BASE_URL.to_string())
+}
+
+fn stream_mac(song_id: &str, user_id: &str, expires: i64, secret: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length, so this cannot fail
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    // The "stream" prefix keeps these signatures from being valid for any other purpose
+    mac.update(format!("stream:{}:{}:{}", song_id, user_id, expires).as_bytes());
+    mac
+}
+
+/// Sign a stream grant for `song_id`, bound to `user_id` and valid until `expires` (unix seconds).
+pub fn sign_stream(song_id: &str, user_id: &str, expires: i64, secret: &[u8]) -> String {
+    hex::encode(stream_mac(song_id, user_id, expires, secret).finalize().into_bytes())
+}
+
+/// Signed URL streaming `song_id` for `user_id` until `expires`, under `base_url`
+pub fn signed_stream_url(base_url: &str, song_id: &str, user_id: &str, expires: i64, secret: &[u8]) -> String {
+    format!(
+        "{}/api/stream/{}?uid={}&exp={}&sig={}",
+        base_url,
+        song_id,
+        user_id,
+        expires,
+        sign_stream(song_id, user_id, expires, secret)
+    )
+}
+
+/// Verify a stream grant in constant time. Expired or malformed signatures are rejected.
+pub fn verify_stream_signature(song_id: &str, user_id: &str, expires: i64, signature: &str, secret: &[u8]) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+
+    let signature_bytes = match hex::decode(signature) {
+        Ok(b) => b,
+        Err(_) => return false,
+    };
+
+    stream_mac(song_id, user_id, expires, secret)
+        .verify_slice(&signature_bytes)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn in_an_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn signature_verifies() {
+        let expires = in_an_hour();
+        let signature = sign_stream("song", "user", expires, SECRET);
+        assert!(verify_stream_signature("song", "user", expires, &signature, SECRET));
+    }
+
+    #[test]
+    fn expired_signature_is_rejected() {
+        let expires = Utc::now().timestamp() - 1;
+        let signature = sign_stream("song", "user", expires, SECRET);
+        assert!(!verify_stream_signature("song", "user", expires, &signature, SECRET));
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let expires = in_an_hour();
+        let mut signature = sign_stream("song", "user", expires, SECRET);
+        let last = if signature.ends_with('0') { "1" } else { "0" };
+        signature.replace_range(signature.len() - 1.., last);
+        assert!(!verify_stream_signature("song", "user", expires, &signature, SECRET));
+        assert!(!verify_stream_signature("song", "user", expires, "not hex", SECRET));
+    }
+
+    #[test]
+    fn signature_is_bound_to_its_grant() {
+        let expires = in_an_hour();
+        let signature = sign_stream("song", "user", expires, SECRET);
+        assert!(!verify_stream_signature("other song", "user", expires, &signature, SECRET));
+        assert!(!verify_stream_signature("song", "other user", expires, &signature, SECRET));
+        assert!(!verify_stream_signature("song", "user", expires + 1, &signature, SECRET));
+        assert!(!verify_stream_signature("song", "user", expires, &signature, b"other secret"));
+    }
+
+    #[test]
+    fn url_points_to_the_base_url() {
+        let url = signed_stream_url("https://music.example", "song", "user", 42, SECRET);
+        let signature = sign_stream("song", "user", 42, SECRET);
+        assert_eq!(url, format!("https://music.example/api/stream/song?uid=user&exp=42&sig={}", signature));
+    }
+}
@@ -0,0 +1,237 @@
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::models::playlist_models::ImportEntry;
+use crate::models::song_models::SongResponse;
+use crate::schema::songs;
+use crate::utils::song_query_utils::{SONG_COLUMNS, SONG_JOINS};
+
+/// Songs looked at for each entry, most relevant title first
+const MATCH_CANDIDATES: i64 = 25;
+/// Lowest title similarity for a song to be a candidate at all
+const MIN_TITLE_SIMILARITY: f64 = 0.8;
+/// Lowest artist similarity, when both the entry and the song name one
+const MIN_ARTIST_SIMILARITY: f64 = 0.6;
+/// Entries longer or shorter than a song by more than this, in seconds, are another recording
+const MAX_DURATION_GAP: i32 = 30;
+const MIN_MATCH_SCORE: f64 = 0.75;
+
+/// Song ids in our own stream and song URLs, as written by the playlist export
+static SONG_URL_ID: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"/(?:stream|songs)/([0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})").unwrap()
+});
+/// Bracketed parts of names: "(feat. ...)", "[Remastered 2011]"...
+static BRACKETED: Lazy<Regex> = Lazy::new(|| Regex::new(r"\([^)]*\)|\[[^\]]*\]").unwrap());
+
+const CANDIDATE_SEARCH: &str = r#"
+    WHERE MATCH(s.title) AGAINST(? IN NATURAL LANGUAGE MODE) OR SOUNDEX(s.title) = SOUNDEX(?)
+    ORDER BY MATCH(s.title) AGAINST(? IN NATURAL LANGUAGE MODE) DESC, s.id
+    LIMIT ?
+"#;
+
+/// Lowercase words of a name, without brackets or punctuation
+fn normalize(name: &str) -> String {
+    let name = BRACKETED.replace_all(name, " ").to_lowercase();
+    let cleaned: String = name.chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).collect();
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 1 for the same normalized names, down to 0 for nothing in common
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize(a).chars().collect();
+    let b: Vec<char> = normalize(b).chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// How well a song fits an entry, from 0 to 1; `None` when it cannot be the entry
+fn match_score(entry: &ImportEntry, title: &str, song: &SongResponse) -> Option<f64> {
+    let title_score = similarity(title, &song.title);
+    if title_score < MIN_TITLE_SIMILARITY {
+        return None;
+    }
+
+    let artist_score = match (entry.artist.as_deref(), song.artist_name.as_deref()) {
+        (Some(wanted), Some(actual)) => {
+            let score = similarity(wanted, actual);
+            if score < MIN_ARTIST_SIMILARITY {
+                return None;
+            }
+            score
+        }
+        // Nothing to compare: neither for nor against
+        _ => 0.5,
+    };
+
+    let duration_score = match entry.duration_seconds {
+        Some(wanted) => match (wanted - song.duration_seconds).abs() {
+            gap if gap > MAX_DURATION_GAP => return None,
+            gap if gap <= 3 => 1.0,
+            gap if gap <= 10 => 0.5,
+            _ => 0.0,
+        },
+        None => 0.5,
+    };
+
+    Some(0.6 * title_score + 0.3 * artist_score + 0.1 * duration_score)
+}
+
+/// The song an imported entry stands for: the one its location points at when it is one of
+/// ours, otherwise the best fit by title, artist and duration, if it fits well enough
+pub fn match_song(conn: &mut MysqlConnection, entry: &ImportEntry) -> QueryResult<Option<String>> {
+    if let Some(caps) = entry.location.as_deref().and_then(|l| SONG_URL_ID.captures(l)) {
+        let song_id = caps[1].to_lowercase();
+        let known = songs::table
+            .filter(songs::id.eq(&song_id))
+            .count()
+            .get_result::<i64>(conn)?;
+        if known > 0 {
+            return Ok(Some(song_id));
+        }
+    }
+
+    let Some(title) = entry.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) else {
+        return Ok(None);
+    };
+    let search = match normalize(title) {
+        normalized if normalized.is_empty() => title.to_string(),
+        normalized => normalized,
+    };
+
+    let candidates = diesel::sql_query(format!("SELECT {} FROM songs s {} {}", SONG_COLUMNS, SONG_JOINS, CANDIDATE_SEARCH))
+        .into_boxed::<Mysql>()
+        .bind::<Text, _>(search.clone())
+        .bind::<Text, _>(search.clone())
+        .bind::<Text, _>(search)
+        .bind::<BigInt, _>(MATCH_CANDIDATES)
+        .load::<SongResponse>(conn)?;
+
+    let best = candidates
+        .into_iter()
+        .filter_map(|song| match_score(entry, title, &song).map(|score| (score, song.id)))
+        .filter(|(score, _)| *score >= MIN_MATCH_SCORE)
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(best.map(|(_, id)| id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(value: &str) -> Vec<char> {
+        value.chars().collect()
+    }
+
+    fn song(title: &str, artist: Option<&str>, duration_seconds: i32) -> SongResponse {
+        SongResponse {
+            id: "song".to_string(),
+            title: title.to_string(),
+            artist_id: "artist".to_string(),
+            artist_name: artist.map(str::to_string),
+            album_id: None,
+            album_name: None,
+            genre_id: None,
+            genre_name: None,
+            track_number: None,
+            duration_seconds,
+            object_url: String::new(),
+            track_gain: None,
+            track_peak: None,
+            album_gain: None,
+            album_peak: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn entry(artist: Option<&str>, duration_seconds: Option<i32>) -> ImportEntry {
+        ImportEntry {
+            artist: artist.map(str::to_string),
+            duration_seconds,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein(&chars(""), &chars("")), 0);
+        assert_eq!(levenshtein(&chars(""), &chars("abc")), 3);
+        assert_eq!(levenshtein(&chars("abc"), &chars("")), 3);
+        assert_eq!(levenshtein(&chars("same"), &chars("same")), 0);
+        assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(levenshtein(&chars("sitting"), &chars("kitten")), 3);
+        // Characters, not bytes
+        assert_eq!(levenshtein(&chars("café"), &chars("cafe")), 1);
+    }
+
+    #[test]
+    fn similarity_ignores_case_punctuation_and_brackets() {
+        assert_close(similarity("Hey Jude", "hey jude"), 1.0);
+        assert_close(similarity("Hey, Jude!", "Hey Jude"), 1.0);
+        assert_close(similarity("Yesterday (Remastered 2009)", "Yesterday"), 1.0);
+        assert_close(similarity("Let It Be [Live]", "let   it be"), 1.0);
+        assert_close(similarity("kitten", "sitting"), 1.0 - 3.0 / 7.0);
+        assert_close(similarity("abc", "xyz"), 0.0);
+        // Names with nothing left to compare never match
+        assert_close(similarity("", ""), 0.0);
+        assert_close(similarity("(Intro)", "!!"), 0.0);
+    }
+
+    #[test]
+    fn match_score_weighs_title_artist_and_duration() {
+        let candidate = song("Hey Jude", Some("The Beatles"), 431);
+
+        assert_close(match_score(&entry(Some("The Beatles"), Some(431)), "Hey Jude", &candidate).unwrap(), 1.0);
+        // Unknown artist and duration count half
+        assert_close(match_score(&entry(None, None), "Hey Jude", &candidate).unwrap(), 0.6 + 0.15 + 0.05);
+        assert_close(match_score(&entry(Some("The Beatles"), None), "Hey Jude", &song("Hey Jude", None, 431)).unwrap(), 0.6 + 0.15 + 0.05);
+    }
+
+    #[test]
+    fn match_score_steps_down_with_the_duration_gap() {
+        let candidate = song("Hey Jude", Some("The Beatles"), 431);
+        let score = |duration| match_score(&entry(Some("The Beatles"), Some(duration)), "Hey Jude", &candidate);
+
+        assert_close(score(428).unwrap(), 1.0);
+        assert_close(score(434).unwrap(), 1.0);
+        assert_close(score(441).unwrap(), 0.95);
+        assert_close(score(442).unwrap(), 0.9);
+        assert_close(score(461).unwrap(), 0.9);
+        assert_eq!(score(462), None);
+        assert_eq!(score(400), None);
+    }
+
+    #[test]
+    fn match_score_rejects_other_titles_and_artists() {
+        let candidate = song("Hey Jude", Some("The Beatles"), 431);
+
+        assert_eq!(match_score(&entry(None, None), "Hey You", &candidate), None);
+        assert_eq!(match_score(&entry(Some("Pink Floyd"), None), "Hey Jude", &candidate), None);
+        // A close enough artist still matches, with a lower score
+        let score = match_score(&entry(Some("Beatles"), Some(431)), "Hey Jude", &candidate).unwrap();
+        assert_close(score, 0.6 + 0.3 * (1.0 - 4.0 / 11.0) + 0.1);
+    }
+}
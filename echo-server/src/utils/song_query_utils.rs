@@ -0,0 +1,505 @@
+use diesel::prelude::*;
+
+use crate::models::pagination_models::{Page, PageError};
+use crate::models::song_models::{SongQuery, SongResponse};
+use crate::utils::pagination_utils::{load_all, load_page, KeysetQuery, PageRequest, SortKey};
+use crate::utils::sql_utils::BindValue;
+
+/// Full song columns of `s`, for `SongResponse`
+pub const SONG_COLUMNS: &str = r#"
+    s.id, s.title, s.artist_id, a.name AS artist_name,
+    s.album_id, al.name AS album_name, s.genre_id, g.name AS genre_name,
+    s.track_number, s.duration_seconds, s.object_url,
+    s.track_gain, s.track_peak, al.album_gain, al.album_peak,
+    s.created_at, s.updated_at
+"#;
+
+pub const SONG_JOINS: &str = r#"
+    JOIN artists a ON s.artist_id = a.id
+    LEFT JOIN albums al ON s.album_id = al.id
+    LEFT JOIN genres g ON s.genre_id = g.id
+"#;
+
+/// Songs a listing is drawn from
+#[derive(Debug, Clone, PartialEq)]
+pub enum SongSource {
+    All,
+    Album(String),
+    Artist(String),
+    Genre(i32),
+    /// Favorites of a user
+    Favorites(String),
+    Playlist(String),
+}
+
+/// A condition on listed songs. Text filters match anywhere in the name, ignoring case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SongFilter {
+    Title(String),
+    Artist(String),
+    Album(String),
+    Genre(String),
+    /// Release year of the album, inclusive
+    YearFrom(i32),
+    YearTo(i32),
+    /// Duration in seconds, inclusive
+    DurationMin(i32),
+    DurationMax(i32),
+    GenreId(i32),
+    /// By any of these artists
+    ArtistIds(Vec<String>),
+    /// Added to the library in the last days
+    AddedWithinDays(i32),
+    /// Among the favorites of a user
+    FavoriteOf(String),
+    /// Played by a user more times than the count
+    PlayedMoreThan(String, i64),
+}
+
+impl SongFilter {
+    fn sql(&self) -> (String, Vec<BindValue>) {
+        let (condition, value) = match self {
+            SongFilter::Title(v) => ("s.title LIKE ?", BindValue::Text(contains_pattern(v))),
+            SongFilter::Artist(v) => ("a.name LIKE ?", BindValue::Text(contains_pattern(v))),
+            SongFilter::Album(v) => ("al.name LIKE ?", BindValue::Text(contains_pattern(v))),
+            SongFilter::Genre(v) => ("g.name LIKE ?", BindValue::Text(contains_pattern(v))),
+            SongFilter::YearFrom(v) => ("al.release_year >= ?", BindValue::Int(*v)),
+            SongFilter::YearTo(v) => ("al.release_year <= ?", BindValue::Int(*v)),
+            SongFilter::DurationMin(v) => ("s.duration_seconds >= ?", BindValue::Int(*v)),
+            SongFilter::DurationMax(v) => ("s.duration_seconds <= ?", BindValue::Int(*v)),
+            SongFilter::GenreId(v) => ("s.genre_id = ?", BindValue::Int(*v)),
+            SongFilter::ArtistIds(ids) => {
+                let placeholders = vec!["?"; ids.len()].join(", ");
+                return (
+                    format!("s.artist_id IN ({})", placeholders),
+                    ids.iter().map(|id| BindValue::Text(id.clone())).collect(),
+                );
+            }
+            SongFilter::AddedWithinDays(v) => ("s.created_at >= NOW() - INTERVAL ? DAY", BindValue::Int(*v)),
+            SongFilter::FavoriteOf(user_id) => (
+                "EXISTS (SELECT 1 FROM favorites fav WHERE fav.song_id = s.id AND fav.user_id = ?)",
+                BindValue::Text(user_id.clone()),
+            ),
+            SongFilter::PlayedMoreThan(user_id, count) => {
+                return (
+                    "(SELECT COUNT(*) FROM plays p WHERE p.song_id = s.id AND p.user_id = ?) > ?".to_string(),
+                    vec![BindValue::Text(user_id.clone()), BindValue::BigInt(*count)],
+                );
+            }
+        };
+        (condition.to_string(), vec![value])
+    }
+}
+
+/// `LIKE` pattern matching `value` anywhere, with its wildcards escaped
+pub(crate) fn contains_pattern(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    /// When the song was added to the library
+    ReleaseDate,
+    Name,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    Track,
+    /// Position in the playlist
+    Position,
+    /// When the song was added to the favorites or playlist
+    AddedAt,
+}
+
+impl SortField {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "release_date" => Some(SortField::ReleaseDate),
+            "name" => Some(SortField::Name),
+            "artist" => Some(SortField::Artist),
+            "album" => Some(SortField::Album),
+            "year" => Some(SortField::Year),
+            "duration" => Some(SortField::Duration),
+            "track" => Some(SortField::Track),
+            "position" => Some(SortField::Position),
+            "added_at" => Some(SortField::AddedAt),
+            _ => None,
+        }
+    }
+
+    /// Column sorted on, if the field applies to the source
+    fn column(&self, source: &SongSource) -> Option<&'static str> {
+        match (self, source) {
+            (SortField::ReleaseDate, _) => Some("s.created_at"),
+            (SortField::Name, _) => Some("s.title"),
+            (SortField::Artist, _) => Some("a.name"),
+            (SortField::Album, _) => Some("al.name"),
+            (SortField::Year, _) => Some("al.release_year"),
+            (SortField::Duration, _) => Some("s.duration_seconds"),
+            (SortField::Track, _) => Some("s.track_number"),
+            (SortField::Position, SongSource::Playlist(_)) => Some("ps.position"),
+            (SortField::AddedAt, SongSource::Playlist(_)) => Some("ps.added_at"),
+            (SortField::AddedAt, SongSource::Favorites(_)) => Some("f.added_at"),
+            (SortField::Position | SortField::AddedAt, _) => None,
+        }
+    }
+
+    /// Whether the column can be NULL
+    fn nullable(&self) -> bool {
+        matches!(
+            self,
+            SortField::ReleaseDate | SortField::Album | SortField::Year | SortField::Track | SortField::AddedAt
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// A song listing, built up from filters and sort keys and run with every value bound
+#[derive(Debug, Clone)]
+pub struct SongListing {
+    source: SongSource,
+    filters: Vec<SongFilter>,
+    order: Vec<(SortField, SortOrder)>,
+    random: bool,
+    /// Only the first songs in the listing's order
+    cap: Option<i64>,
+}
+
+impl SongListing {
+    pub fn new(source: SongSource) -> Self {
+        Self {
+            source,
+            filters: Vec::new(),
+            order: Vec::new(),
+            random: false,
+            cap: None,
+        }
+    }
+
+    pub fn filters(mut self, filters: impl IntoIterator<Item = SongFilter>) -> Self {
+        self.filters.extend(filters);
+        self
+    }
+
+    /// Shuffle instead of sorting
+    pub fn random(mut self, random: bool) -> Self {
+        self.random = random;
+        self
+    }
+
+    /// Keep only the first `count` songs, in the listing's order
+    pub fn cap(mut self, count: i64) -> Self {
+        self.cap = Some(count);
+        self
+    }
+
+    /// Parse `sort` (`name`, `-year`, `artist,-year`...) into sort keys for the source
+    pub fn sort_by(mut self, sort: &str) -> Result<Self, String> {
+        for key in sort.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+            let (name, order) = match key.strip_prefix('-') {
+                Some(name) => (name, SortOrder::Desc),
+                None => (key, SortOrder::Asc),
+            };
+            let field = SortField::parse(name)
+                .filter(|f| f.column(&self.source).is_some())
+                .ok_or_else(|| format!("Cannot sort by {}", name))?;
+            self.order.push((field, order));
+        }
+        Ok(self)
+    }
+
+    /// Natural order of the source, when nothing else is asked for
+    fn default_order(&self) -> Vec<SortKey> {
+        match self.source {
+            SongSource::All => vec![SortKey::asc("s.title")],
+            SongSource::Album(_) => vec![SortKey::asc("s.track_number").nullable(), SortKey::asc("s.title")],
+            SongSource::Artist(_) => vec![
+                SortKey::desc("al.release_year").nullable(),
+                SortKey::asc("al.name").nullable(),
+                SortKey::asc("s.title"),
+            ],
+            SongSource::Genre(_) => vec![SortKey::asc("s.title")],
+            SongSource::Favorites(_) => vec![SortKey::desc("f.added_at").nullable()],
+            SongSource::Playlist(_) => vec![SortKey::asc("ps.position")],
+        }
+    }
+
+    /// The listing as a query paged on its sort keys
+    fn keyset(&self) -> KeysetQuery {
+        let mut binds = Vec::new();
+        let mut conditions = Vec::new();
+
+        let from = match &self.source {
+            SongSource::All => "FROM songs s",
+            SongSource::Album(album_id) => {
+                conditions.push("s.album_id = ?".to_string());
+                binds.push(BindValue::Text(album_id.clone()));
+                "FROM songs s"
+            }
+            SongSource::Artist(artist_id) => {
+                conditions.push("s.artist_id = ?".to_string());
+                binds.push(BindValue::Text(artist_id.clone()));
+                "FROM songs s"
+            }
+            SongSource::Genre(genre_id) => {
+                conditions.push("s.genre_id = ?".to_string());
+                binds.push(BindValue::Int(*genre_id));
+                "FROM songs s"
+            }
+            SongSource::Favorites(user_id) => {
+                conditions.push("f.user_id = ?".to_string());
+                binds.push(BindValue::Text(user_id.clone()));
+                "FROM favorites f JOIN songs s ON f.song_id = s.id"
+            }
+            SongSource::Playlist(playlist_id) => {
+                conditions.push("ps.playlist_id = ?".to_string());
+                binds.push(BindValue::Text(playlist_id.clone()));
+                "FROM playlist_songs ps JOIN songs s ON ps.song_id = s.id"
+            }
+        };
+
+        for filter in &self.filters {
+            let (condition, values) = filter.sql();
+            conditions.push(condition);
+            binds.extend(values);
+        }
+
+        let mut keys: Vec<SortKey> = self
+            .order
+            .iter()
+            .filter_map(|(field, order)| {
+                let column = field.column(&self.source)?;
+                let key = match order {
+                    SortOrder::Asc => SortKey::asc(column),
+                    SortOrder::Desc => SortKey::desc(column),
+                };
+                Some(if field.nullable() { key.nullable() } else { key })
+            })
+            .collect();
+        if keys.is_empty() {
+            keys = self.default_order();
+        }
+        // Stable pages
+        keys.push(SortKey::asc("s.id"));
+
+        let query = KeysetQuery {
+            select: SONG_COLUMNS.to_string(),
+            from: format!("{} {}", from, SONG_JOINS),
+            conditions,
+            binds,
+            keys,
+            shuffle: self.random,
+        };
+        match self.cap {
+            Some(count) => query.capped("s.id", count),
+            None => query,
+        }
+    }
+
+    pub fn load_page(&self, conn: &mut MysqlConnection, page: &PageRequest) -> Result<Page<SongResponse>, PageError> {
+        load_page::<SongResponse>(conn, &self.keyset(), page)
+    }
+
+    /// Every song of the listing, unpaged
+    pub fn load_all(&self, conn: &mut MysqlConnection) -> QueryResult<Vec<SongResponse>> {
+        load_all::<SongResponse>(conn, &self.keyset())
+    }
+}
+
+/// Filters given in the query string of a song listing
+pub fn song_filters(query: &SongQuery) -> Result<Vec<SongFilter>, String> {
+    if let (Some(from), Some(to)) = (query.year_from, query.year_to)
+        && from > to
+    {
+        return Err("year_from must not be after year_to".to_string());
+    }
+    if let (Some(min), Some(max)) = (query.duration_min, query.duration_max)
+        && min > max
+    {
+        return Err("duration_min must not be greater than duration_max".to_string());
+    }
+
+    let text = |value: &Option<String>, filter: fn(String) -> SongFilter| {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(|v| filter(v.to_string()))
+    };
+
+    Ok([
+        text(&query.name, SongFilter::Title),
+        text(&query.artist, SongFilter::Artist),
+        text(&query.album, SongFilter::Album),
+        text(&query.genre, SongFilter::Genre),
+        query.year_from.map(SongFilter::YearFrom),
+        query.year_to.map(SongFilter::YearTo),
+        query.duration_min.map(SongFilter::DurationMin),
+        query.duration_max.map(SongFilter::DurationMax),
+    ]
+    .into_iter()
+    .flatten()
+    .collect())
+}
+
+/// Listing of `source` with the filters, sort and shuffle of `query`
+pub fn song_listing(source: SongSource, query: &SongQuery) -> Result<SongListing, String> {
+    let listing = SongListing::new(source)
+        .filters(song_filters(query)?)
+        .random(query.random.unwrap_or(false));
+    match query.sort.as_deref() {
+        Some(sort) => listing.sort_by(sort),
+        None => Ok(listing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> BindValue {
+        BindValue::Text(value.to_string())
+    }
+
+    #[test]
+    fn text_filters_bind_contains_patterns() {
+        assert_eq!(
+            SongFilter::Title("love".to_string()).sql(),
+            ("s.title LIKE ?".to_string(), vec![text("%love%")])
+        );
+        assert_eq!(
+            SongFilter::Artist("o'brien".to_string()).sql(),
+            ("a.name LIKE ?".to_string(), vec![text("%o'brien%")])
+        );
+        assert_eq!(SongFilter::Album("x".to_string()).sql().0, "al.name LIKE ?");
+        assert_eq!(SongFilter::Genre("x".to_string()).sql().0, "g.name LIKE ?");
+    }
+
+    #[test]
+    fn range_filters_bind_their_bounds() {
+        assert_eq!(
+            SongFilter::YearFrom(1990).sql(),
+            ("al.release_year >= ?".to_string(), vec![BindValue::Int(1990)])
+        );
+        assert_eq!(
+            SongFilter::YearTo(1999).sql(),
+            ("al.release_year <= ?".to_string(), vec![BindValue::Int(1999)])
+        );
+        assert_eq!(
+            SongFilter::DurationMin(60).sql(),
+            ("s.duration_seconds >= ?".to_string(), vec![BindValue::Int(60)])
+        );
+        assert_eq!(
+            SongFilter::DurationMax(300).sql(),
+            ("s.duration_seconds <= ?".to_string(), vec![BindValue::Int(300)])
+        );
+    }
+
+    #[test]
+    fn artist_ids_bind_one_placeholder_each() {
+        let filter = SongFilter::ArtistIds(vec!["a1".to_string(), "a2".to_string(), "a3".to_string()]);
+        assert_eq!(
+            filter.sql(),
+            ("s.artist_id IN (?, ?, ?)".to_string(), vec![text("a1"), text("a2"), text("a3")])
+        );
+    }
+
+    #[test]
+    fn played_more_than_binds_user_then_count() {
+        let (sql, binds) = SongFilter::PlayedMoreThan("u1".to_string(), 5).sql();
+        assert!(sql.ends_with("> ?"));
+        assert_eq!(binds, vec![text("u1"), BindValue::BigInt(5)]);
+    }
+
+    #[test]
+    fn filter_values_never_reach_the_sql() {
+        let hostile = "x' OR '1'='1";
+        for filter in [
+            SongFilter::Title(hostile.to_string()),
+            SongFilter::FavoriteOf(hostile.to_string()),
+            SongFilter::ArtistIds(vec![hostile.to_string()]),
+        ] {
+            let (sql, binds) = filter.sql();
+            assert!(!sql.contains(hostile));
+            assert_eq!(sql.matches('?').count(), binds.len());
+        }
+    }
+
+    #[test]
+    fn contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("abc"), "%abc%");
+        assert_eq!(contains_pattern("100%"), "%100\\%%");
+        assert_eq!(contains_pattern("a_b"), "%a\\_b%");
+        assert_eq!(contains_pattern("c:\\dir"), "%c:\\\\dir%");
+        assert_eq!(contains_pattern("\\%"), "%\\\\\\%%");
+    }
+
+    #[test]
+    fn sort_by_parses_keys_and_directions() {
+        let listing = SongListing::new(SongSource::All).sort_by("artist, -year,,name").unwrap();
+        assert_eq!(
+            listing.order,
+            vec![
+                (SortField::Artist, SortOrder::Asc),
+                (SortField::Year, SortOrder::Desc),
+                (SortField::Name, SortOrder::Asc),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_rejects_unknown_keys() {
+        let err = SongListing::new(SongSource::All).sort_by("name,title").unwrap_err();
+        assert_eq!(err, "Cannot sort by title");
+        assert!(SongListing::new(SongSource::All).sort_by("name; DROP TABLE songs").is_err());
+    }
+
+    #[test]
+    fn sort_by_rejects_keys_foreign_to_the_source() {
+        assert!(SongListing::new(SongSource::All).sort_by("position").is_err());
+        assert!(SongListing::new(SongSource::Favorites("u".to_string())).sort_by("position").is_err());
+        assert!(SongListing::new(SongSource::Playlist("p".to_string())).sort_by("position").is_ok());
+        assert!(SongListing::new(SongSource::Favorites("u".to_string())).sort_by("-added_at").is_ok());
+    }
+
+    #[test]
+    fn keyset_binds_source_then_filters() {
+        let query = SongListing::new(SongSource::Album("al1".to_string()))
+            .filters([SongFilter::Title("x".to_string()), SongFilter::YearFrom(2000)])
+            .keyset();
+        assert_eq!(query.conditions, vec!["s.album_id = ?", "s.title LIKE ?", "al.release_year >= ?"]);
+        assert_eq!(query.binds, vec![text("al1"), text("%x%"), BindValue::Int(2000)]);
+    }
+
+    #[test]
+    fn song_filters_reads_the_query() {
+        let query = SongQuery {
+            name: Some("  love ".to_string()),
+            artist: Some("   ".to_string()),
+            year_from: Some(1990),
+            duration_max: Some(240),
+            ..Default::default()
+        };
+        assert_eq!(
+            song_filters(&query).unwrap(),
+            vec![
+                SongFilter::Title("love".to_string()),
+                SongFilter::YearFrom(1990),
+                SongFilter::DurationMax(240),
+            ]
+        );
+    }
+
+    #[test]
+    fn song_filters_validates_ranges() {
+        let years = SongQuery { year_from: Some(2000), year_to: Some(1990), ..Default::default() };
+        assert!(song_filters(&years).is_err());
+        let durations = SongQuery { duration_min: Some(300), duration_max: Some(100), ..Default::default() };
+        assert!(song_filters(&durations).is_err());
+        let equal = SongQuery { year_from: Some(2000), year_to: Some(2000), ..Default::default() };
+        assert_eq!(song_filters(&equal).unwrap().len(), 2);
+    }
+}
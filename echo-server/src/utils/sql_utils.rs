@@ -0,0 +1,29 @@
+use diesel::mysql::Mysql;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
+
+/// Value bound to a `?` of a built query
+#[derive(Debug, Clone, PartialEq)]
+pub enum BindValue {
+    Text(String),
+    Int(i32),
+    BigInt(i64),
+    /// Value read back from a row, compared with the column it came from
+    NullableText(Option<String>),
+}
+
+/// Bind `values` to the `?` of `query`, in order
+pub fn bind_values<'a>(
+    mut query: BoxedSqlQuery<'a, Mysql, SqlQuery>,
+    values: Vec<BindValue>,
+) -> BoxedSqlQuery<'a, Mysql, SqlQuery> {
+    for value in values {
+        query = match value {
+            BindValue::Text(v) => query.bind::<Text, _>(v),
+            BindValue::Int(v) => query.bind::<Integer, _>(v),
+            BindValue::BigInt(v) => query.bind::<BigInt, _>(v),
+            BindValue::NullableText(v) => query.bind::<Nullable<Text>, _>(v),
+        };
+    }
+    query
+}
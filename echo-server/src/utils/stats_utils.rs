@@ -0,0 +1,272 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Text, Timestamp};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, Instant};
+
+use crate::models::stats_models::{BusiestDay, ListeningSummary, StatsBucket, TopItem, TopKind};
+
+/// Seconds a cached statistic is served for, overridable with `STATS_CACHE_TTL_SECONDS`
+const DEFAULT_STATS_CACHE_TTL_SECONDS: u64 = 600;
+
+/// Ranges shorter than this are cheap enough to compute on every request
+const MIN_CACHED_RANGE_DAYS: i64 = 7;
+
+/// Upper bound on cached results, to keep memory in check
+const MAX_CACHE_ENTRIES: usize = 1000;
+
+/// Seconds of listening counted for a play: what the client reported, or the whole song
+const LISTENED_SECONDS: &str = "COALESCE(p.seconds_listened, s.duration_seconds)";
+
+/// Plays of one user between two instants, with the user's time zone for day boundaries
+#[derive(Debug, Clone)]
+pub struct StatsWindow {
+    pub user_id: String,
+    /// Inclusive, UTC
+    pub start: NaiveDateTime,
+    /// Exclusive, UTC
+    pub end: NaiveDateTime,
+    /// Minutes east of UTC
+    pub tz_offset: i32,
+}
+
+impl StatsWindow {
+    /// Window covering the local days `from` to `to`, both included; `None` when its
+    /// bounds fall outside the dates chrono can represent
+    pub fn for_days(user_id: &str, from: NaiveDate, to: NaiveDate, tz_offset: i32) -> Option<Self> {
+        let offset = Duration::minutes(i64::from(tz_offset));
+        let after_to = to.checked_add_signed(Duration::days(1))?;
+        Some(Self {
+            user_id: user_id.to_string(),
+            start: from.and_hms_opt(0, 0, 0)?.checked_sub_signed(offset)?,
+            end: after_to.and_hms_opt(0, 0, 0)?.checked_sub_signed(offset)?,
+            tz_offset,
+        })
+    }
+
+    /// Key identifying this window in the cache, with the statistic it is for
+    pub fn cache_key(&self, statistic: &str) -> String {
+        format!("{}:{}:{}:{}:{}", self.user_id, statistic, self.start, self.end, self.tz_offset)
+    }
+
+    pub fn is_expensive(&self) -> bool {
+        self.end - self.start >= Duration::days(MIN_CACHED_RANGE_DAYS)
+    }
+}
+
+pub fn top_items(conn: &mut MysqlConnection, window: &StatsWindow, kind: TopKind, limit: i64) -> QueryResult<Vec<TopItem>> {
+    // (id, name, detail, extra joins, grouping) per kind
+    let (id, name, detail, joins, group_by) = match kind {
+        TopKind::Songs => ("s.id", "s.title", "a.name", "JOIN artists a ON s.artist_id = a.id", "s.id, s.title, a.name"),
+        TopKind::Artists => ("a.id", "a.name", "NULL", "JOIN artists a ON s.artist_id = a.id", "a.id, a.name"),
+        TopKind::Albums => (
+            "al.id",
+            "al.name",
+            "a.name",
+            "JOIN albums al ON s.album_id = al.id JOIN artists a ON al.artist_id = a.id",
+            "al.id, al.name, a.name",
+        ),
+        TopKind::Genres => ("CAST(g.id AS CHAR)", "g.name", "NULL", "JOIN genres g ON s.genre_id = g.id", "g.id, g.name"),
+    };
+
+    let sql = format!(
+        r#"
+        SELECT
+            {id} AS id, {name} AS name, {detail} AS detail,
+            COUNT(*) AS plays,
+            CAST(SUM({listened}) AS SIGNED) AS seconds
+        FROM plays p
+        JOIN songs s ON p.song_id = s.id
+        {joins}
+        WHERE p.user_id = ? AND p.played_at >= ? AND p.played_at < ?
+        GROUP BY {group_by}
+        ORDER BY plays DESC, seconds DESC, name
+        LIMIT ?
+        "#,
+        listened = LISTENED_SECONDS,
+    );
+
+    diesel::sql_query(sql)
+        .bind::<Text, _>(&window.user_id)
+        .bind::<Timestamp, _>(window.start)
+        .bind::<Timestamp, _>(window.end)
+        .bind::<BigInt, _>(limit)
+        .load::<TopItem>(conn)
+}
+
+pub fn listening_summary(conn: &mut MysqlConnection, window: &StatsWindow) -> QueryResult<ListeningSummary> {
+    let sql = format!(
+        r#"
+        SELECT
+            COUNT(*) AS plays,
+            CAST(COALESCE(SUM({listened}), 0) AS SIGNED) AS seconds,
+            COUNT(DISTINCT p.song_id) AS distinct_songs,
+            COUNT(DISTINCT s.artist_id) AS distinct_artists,
+            COUNT(DISTINCT DATE(p.played_at + INTERVAL ? MINUTE)) AS active_days
+        FROM plays p
+        JOIN songs s ON p.song_id = s.id
+        WHERE p.user_id = ? AND p.played_at >= ? AND p.played_at < ?
+        "#,
+        listened = LISTENED_SECONDS,
+    );
+
+    diesel::sql_query(sql)
+        .bind::<Integer, _>(window.tz_offset)
+        .bind::<Text, _>(&window.user_id)
+        .bind::<Timestamp, _>(window.start)
+        .bind::<Timestamp, _>(window.end)
+        .get_result::<ListeningSummary>(conn)
+}
+
+/// Local time unit plays are grouped by
+#[derive(Debug, Clone, Copy)]
+pub enum BucketUnit {
+    HourOfDay,
+    DayOfWeek,
+    Month,
+}
+
+impl BucketUnit {
+    /// SQL function giving the bucket of a local time, and the buckets it can return
+    fn sql(&self) -> (&'static str, std::ops::RangeInclusive<i32>) {
+        match self {
+            BucketUnit::HourOfDay => ("HOUR", 0..=23),
+            // WEEKDAY() counts from 0 = Monday
+            BucketUnit::DayOfWeek => ("WEEKDAY", 0..=6),
+            BucketUnit::Month => ("MONTH", 1..=12),
+        }
+    }
+}
+
+/// Plays per bucket, including empty ones
+pub fn listening_by(conn: &mut MysqlConnection, window: &StatsWindow, unit: BucketUnit) -> QueryResult<Vec<StatsBucket>> {
+    let (function, buckets) = unit.sql();
+    let sql = format!(
+        r#"
+        SELECT
+            {function}(p.played_at + INTERVAL ? MINUTE) AS bucket,
+            COUNT(*) AS plays,
+            CAST(SUM({listened}) AS SIGNED) AS seconds
+        FROM plays p
+        JOIN songs s ON p.song_id = s.id
+        WHERE p.user_id = ? AND p.played_at >= ? AND p.played_at < ?
+        GROUP BY bucket
+        "#,
+        listened = LISTENED_SECONDS,
+    );
+
+    let rows: HashMap<i32, StatsBucket> = diesel::sql_query(sql)
+        .bind::<Integer, _>(window.tz_offset)
+        .bind::<Text, _>(&window.user_id)
+        .bind::<Timestamp, _>(window.start)
+        .bind::<Timestamp, _>(window.end)
+        .load::<StatsBucket>(conn)?
+        .into_iter()
+        .map(|b| (b.bucket, b))
+        .collect();
+
+    Ok(buckets
+        .map(|bucket| rows.get(&bucket).copied().unwrap_or(StatsBucket { bucket, plays: 0, seconds: 0 }))
+        .collect())
+}
+
+/// Local day with the most plays
+pub fn busiest_day(conn: &mut MysqlConnection, window: &StatsWindow) -> QueryResult<Option<BusiestDay>> {
+    diesel::sql_query(
+        r#"
+        SELECT DATE_FORMAT(p.played_at + INTERVAL ? MINUTE, '%Y-%m-%d') AS day, COUNT(*) AS plays
+        FROM plays p
+        WHERE p.user_id = ? AND p.played_at >= ? AND p.played_at < ?
+        GROUP BY day
+        ORDER BY plays DESC, day
+        LIMIT 1
+        "#,
+    )
+    .bind::<Integer, _>(window.tz_offset)
+    .bind::<Text, _>(&window.user_id)
+    .bind::<Timestamp, _>(window.start)
+    .bind::<Timestamp, _>(window.end)
+    .get_result::<BusiestDay>(conn)
+    .optional()
+}
+
+/// Results of expensive statistics, kept for a while since plays only trickle in
+pub struct StatsCache {
+    ttl: StdDuration,
+    entries: Mutex<HashMap<String, (Instant, Value)>>,
+}
+
+impl StatsCache {
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("STATS_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_STATS_CACHE_TTL_SECONDS);
+        Self {
+            ttl: StdDuration::from_secs(ttl),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn put(&self, key: String, value: Value) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+
+        if entries.len() >= MAX_CACHE_ENTRIES {
+            let ttl = self.ttl;
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < ttl);
+        }
+        // Still full of fresh entries: drop the oldest
+        if entries.len() >= MAX_CACHE_ENTRIES
+            && let Some(oldest) = entries.iter().min_by_key(|(_, (stored_at, _))| *stored_at).map(|(k, _)| k.clone())
+        {
+            entries.remove(&oldest);
+        }
+        entries.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn window_covers_local_days() {
+        // UTC+02:00: local midnight is 22:00 UTC the day before
+        let window = StatsWindow::for_days("u", day(2024, 1, 1), day(2024, 12, 31), 120).unwrap();
+        assert_eq!(window.start, day(2023, 12, 31).and_hms_opt(22, 0, 0).unwrap());
+        assert_eq!(window.end, day(2024, 12, 31).and_hms_opt(22, 0, 0).unwrap());
+        assert!(window.is_expensive());
+
+        let short = StatsWindow::for_days("u", day(2024, 3, 1), day(2024, 3, 6), 0).unwrap();
+        assert!(!short.is_expensive());
+    }
+
+    #[test]
+    fn window_past_the_representable_dates_is_none() {
+        assert!(StatsWindow::for_days("u", NaiveDate::MAX, NaiveDate::MAX, 0).is_none());
+        assert!(StatsWindow::for_days("u", NaiveDate::MIN, NaiveDate::MIN, 840).is_none());
+        // The last year chrono knows cannot be closed by the day after it
+        let last = NaiveDate::MAX.year();
+        assert!(StatsWindow::for_days("u", day(last, 1, 1), NaiveDate::MAX, 0).is_none());
+    }
+}
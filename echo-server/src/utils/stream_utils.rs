@@ -0,0 +1,91 @@
+use actix_web::body::SizedStream;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::storage::{ByteStream, ObjectStorage, StorageError};
+use crate::utils::play_utils::PlayRecorder;
+use crate::utils::range_utils::{if_range_matches, parse_range_header, RangeRequest};
+
+/// Proxy an object from storage to the client, honoring `Range` and `If-Range`.
+/// The body is streamed chunk by chunk from the object store and never buffered whole.
+/// `fallback_etag` is used when the storage backend does not report an ETag.
+/// `play` is recorded once the whole object has been sent, in one response or in ranges.
+pub async fn proxy_object(
+    req: &HttpRequest,
+    storage: &dyn ObjectStorage,
+    key: &str,
+    fallback_etag: String,
+    play: Option<PlayRecorder>,
+) -> HttpResponse {
+    // Size and validators are needed before a range can be evaluated
+    let meta = match storage.head(key).await {
+        Ok(m) => m,
+        Err(e) => return storage_error_response(e),
+    };
+
+    let total_size = meta.size;
+    let etag = meta.etag.clone().unwrap_or(fallback_etag);
+    let last_modified = meta.last_modified.clone();
+    let content_type = meta.content_type.clone().unwrap_or_else(|| "audio/mpeg".to_string());
+
+    let range_header = req.headers().get(header::RANGE).and_then(|v| v.to_str().ok());
+    let if_range = req.headers().get(header::IF_RANGE).and_then(|v| v.to_str().ok());
+
+    let range_request = match (range_header, if_range) {
+        (None, _) => RangeRequest::Full,
+        // A stale If-Range validator means the client must restart with the full representation
+        (Some(_), Some(validator)) if !if_range_matches(validator, &etag, last_modified.as_deref()) => RangeRequest::Full,
+        (Some(range), _) => parse_range_header(range, total_size),
+    };
+
+    let range = match range_request {
+        RangeRequest::Unsatisfiable => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::ACCEPT_RANGES, "bytes"))
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", total_size)))
+                .finish();
+        }
+        RangeRequest::Partial(r) => Some(r),
+        RangeRequest::Full => None,
+    };
+
+    let body = match storage.get(key, range).await {
+        Ok(b) => b,
+        Err(e) => return storage_error_response(e),
+    };
+
+    let stream: ByteStream = match (play, range) {
+        (Some(recorder), None) => Box::pin(recorder.on_completion(body.stream)),
+        (Some(recorder), Some(r)) => Box::pin(recorder.on_part_completion(body.stream, r, total_size)),
+        (None, _) => body.stream,
+    };
+
+    let mut builder = match range {
+        Some(_) => HttpResponse::PartialContent(),
+        None => HttpResponse::Ok(),
+    };
+    builder
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .insert_header((header::CACHE_CONTROL, "private, no-transform"));
+    if let Some(lm) = last_modified {
+        builder.insert_header((header::LAST_MODIFIED, lm));
+    }
+
+    match range {
+        Some(r) => builder
+            .insert_header((header::CONTENT_RANGE, r.content_range(total_size)))
+            .body(SizedStream::new(r.content_length(), stream)),
+        None => builder.body(SizedStream::new(total_size, stream)),
+    }
+}
+
+/// Map a storage failure to the response sent to API clients
+pub fn storage_error_response(e: StorageError) -> HttpResponse {
+    match e {
+        StorageError::NotFound => HttpResponse::NotFound().body("Audio object not found in storage"),
+        StorageError::InvalidKey(_) => HttpResponse::InternalServerError().body(e.to_string()),
+        _ => HttpResponse::BadGateway().body(format!("Object storage error: {}", e)),
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::http::header;
+use actix_web::HttpResponse;
+use chrono::{NaiveDateTime, SecondsFormat};
+use diesel::prelude::*;
+use md5::{Digest, Md5};
+use serde_json::{Map, Value};
+
+use crate::models::subsonic_models::{
+    SubsonicError, SubsonicFormat, SubsonicParams, SUBSONIC_API_VERSION, SUBSONIC_ERROR_WRONG_CREDENTIALS,
+};
+use crate::schema::users;
+
+const SUBSONIC_XML_NAMESPACE: &str = "http://subsonic.org/restapi";
+
+/// Check the `u` parameter against either `t`/`s` (token: md5 of password and salt) or `p`
+/// (password, in clear or hex-encoded with an `enc:` prefix). The password is the user's
+/// Subsonic password, never the login one. Returns the user id.
+pub fn authenticate(conn: &mut MysqlConnection, params: &SubsonicParams) -> Result<String, SubsonicError> {
+    let username = params.require("u")?;
+
+    let (user_id, password) = users::table
+        .filter(users::username.eq(username))
+        .select((users::id, users::subsonic_password))
+        .first::<(String, Option<String>)>(conn)
+        .optional()
+        .map_err(|_| SubsonicError::database())?
+        .and_then(|(user_id, password)| password.map(|p| (user_id, p)))
+        .ok_or_else(wrong_credentials)?;
+
+    let valid = match (params.get("t"), params.get("s"), params.get("p")) {
+        (Some(token), Some(salt), _) => {
+            let expected = hex::encode(Md5::digest(format!("{}{}", password, salt).as_bytes()));
+            token.eq_ignore_ascii_case(&expected)
+        }
+        (_, _, Some(given)) => match given.strip_prefix("enc:") {
+            Some(encoded) => hex::decode(encoded).is_ok_and(|bytes| bytes == password.as_bytes()),
+            None => given == password,
+        },
+        _ => return Err(SubsonicError::missing("t")),
+    };
+
+    if valid {
+        Ok(user_id)
+    } else {
+        Err(wrong_credentials())
+    }
+}
+
+fn wrong_credentials() -> SubsonicError {
+    SubsonicError::new(SUBSONIC_ERROR_WRONG_CREDENTIALS, "Wrong username or password")
+}
+
+/// Timestamps as the Subsonic API writes them, e.g. "2024-03-01T12:00:00Z"
+pub fn subsonic_timestamp(time: Option<NaiveDateTime>) -> Option<String> {
+    time.map(|t| t.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Wrap a result in a `subsonic-response` envelope. Subsonic clients expect errors in the
+/// body with HTTP 200, so the status is always OK.
+pub fn subsonic_response(format: &SubsonicFormat, result: Result<Option<(&str, Value)>, SubsonicError>) -> HttpResponse {
+    let mut body = Map::new();
+    match result {
+        Ok(payload) => {
+            body.insert("status".to_string(), Value::from("ok"));
+            body.insert("version".to_string(), Value::from(SUBSONIC_API_VERSION));
+            insert_server_info(&mut body);
+            if let Some((name, value)) = payload {
+                body.insert(name.to_string(), value);
+            }
+        }
+        Err(error) => {
+            body.insert("status".to_string(), Value::from("failed"));
+            body.insert("version".to_string(), Value::from(SUBSONIC_API_VERSION));
+            insert_server_info(&mut body);
+            body.insert("error".to_string(), serde_json::to_value(error).unwrap_or(Value::Null));
+        }
+    }
+
+    match format {
+        SubsonicFormat::Xml => {
+            body.insert("xmlns".to_string(), Value::from(SUBSONIC_XML_NAMESPACE));
+            let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            write_xml_element(&mut xml, "subsonic-response", &Value::Object(body));
+            HttpResponse::Ok()
+                .insert_header((header::CONTENT_TYPE, "text/xml; charset=utf-8"))
+                .body(xml)
+        }
+        SubsonicFormat::Json => HttpResponse::Ok().json(wrap_json(body)),
+        SubsonicFormat::Jsonp(callback) => HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, "application/javascript; charset=utf-8"))
+            .body(format!("{}({});", callback, wrap_json(body))),
+    }
+}
+
+// OpenSubsonic fields, ignored by plain Subsonic clients
+fn insert_server_info(body: &mut Map<String, Value>) {
+    body.insert("type".to_string(), Value::from("echo"));
+    body.insert("serverVersion".to_string(), Value::from(env!("CARGO_PKG_VERSION")));
+    body.insert("openSubsonic".to_string(), Value::from(true));
+}
+
+fn wrap_json(body: Map<String, Value>) -> Value {
+    let mut root = Map::new();
+    root.insert("subsonic-response".to_string(), Value::Object(body));
+    Value::Object(root)
+}
+
+/// Write an object as an element: scalar members become attributes, arrays repeated child
+/// elements and nested objects child elements, which is how the Subsonic XML schema maps
+/// onto its JSON form.
+fn write_xml_element(out: &mut String, name: &str, value: &Value) {
+    out.push('<');
+    out.push_str(name);
+
+    let mut children: Vec<(&str, &Value)> = Vec::new();
+    if let Value::Object(members) = value {
+        for (key, member) in members {
+            match member {
+                Value::Null => {}
+                Value::Array(items) => children.extend(items.iter().map(|item| (key.as_str(), item))),
+                Value::Object(_) => children.push((key.as_str(), member)),
+                Value::String(s) => push_attribute(out, key, s),
+                other => push_attribute(out, key, &other.to_string()),
+            }
+        }
+    }
+
+    if children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for (child_name, child) in children {
+        write_xml_element(out, child_name, child);
+    }
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
+
+fn push_attribute(out: &mut String, name: &str, value: &str) {
+    out.push(' ');
+    out.push_str(name);
+    out.push_str("=\"");
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
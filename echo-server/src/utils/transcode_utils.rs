@@ -0,0 +1,146 @@
+use actix_web::web::Bytes;
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, StreamExt};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::io::ReaderStream;
+
+use crate::models::rendition_models::RenditionProfile;
+use crate::storage::{content_type_for_key, ByteStream, ObjectStorage, StorageError};
+use crate::utils::audio_utils::transcode_args;
+use crate::workers::ingest_worker::discard_objects;
+
+/// Default number of simultaneous on-the-fly transcodes, overridable with `TRANSCODE_MAX_CONCURRENT`
+const DEFAULT_MAX_CONCURRENT: usize = 2;
+
+/// Seconds clients are told to wait when every transcode slot is busy
+pub const TRANSCODE_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Limits how many ffmpeg processes live transcodes may run at once
+pub struct Transcoder {
+    slots: Arc<Semaphore>,
+}
+
+impl Transcoder {
+    pub fn from_env() -> Self {
+        let max = std::env::var("TRANSCODE_MAX_CONCURRENT")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT);
+        Self { slots: Arc::new(Semaphore::new(max)) }
+    }
+
+    /// A free slot, or `None` when the limit is reached
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.slots.clone().try_acquire_owned().ok()
+    }
+}
+
+/// Pipe `source` through ffmpeg and stream the encoded output as it is produced.
+/// Encoding continues if the client goes away, and the output is streamed to storage under
+/// `cache_key` so repeat requests (including seeks, which need Range) are served from there;
+/// an incomplete output is not kept. The slot is held until ffmpeg exits.
+pub fn transcode_stream(
+    source: ByteStream,
+    profile: RenditionProfile,
+    storage: Arc<dyn ObjectStorage>,
+    cache_key: String,
+    permit: OwnedSemaphorePermit,
+) -> std::io::Result<impl Stream<Item = Result<Bytes, std::io::Error>>> {
+    let mut child = Command::new("ffmpeg")
+        .args(transcode_args(&profile))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().ok_or_else(|| std::io::Error::other("Failed to take stdin"))?;
+    let stdout = child.stdout.take().ok_or_else(|| std::io::Error::other("Failed to take stdout"))?;
+
+    // Feed the stored object to ffmpeg
+    actix_web::rt::spawn(async move {
+        let mut source = source;
+        while let Some(chunk) = source.next().await {
+            let written = match chunk {
+                Ok(data) => stdin.write_all(&data).await,
+                Err(e) => Err(std::io::Error::other(e.to_string())),
+            };
+            if written.is_err() {
+                // ffmpeg's exit status reports the failure
+                return;
+            }
+        }
+        let _ = stdin.shutdown().await;
+    });
+
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(16);
+
+    // Forward ffmpeg's output to the client and, as it comes, to the cache object
+    actix_web::rt::spawn(async move {
+        let _permit = permit;
+        let (mut cache_tx, cache_rx) = mpsc::channel::<Result<Bytes, StorageError>>(16);
+        let upload = {
+            let (storage, cache_key) = (storage.clone(), cache_key.clone());
+            actix_web::rt::spawn(async move {
+                let content_type = content_type_for_key(&cache_key);
+                storage.put_stream(&cache_key, Box::pin(cache_rx), content_type).await
+            })
+        };
+
+        let mut output = ReaderStream::new(stdout);
+        let mut client_connected = true;
+        let mut caching = true;
+        let mut failure: Option<std::io::Error> = None;
+
+        while let Some(chunk) = output.next().await {
+            match chunk {
+                Ok(data) => {
+                    // A failed upload stops caching, not the stream
+                    if caching && cache_tx.send(Ok(data.clone())).await.is_err() {
+                        caching = false;
+                    }
+                    if client_connected && tx.send(Ok(data)).await.is_err() {
+                        client_connected = false;
+                    }
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        if failure.is_none() {
+            failure = match child.wait().await {
+                Ok(status) if status.success() => None,
+                Ok(status) => Some(std::io::Error::other(format!("ffmpeg exited with {}", status))),
+                Err(e) => Some(e),
+            };
+        }
+
+        match failure {
+            None => {
+                drop(tx);
+                drop(cache_tx);
+                match upload.await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => eprintln!("Failed to cache transcode {}: {}", cache_key, e),
+                    Err(e) => eprintln!("Failed to cache transcode {}: {}", cache_key, e),
+                }
+            }
+            Some(e) => {
+                // An incomplete output must not be served as the cached transcode
+                let _ = cache_tx.send(Err(StorageError::Backend(e.to_string()))).await;
+                drop(cache_tx);
+                let _ = upload.await;
+                discard_objects(storage.as_ref(), std::slice::from_ref(&cache_key)).await;
+                let _ = tx.send(Err(e)).await;
+            }
+        }
+    });
+
+    Ok(rx)
+}
@@ -0,0 +1,514 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use uuid::Uuid;
+
+use crate::db::{get_conn, DbPool};
+use crate::models::ingest_models::{IngestItem, IngestJob, JOB_FAILED, JOB_PROCESSING, JOB_QUEUED, JOB_SUCCEEDED};
+use crate::models::rendition_models::{NewRendition, RenditionProfile};
+use crate::models::song_models::{NewSong, UploadItemResult, UploadItemStatus};
+use crate::schema::{ingest_jobs, song_renditions, songs};
+use crate::storage::{content_type_for_key, read_object, ObjectStorage, StorageError};
+use crate::utils::audio_utils::{
+    measure_loudness_async, normalize_song_async, transcode_async, LoudnessSettings, NormalizationMode,
+};
+use crate::utils::catalog_utils::{find_or_create_album, find_or_create_artist, find_or_create_genre, refresh_album_gain};
+
+/// Default number of jobs processed concurrently, overridable with `INGEST_WORKERS`
+const DEFAULT_INGEST_WORKERS: usize = 2;
+
+/// Safety net in case a wake-up is missed, e.g. for jobs queued by another instance
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Handle used by the upload handler to wake the workers when a job is queued
+#[derive(Default)]
+pub struct IngestQueue {
+    notify: Notify,
+}
+
+impl IngestQueue {
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+}
+
+/// How uploads are processed, read once at startup
+#[derive(Debug, Clone)]
+pub struct IngestSettings {
+    pub loudness: LoudnessSettings,
+    /// Extra encodings generated for every song, next to the stored original
+    pub renditions: Vec<RenditionProfile>,
+}
+
+impl IngestSettings {
+    pub fn from_env() -> Self {
+        Self {
+            loudness: LoudnessSettings::from_env(),
+            renditions: RenditionProfile::list_from_env(),
+        }
+    }
+}
+
+pub fn ingest_workers_from_env() -> usize {
+    std::env::var("INGEST_WORKERS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_INGEST_WORKERS)
+}
+
+/// Dispatch loop: claims queued jobs and processes up to `concurrency` of them at once.
+pub async fn run(
+    pool: DbPool,
+    storage: Arc<dyn ObjectStorage>,
+    queue: Arc<IngestQueue>,
+    concurrency: usize,
+    settings: IngestSettings,
+) {
+    let settings = Arc::new(settings);
+    requeue_interrupted_jobs(&pool);
+
+    let permits = Arc::new(Semaphore::new(concurrency));
+
+    loop {
+        let permit = match permits.clone().acquire_owned().await {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+
+        match claim_next_job(&pool) {
+            Ok(Some(job)) => {
+                let pool = pool.clone();
+                let storage = storage.clone();
+                let settings = settings.clone();
+                actix_web::rt::spawn(async move {
+                    process_job(&pool, storage.as_ref(), job, &settings).await;
+                    drop(permit);
+                });
+            }
+            Ok(None) => {
+                drop(permit);
+                tokio::select! {
+                    _ = queue.notify.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+            Err(e) => {
+                drop(permit);
+                eprintln!("Ingest worker could not claim a job: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Jobs left in `processing` were interrupted by a restart: their staged uploads are
+/// still in storage, so they are simply processed again. A job whose rows were committed
+/// is never among them, as it is marked succeeded in the same transaction.
+fn requeue_interrupted_jobs(pool: &DbPool) {
+    let result = get_conn(pool).map_err(|e| e.to_string()).and_then(|mut conn| {
+        diesel::update(ingest_jobs::table.filter(ingest_jobs::status.eq(JOB_PROCESSING)))
+            .set((ingest_jobs::status.eq(JOB_QUEUED), ingest_jobs::processed_items.eq(0)))
+            .execute(&mut conn)
+            .map_err(|e| e.to_string())
+    });
+
+    match result {
+        Ok(0) => {}
+        Ok(n) => println!("Requeued {} interrupted ingest job(s)", n),
+        Err(e) => eprintln!("Failed to requeue interrupted ingest jobs: {}", e),
+    }
+}
+
+/// Atomically move the oldest queued job to `processing`
+fn claim_next_job(pool: &DbPool) -> Result<Option<IngestJob>, String> {
+    let mut conn = get_conn(pool).map_err(|e| e.to_string())?;
+
+    conn.transaction::<Option<IngestJob>, DieselError, _>(|conn| {
+        let job = ingest_jobs::table
+            .filter(ingest_jobs::status.eq(JOB_QUEUED))
+            .order(ingest_jobs::created_at.asc())
+            .select(IngestJob::as_select())
+            .for_update()
+            .first::<IngestJob>(conn)
+            .optional()?;
+
+        let mut job = match job {
+            Some(j) => j,
+            None => return Ok(None),
+        };
+
+        let started_at = Some(Utc::now().naive_utc());
+        diesel::update(ingest_jobs::table.find(&job.id))
+            .set((ingest_jobs::status.eq(JOB_PROCESSING), ingest_jobs::started_at.eq(started_at)))
+            .execute(conn)?;
+
+        job.status = JOB_PROCESSING.to_string();
+        job.started_at = started_at;
+        Ok(Some(job))
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Error of the batch insert transaction, remembering which item failed
+enum BatchInsertError {
+    Item(usize, DieselError),
+    /// The connection or the transaction itself failed: no single item is to blame
+    Transaction(String),
+}
+
+impl From<DieselError> for BatchInsertError {
+    fn from(e: DieselError) -> Self {
+        BatchInsertError::Transaction(e.to_string())
+    }
+}
+
+/// Process a claimed job. The batch is all-or-nothing: objects are stored first, then
+/// every row, catalog rows included, is inserted in a single transaction. Any failure deletes the objects stored
+/// so far. Staged uploads are removed once the job is over either way.
+async fn process_job(pool: &DbPool, storage: &dyn ObjectStorage, job: IngestJob, settings: &IngestSettings) {
+    let items: Vec<IngestItem> = match serde_json::from_str(&job.items_json) {
+        Ok(items) => items,
+        Err(e) => {
+            finish_job(pool, &job.id, JOB_FAILED, &[], &[], Some(format!("Corrupted job items: {}", e)));
+            return;
+        }
+    };
+
+    let mut report: Vec<UploadItemResult> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| UploadItemResult {
+            index,
+            title: item.title.clone(),
+            status: UploadItemStatus::Skipped,
+            song_id: None,
+            error: None,
+        })
+        .collect();
+    let mut stored_keys: Vec<String> = Vec::new();
+    let mut new_songs: Vec<NewSong> = Vec::new();
+    let mut new_renditions: Vec<NewRendition> = Vec::new();
+
+    for (index, item) in items.iter().enumerate() {
+        match store_item(storage, item, settings).await {
+            Ok(stored) => {
+                stored_keys.extend(stored.object_keys);
+                new_songs.push(stored.song);
+                new_renditions.extend(stored.renditions);
+            }
+            Err(error) => {
+                discard_objects(storage, &stored_keys).await;
+                mark_failed(&mut report, index, &error);
+                finish_job(pool, &job.id, JOB_FAILED, &report, &[], Some(error));
+                discard_staged(storage, &items).await;
+                return;
+            }
+        }
+        update_progress(pool, &job.id, index as i32 + 1);
+    }
+
+    // Insert DB
+    match insert_songs(pool, &job.id, &items, &mut new_songs, &new_renditions) {
+        Ok(()) => refresh_album_gains(pool, &new_songs, settings.loudness.target_lufs),
+        Err(BatchInsertError::Item(index, e)) => {
+            let error = match e {
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    "A song with this title already exists for this artist".to_string()
+                }
+                DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                    "Invalid artist_id or album_id: does not exist".to_string()
+                }
+                e => format!("Database error: {}", e),
+            };
+            discard_objects(storage, &stored_keys).await;
+            // Every item was processed, so all others are rolled back rather than skipped
+            for entry in report.iter_mut() {
+                entry.status = UploadItemStatus::RolledBack;
+            }
+            mark_failed(&mut report, index, &error);
+            finish_job(pool, &job.id, JOB_FAILED, &report, &[], Some(error));
+        }
+        Err(BatchInsertError::Transaction(e)) => {
+            discard_objects(storage, &stored_keys).await;
+            let error = format!("Database error: {}", e);
+            for entry in report.iter_mut() {
+                entry.status = UploadItemStatus::RolledBack;
+                entry.error = Some(error.clone());
+            }
+            finish_job(pool, &job.id, JOB_FAILED, &report, &[], Some(error));
+        }
+    }
+
+    discard_staged(storage, &items).await;
+}
+
+/// Insert all rows of a batch in one transaction, and mark the job succeeded in it so a
+/// requeued job can never insert them twice. The catalog ids of `new_songs` are filled in
+/// here, creating the artists, albums and genres the items name.
+fn insert_songs(
+    pool: &DbPool,
+    job_id: &str,
+    items: &[IngestItem],
+    new_songs: &mut [NewSong],
+    new_renditions: &[NewRendition],
+) -> Result<(), BatchInsertError> {
+    let mut conn = get_conn(pool).map_err(|e| BatchInsertError::Transaction(e.to_string()))?;
+
+    conn.transaction::<(), BatchInsertError, _>(|conn| {
+        for (index, (item, new_song)) in items.iter().zip(new_songs.iter_mut()).enumerate() {
+            resolve_catalog(conn, item, new_song).map_err(|e| BatchInsertError::Item(index, e))?;
+            diesel::insert_into(songs::table)
+                .values(new_song)
+                .execute(conn)
+                .map_err(|e| BatchInsertError::Item(index, e))?;
+        }
+        if !new_renditions.is_empty() {
+            diesel::insert_into(song_renditions::table)
+                .values(new_renditions)
+                .execute(conn)?;
+        }
+
+        let report: Vec<UploadItemResult> = new_songs
+            .iter()
+            .enumerate()
+            .map(|(index, new_song)| UploadItemResult {
+                index,
+                title: new_song.title.clone(),
+                status: UploadItemStatus::Created,
+                song_id: Some(new_song.id.clone()),
+                error: None,
+            })
+            .collect();
+        let song_ids: Vec<String> = new_songs.iter().map(|s| s.id.clone()).collect();
+        record_result(conn, job_id, JOB_SUCCEEDED, &report, &song_ids, None)?;
+        Ok(())
+    })
+}
+
+/// Point a song at the catalog rows of its item, looking up or creating those given by name
+fn resolve_catalog(conn: &mut MysqlConnection, item: &IngestItem, new_song: &mut NewSong) -> QueryResult<()> {
+    new_song.artist_id = match (&item.artist_id, &item.artist) {
+        (Some(artist), _) => artist.clone(),
+        (None, Some(name)) => find_or_create_artist(conn, name)?,
+        // Rejected by the upload handler
+        (None, None) => return Err(DieselError::NotFound),
+    };
+    new_song.album_id = match (&item.album_id, &item.album) {
+        (Some(album), _) => Some(album.clone()),
+        (None, Some(name)) => Some(find_or_create_album(conn, name, &new_song.artist_id, item.year)?),
+        (None, None) => None,
+    };
+    new_song.genre_id = match (item.genre_id, &item.genre) {
+        (Some(genre), _) => Some(genre),
+        (None, Some(name)) => Some(find_or_create_genre(conn, name)?),
+        (None, None) => None,
+    };
+    Ok(())
+}
+
+/// Everything stored for one item: objects to delete if the batch fails, and rows to insert
+struct StoredItem {
+    object_keys: Vec<String>,
+    song: NewSong,
+    renditions: Vec<NewRendition>,
+}
+
+/// Measure the loudness of a staged upload, store the final object and its renditions.
+/// In ReplayGain mode the original audio is kept as is, unless its format is not one
+/// clients can play, in which case it is re-encoded like in re-encode mode.
+async fn store_item(
+    storage: &dyn ObjectStorage,
+    item: &IngestItem,
+    settings: &IngestSettings,
+) -> Result<StoredItem, String> {
+    let loudness = settings.loudness;
+    let file = read_object(storage, &item.staging_key)
+        .await
+        .map_err(|e| format!("Failed to read staged upload: {}", e))?;
+
+    let measured = measure_loudness_async(&file, loudness.target_lufs)
+        .await
+        .map_err(|e| format!("Loudness measurement error: {}", e))?;
+
+    let (audio, extension, measured) = match (loudness.mode, item.extension.as_deref()) {
+        (NormalizationMode::ReplayGain, Some(ext)) => (file, ext.to_string(), measured),
+        _ => {
+            let normalized_file = normalize_song_async(&file, loudness.target_lufs, &measured)
+                .await
+                .map_err(|e| format!("Audio normalization error: {}", e))?;
+            // Gain and peak are reported for the audio actually stored
+            let remeasured = measure_loudness_async(&normalized_file, loudness.target_lufs)
+                .await
+                .map_err(|e| format!("Loudness measurement error: {}", e))?;
+            (normalized_file, "mp3".to_string(), remeasured)
+        }
+    };
+
+    let song_id = Uuid::new_v4().to_string();
+    let renditions = store_renditions(storage, &song_id, &audio, &extension, &settings.renditions).await?;
+    let mut object_keys: Vec<String> = renditions.iter().map(|r| r.object_key.clone()).collect();
+
+    let object_name = format!("{}.{}", song_id, extension);
+    if let Err(e) = storage.put(&object_name, audio, content_type_for_key(&object_name)).await {
+        discard_objects(storage, &object_keys).await;
+        return Err(format!("Failed to upload to Object Storage: {}", e));
+    }
+    object_keys.push(object_name.clone());
+
+    let song = NewSong {
+        id: song_id,
+        title: item.title.clone(),
+        // Resolved by `insert_songs`
+        artist_id: String::new(),
+        album_id: None,
+        genre_id: None,
+        track_number: item.track_number,
+        duration_seconds: item.duration_seconds,
+        object_url: storage.object_url(&object_name),
+        loudness_lufs: measured.integrated(),
+        track_gain: measured.track_gain(loudness.target_lufs),
+        track_peak: measured.track_peak(),
+    };
+    Ok(StoredItem { object_keys, song, renditions })
+}
+
+/// Encode and store every configured rendition of a song. Lossless renditions are only made
+/// from lossless sources. On failure the renditions stored so far are deleted.
+async fn store_renditions(
+    storage: &dyn ObjectStorage,
+    song_id: &str,
+    source: &[u8],
+    source_extension: &str,
+    profiles: &[RenditionProfile],
+) -> Result<Vec<NewRendition>, String> {
+    let source_is_lossless = matches!(source_extension, "flac" | "wav");
+    let mut renditions: Vec<NewRendition> = Vec::new();
+
+    for profile in profiles.iter().filter(|p| source_is_lossless || !p.format.is_lossless()) {
+        let object_key = profile.object_key(song_id);
+        let stored = match transcode_async(source, profile).await {
+            Ok(encoded) => {
+                let size_bytes = encoded.len() as i64;
+                storage
+                    .put(&object_key, encoded, content_type_for_key(&object_key))
+                    .await
+                    .map(|_| size_bytes)
+                    .map_err(|e| format!("Failed to upload rendition to Object Storage: {}", e))
+            }
+            Err(e) => Err(format!("Rendition error: {}", e)),
+        };
+
+        match stored {
+            Ok(size_bytes) => renditions.push(NewRendition {
+                id: Uuid::new_v4().to_string(),
+                song_id: song_id.to_string(),
+                format: profile.format.name().to_string(),
+                codec: profile.format.codec().to_string(),
+                bitrate_kbps: profile.bitrate_kbps,
+                size_bytes,
+                object_key,
+            }),
+            Err(e) => {
+                let keys: Vec<String> = renditions.iter().map(|r| r.object_key.clone()).collect();
+                discard_objects(storage, &keys).await;
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(renditions)
+}
+
+/// Album gain covers every song of the album, so it is recomputed as songs join it
+fn refresh_album_gains(pool: &DbPool, new_songs: &[NewSong], target_lufs: f32) {
+    let mut album_ids: Vec<&str> = new_songs.iter().filter_map(|s| s.album_id.as_deref()).collect();
+    album_ids.sort_unstable();
+    album_ids.dedup();
+
+    if let Ok(mut conn) = get_conn(pool) {
+        for album_id in album_ids {
+            if let Err(e) = refresh_album_gain(&mut conn, album_id, target_lufs) {
+                eprintln!("Failed to refresh album gain of {}: {}", album_id, e);
+            }
+        }
+    }
+}
+
+/// Mark item `failed_index` as failed and the items before it as rolled back
+fn mark_failed(report: &mut [UploadItemResult], failed_index: usize, error: &str) {
+    for entry in report.iter_mut() {
+        if entry.index < failed_index {
+            entry.status = UploadItemStatus::RolledBack;
+        } else if entry.index == failed_index {
+            entry.status = UploadItemStatus::Failed;
+            entry.error = Some(error.to_string());
+        }
+    }
+}
+
+fn update_progress(pool: &DbPool, job_id: &str, processed: i32) {
+    if let Ok(mut conn) = get_conn(pool) {
+        let _ = diesel::update(ingest_jobs::table.find(job_id))
+            .set(ingest_jobs::processed_items.eq(processed))
+            .execute(&mut conn);
+    }
+}
+
+fn finish_job(
+    pool: &DbPool,
+    job_id: &str,
+    status: &str,
+    report: &[UploadItemResult],
+    song_ids: &[String],
+    error_message: Option<String>,
+) {
+    let result = get_conn(pool).map_err(|e| e.to_string()).and_then(|mut conn| {
+        record_result(&mut conn, job_id, status, report, song_ids, error_message).map_err(|e| e.to_string())
+    });
+
+    if let Err(e) = result {
+        eprintln!("Failed to record result of ingest job {}: {}", job_id, e);
+    }
+}
+
+fn record_result(
+    conn: &mut MysqlConnection,
+    job_id: &str,
+    status: &str,
+    report: &[UploadItemResult],
+    song_ids: &[String],
+    error_message: Option<String>,
+) -> QueryResult<usize> {
+    let report_json = serde_json::to_string(report).ok();
+    let song_ids_json = serde_json::to_string(song_ids).ok();
+
+    diesel::update(ingest_jobs::table.find(job_id))
+        .set((
+            ingest_jobs::status.eq(status),
+            ingest_jobs::report_json.eq(report_json),
+            ingest_jobs::song_ids_json.eq(song_ids_json),
+            ingest_jobs::error_message.eq(error_message),
+            ingest_jobs::finished_at.eq(Some(Utc::now().naive_utc())),
+        ))
+        .execute(conn)
+}
+
+/// Compensating deletes for objects of a batch that did not make it into the DB
+pub async fn discard_objects(storage: &dyn ObjectStorage, keys: &[String]) {
+    for key in keys {
+        match storage.delete(key).await {
+            Ok(()) | Err(StorageError::NotFound) => {}
+            // Leave a trace so orphaned objects can be cleaned up by hand
+            Err(e) => eprintln!("Failed to delete orphaned object {}: {}", key, e),
+        }
+    }
+}
+
+async fn discard_staged(storage: &dyn ObjectStorage, items: &[IngestItem]) {
+    let keys: Vec<String> = items.iter().map(|i| i.staging_key.clone()).collect();
+    discard_objects(storage, &keys).await;
+}